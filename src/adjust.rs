@@ -0,0 +1,277 @@
+use winapi::minwindef::*;
+use winapi::windef::*;
+
+use constants::*;
+use windows::Bounds;
+
+/// Pixels moved per key press until a number key picks another step.
+pub const DEFAULT_STEP: i32 = 20;
+
+/// Edges closer than this to where a window edge is heading pull it in.
+pub const SNAP_DISTANCE: i32 = 12;
+
+/// Windows are not resized below this width or height.
+pub const MIN_SIZE: i32 = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Arrow {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// Keys the adjust mode responds to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Key {
+    /// Arrow keys move the window
+    Move(Arrow),
+    /// Shift+arrows grow the window right or down, and shrink it left or up
+    Resize(Arrow),
+    /// Number keys pick the step: 1-9 are tens of pixels, 0 is one pixel
+    Step(u32),
+    /// Enter keeps the new bounds
+    Commit,
+    /// Esc puts the window back
+    Cancel,
+}
+
+impl Key {
+    pub fn from_vk(vk: UINT, modifiers: UINT) -> Option<Key> {
+        let arrow = match vk {
+            VK_LEFT => Some(Arrow::Left),
+            VK_RIGHT => Some(Arrow::Right),
+            VK_UP => Some(Arrow::Up),
+            VK_DOWN => Some(Arrow::Down),
+            _ => None
+        };
+
+        if let Some(arrow) = arrow {
+            return match modifiers & MOD_SHIFT {
+                0 => Some(Key::Move(arrow)),
+                _ => Some(Key::Resize(arrow)),
+            };
+        }
+
+        match vk {
+            VK_0..=VK_9 => Some(Key::Step(vk - VK_0)),
+            VK_RETURN => Some(Key::Commit),
+            VK_ESCAPE => Some(Key::Cancel),
+            _ => None
+        }
+    }
+}
+
+/// What the caller should do to the window after a key.
+#[derive(Debug, PartialEq)]
+pub enum Effect {
+    Nothing,
+    /// Show the window at new bounds, the mode goes on
+    Preview(HWND, Bounds),
+    /// The mode is over and the window stays where it is
+    Commit(HWND),
+    /// The mode is over and the window goes back to its original bounds
+    Revert(HWND, Bounds),
+}
+
+struct Session {
+    hwnd: HWND,
+    original: Bounds,
+    bounds: Bounds,
+    step: i32,
+    /// Edges to snap to: monitor work areas and other windows
+    xs: Vec<i32>,
+    ys: Vec<i32>,
+}
+
+enum State {
+    Idle,
+    Adjusting(Session),
+}
+
+/// Moving and resizing the foreground window from the keyboard.
+///
+/// A plain state machine over `Key`s, the caller registers the keys while
+/// the mode is active and applies the returned `Effect`s.
+pub struct AdjustMode {
+    state: State,
+}
+
+impl AdjustMode {
+    pub fn new() -> Self {
+        AdjustMode {
+            state: State::Idle,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        match self.state {
+            State::Idle => false,
+            State::Adjusting(_) => true,
+        }
+    }
+
+    /// Starts adjusting `hwnd`. Its edges snap to the edges of `targets`.
+    pub fn enter(&mut self, hwnd: HWND, bounds: Bounds, targets: &[Bounds]) {
+        self.state = State::Adjusting(Session {
+            hwnd: hwnd,
+            original: bounds,
+            bounds: bounds,
+            step: DEFAULT_STEP,
+            xs: targets.iter().flat_map(|&(l, _, r, _)| vec![l, r]).collect(),
+            ys: targets.iter().flat_map(|&(_, t, _, b)| vec![t, b]).collect(),
+        });
+    }
+
+    pub fn key(&mut self, key: Key) -> Effect {
+        let effect = match self.state {
+            State::Idle => return Effect::Nothing,
+            State::Adjusting(ref mut session) => session.key(key)
+        };
+
+        match effect {
+            Effect::Commit(_) | Effect::Revert(..) => self.state = State::Idle,
+            _ => {}
+        }
+
+        effect
+    }
+}
+
+impl Session {
+    fn key(&mut self, key: Key) -> Effect {
+        let (l, t, r, b) = self.bounds;
+        let step = self.step;
+
+        let bounds = match key {
+            Key::Step(0) => {
+                self.step = 1;
+                return Effect::Nothing;
+            },
+            Key::Step(n) => {
+                self.step = n as i32 * 10;
+                return Effect::Nothing;
+            },
+            Key::Commit => return Effect::Commit(self.hwnd),
+            Key::Cancel => return Effect::Revert(self.hwnd, self.original),
+
+            Key::Move(Arrow::Left) => {
+                let dx = self.snap_move(&[l, r], -step, true);
+                (l + dx, t, r + dx, b)
+            },
+            Key::Move(Arrow::Right) => {
+                let dx = self.snap_move(&[l, r], step, true);
+                (l + dx, t, r + dx, b)
+            },
+            Key::Move(Arrow::Up) => {
+                let dy = self.snap_move(&[t, b], -step, false);
+                (l, t + dy, r, b + dy)
+            },
+            Key::Move(Arrow::Down) => {
+                let dy = self.snap_move(&[t, b], step, false);
+                (l, t + dy, r, b + dy)
+            },
+
+            Key::Resize(Arrow::Left) => (l, t, (r + self.snap_move(&[r], -step, true)).max(l + MIN_SIZE), b),
+            Key::Resize(Arrow::Right) => (l, t, r + self.snap_move(&[r], step, true), b),
+            Key::Resize(Arrow::Up) => (l, t, r, (b + self.snap_move(&[b], -step, false)).max(t + MIN_SIZE)),
+            Key::Resize(Arrow::Down) => (l, t, r, b + self.snap_move(&[b], step, false)),
+        };
+
+        if bounds == self.bounds {
+            return Effect::Nothing;
+        }
+
+        self.bounds = bounds;
+        Effect::Preview(self.hwnd, bounds)
+    }
+
+    /// How far to move `edges` for a step of `delta`. If a snap edge lies
+    /// ahead within `SNAP_DISTANCE` of where an edge would land, moves
+    /// exactly onto it instead. Edges already touching are left behind.
+    fn snap_move(&self, edges: &[i32], delta: i32, horizontal: bool) -> i32 {
+        let targets = if horizontal { &self.xs } else { &self.ys };
+
+        edges.iter()
+            .flat_map(|&edge| targets.iter().map(move |&target| target - edge))
+            .filter(|&d| d.signum() == delta.signum() && (d - delta).abs() <= SNAP_DISTANCE)
+            .min_by_key(|&d| (d - delta).abs())
+            .unwrap_or(delta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winapi::windef::*;
+
+    use constants::*;
+    use super::{AdjustMode, Arrow, Effect, Key, MIN_SIZE};
+
+    const HWND_1: HWND = 1 as HWND;
+    const AREA: (i32, i32, i32, i32) = (0, 0, 1920, 1040);
+
+    #[test]
+    fn keys() {
+        assert_eq!(Some(Key::Move(Arrow::Left)), Key::from_vk(VK_LEFT, 0));
+        assert_eq!(Some(Key::Resize(Arrow::Down)), Key::from_vk(VK_DOWN, MOD_SHIFT));
+        assert_eq!(Some(Key::Step(5)), Key::from_vk(VK_5, 0));
+        assert_eq!(Some(Key::Cancel), Key::from_vk(VK_ESCAPE, 0));
+        assert_eq!(None, Key::from_vk(VK_A, 0));
+    }
+
+    #[test]
+    fn move_resize_and_commit() {
+        let mut mode = AdjustMode::new();
+        mode.enter(HWND_1, (500, 500, 900, 800), &[AREA]);
+
+        assert_eq!(Effect::Preview(HWND_1, (520, 500, 920, 800)), mode.key(Key::Move(Arrow::Right)));
+        assert_eq!(Effect::Nothing, mode.key(Key::Step(0)));
+        assert_eq!(Effect::Preview(HWND_1, (520, 499, 920, 799)), mode.key(Key::Move(Arrow::Up)));
+        assert_eq!(Effect::Nothing, mode.key(Key::Step(5)));
+        assert_eq!(Effect::Preview(HWND_1, (520, 499, 970, 799)), mode.key(Key::Resize(Arrow::Right)));
+        assert_eq!(Effect::Commit(HWND_1), mode.key(Key::Commit));
+
+        assert!(!mode.is_active());
+        assert_eq!(Effect::Nothing, mode.key(Key::Move(Arrow::Left)));
+    }
+
+    #[test]
+    fn cancel_reverts() {
+        let mut mode = AdjustMode::new();
+        mode.enter(HWND_1, (500, 500, 900, 800), &[AREA]);
+
+        mode.key(Key::Move(Arrow::Down));
+        mode.key(Key::Resize(Arrow::Left));
+
+        assert_eq!(Effect::Revert(HWND_1, (500, 500, 900, 800)), mode.key(Key::Cancel));
+        assert!(!mode.is_active());
+    }
+
+    #[test]
+    fn snaps_to_edges_and_moves_past_them() {
+        let mut mode = AdjustMode::new();
+        let other = (1000, 0, 1500, 600);
+        mode.enter(HWND_1, (100, 10, 970, 500), &[AREA, other]);
+
+        // Right edge pulled onto the other window, then past it
+        assert_eq!(Effect::Preview(HWND_1, (130, 10, 1000, 500)), mode.key(Key::Move(Arrow::Right)));
+        assert_eq!(Effect::Preview(HWND_1, (150, 10, 1020, 500)), mode.key(Key::Move(Arrow::Right)));
+
+        // Top edge onto the work area
+        assert_eq!(Effect::Preview(HWND_1, (150, 0, 1020, 490)), mode.key(Key::Move(Arrow::Up)));
+        assert_eq!(Effect::Nothing, mode.key(Key::Step(1)));
+        assert_eq!(Effect::Preview(HWND_1, (150, -10, 1020, 480)), mode.key(Key::Move(Arrow::Up)));
+
+        // Resizing never goes below the minimum size
+        assert_eq!(Effect::Nothing, mode.key(Key::Step(9)));
+        let mut resize = |arrow| {
+            let effects: Vec<Effect> = (0..20).map(|_| mode.key(Key::Resize(arrow))).collect();
+            // Presses past the minimum change nothing
+            assert_eq!(Some(&Effect::Nothing), effects.last());
+            effects.into_iter().filter(|e| *e != Effect::Nothing).last()
+        };
+        assert_eq!(Some(Effect::Preview(HWND_1, (150, -10, 1020, -10 + MIN_SIZE))), resize(Arrow::Up));
+        assert_eq!(Some(Effect::Preview(HWND_1, (150, -10, 150 + MIN_SIZE, -10 + MIN_SIZE))), resize(Arrow::Left));
+        assert_eq!(Effect::Commit(HWND_1), mode.key(Key::Commit));
+    }
+}
@@ -0,0 +1,397 @@
+use std::fmt;
+use std::str::FromStr;
+
+use keys::Chord;
+use tiling::Layout;
+
+/// Everything WinMan can be asked to do, in a text form such as
+/// `swap-slots 1 2`. Hotkeys send commands, and they are typed into the
+/// popup after a ':'. See `dispatcher::Dispatcher` for running them.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    /// Switches to the next window of a slot, starting its program if it
+    /// has none
+    FocusSlot(String),
+    /// Adds the current window to a slot, `mark 1`, `grab 1` or `grab-slot 1`
+    GrabToSlot(String),
+    ClearSlot(String),
+    /// Raises every window of a slot
+    ActivateSlot(String),
+    ShowPopup,
+    Quit,
+    /// Moves the current window of one slot to another
+    MoveWindow(String, String),
+    SwapSlots(String, String),
+    /// Appends the windows of the first slot to the second and empties the first
+    MergeSlots(String, String),
+    /// Removes the foreground window from every slot holding it
+    RemoveWindow,
+    /// Moves the foreground window within its slot's cycling order
+    ReorderWindow(Direction),
+    /// Reverts the last slot edit
+    Undo,
+    Redo,
+    /// Prints every slot with the current titles of its windows
+    ListSlots,
+    /// Prints how registering each hotkey went
+    ListHotkeys,
+    /// Snaps the foreground window to a named region, e.g. `snap left`
+    Snap(String),
+    /// Tiles a slot, or else the monitor of the foreground window
+    Tile(Layout, Option<String>),
+    Untile(Option<String>),
+    /// Writes the placement of every window to a named layout file
+    SaveLayout(String),
+    /// Moves windows back to a saved layout
+    RestoreLayout(String),
+    /// Moves the foreground window to the next or previous monitor
+    MoveToMonitor(Cycle),
+    /// Hides the foreground window in a named scratchpad
+    SendToScratchpad(String),
+    /// Shows or hides a scratchpad window
+    ToggleScratchpad(String),
+    /// Runs a script from the settings, `script arrange`
+    RunScript(String),
+    /// Picks an item from the list a script showed in the popup
+    Choose(String),
+    /// Starts moving and resizing the foreground window from the keyboard
+    AdjustWindow,
+    /// Binds a chord to a command until WinMan exits, `bind Alt+F5 focus-slot 2`,
+    /// taking the chord from whatever had it
+    Bind(Chord, Box<Command>),
+    Unbind(Chord),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+impl Direction {
+    /// Offset in cycling order, up comes around sooner
+    pub fn offset(&self) -> isize {
+        match *self {
+            Direction::Up => -1,
+            Direction::Down => 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cycle {
+    Next,
+    Previous,
+}
+
+impl Cycle {
+    pub fn offset(&self) -> isize {
+        match *self {
+            Cycle::Next => 1,
+            Cycle::Previous => -1,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseCommandError(pub String);
+
+impl fmt::Display for ParseCommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for Command {
+    type Err = ParseCommandError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let args: Vec<&str> = s.split_whitespace().collect();
+        let name = match args.first() {
+            Some(name) => *name,
+            None => return Err(ParseCommandError("empty command".to_string()))
+        };
+
+        let expect_args = |n: usize| {
+            if args.len() == n + 1 {
+                Ok(())
+            } else {
+                Err(ParseCommandError(format!("{} takes {} argument(s)", name, n)))
+            }
+        };
+
+        match name {
+            "focus-slot" => {
+                expect_args(1)?;
+                Ok(Command::FocusSlot(args[1].to_string()))
+            },
+
+            "mark" | "grab" | "grab-slot" => {
+                expect_args(1)?;
+                Ok(Command::GrabToSlot(args[1].to_string()))
+            },
+
+            "clear-slot" => {
+                expect_args(1)?;
+                Ok(Command::ClearSlot(args[1].to_string()))
+            },
+
+            "activate-slot" => {
+                expect_args(1)?;
+                Ok(Command::ActivateSlot(args[1].to_string()))
+            },
+
+            "show-popup" => {
+                expect_args(0)?;
+                Ok(Command::ShowPopup)
+            },
+
+            "quit" => {
+                expect_args(0)?;
+                Ok(Command::Quit)
+            },
+
+            "move-window" => {
+                expect_args(2)?;
+                Ok(Command::MoveWindow(args[1].to_string(), args[2].to_string()))
+            },
+
+            "swap-slots" => {
+                expect_args(2)?;
+                Ok(Command::SwapSlots(args[1].to_string(), args[2].to_string()))
+            },
+
+            "merge-slots" => {
+                expect_args(2)?;
+                Ok(Command::MergeSlots(args[1].to_string(), args[2].to_string()))
+            },
+
+            "remove-window" => {
+                expect_args(0)?;
+                Ok(Command::RemoveWindow)
+            },
+
+            "undo" => {
+                expect_args(0)?;
+                Ok(Command::Undo)
+            },
+
+            "redo" => {
+                expect_args(0)?;
+                Ok(Command::Redo)
+            },
+
+            "list-slots" => {
+                expect_args(0)?;
+                Ok(Command::ListSlots)
+            },
+
+            "list-hotkeys" => {
+                expect_args(0)?;
+                Ok(Command::ListHotkeys)
+            },
+
+            "snap" => {
+                expect_args(1)?;
+                Ok(Command::Snap(args[1].to_string()))
+            },
+
+            "tile" => {
+                if args.len() != 2 && args.len() != 3 {
+                    return Err(ParseCommandError("tile takes a layout and an optional slot".to_string()));
+                }
+
+                let layout = args[1].parse::<Layout>().map_err(ParseCommandError)?;
+                Ok(Command::Tile(layout, args.get(2).map(|s| s.to_string())))
+            },
+
+            "untile" => {
+                if args.len() > 2 {
+                    return Err(ParseCommandError("untile takes an optional slot".to_string()));
+                }
+
+                Ok(Command::Untile(args.get(1).map(|s| s.to_string())))
+            },
+
+            "layout" => {
+                expect_args(2)?;
+                match args[1] {
+                    "save" => Ok(Command::SaveLayout(args[2].to_string())),
+                    "restore" => Ok(Command::RestoreLayout(args[2].to_string())),
+                    other => Err(ParseCommandError(format!("expected save or restore, got '{}'", other)))
+                }
+            },
+
+            "scratchpad" => {
+                expect_args(2)?;
+                match args[1] {
+                    "send" => Ok(Command::SendToScratchpad(args[2].to_string())),
+                    "toggle" => Ok(Command::ToggleScratchpad(args[2].to_string())),
+                    other => Err(ParseCommandError(format!("expected send or toggle, got '{}'", other)))
+                }
+            },
+
+            "move-to-monitor" => {
+                expect_args(1)?;
+                match args[1] {
+                    "next" => Ok(Command::MoveToMonitor(Cycle::Next)),
+                    "previous" => Ok(Command::MoveToMonitor(Cycle::Previous)),
+                    other => Err(ParseCommandError(format!("expected next or previous, got '{}'", other)))
+                }
+            },
+
+            "reorder-window" => {
+                expect_args(1)?;
+                match args[1] {
+                    "up" => Ok(Command::ReorderWindow(Direction::Up)),
+                    "down" => Ok(Command::ReorderWindow(Direction::Down)),
+                    other => Err(ParseCommandError(format!("expected up or down, got '{}'", other)))
+                }
+            },
+
+            "script" => {
+                expect_args(1)?;
+                Ok(Command::RunScript(args[1].to_string()))
+            },
+
+            "adjust-window" => {
+                expect_args(0)?;
+                Ok(Command::AdjustWindow)
+            },
+
+            "bind" => {
+                if args.len() < 3 {
+                    return Err(ParseCommandError("bind takes a chord and a command".to_string()));
+                }
+
+                let chord = args[1].parse::<Chord>().map_err(ParseCommandError)?;
+                let command = args[2..].join(" ").parse::<Command>()?;
+                Ok(Command::Bind(chord, Box::new(command)))
+            },
+
+            "unbind" => {
+                expect_args(1)?;
+                let chord = args[1].parse::<Chord>().map_err(ParseCommandError)?;
+                Ok(Command::Unbind(chord))
+            },
+
+            "choose" => {
+                if args.len() < 2 {
+                    return Err(ParseCommandError("choose takes an item".to_string()));
+                }
+
+                Ok(Command::Choose(args[1..].join(" ")))
+            },
+
+            _ => Err(ParseCommandError(format!("unknown command '{}'", name)))
+        }
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Command::FocusSlot(ref slot) => write!(f, "focus-slot {}", slot),
+            Command::GrabToSlot(ref slot) => write!(f, "mark {}", slot),
+            Command::ClearSlot(ref slot) => write!(f, "clear-slot {}", slot),
+            Command::ActivateSlot(ref slot) => write!(f, "activate-slot {}", slot),
+            Command::ShowPopup => write!(f, "show-popup"),
+            Command::Quit => write!(f, "quit"),
+            Command::MoveWindow(ref from, ref to) => write!(f, "move-window {} {}", from, to),
+            Command::SwapSlots(ref a, ref b) => write!(f, "swap-slots {} {}", a, b),
+            Command::MergeSlots(ref from, ref into) => write!(f, "merge-slots {} {}", from, into),
+            Command::RemoveWindow => write!(f, "remove-window"),
+            Command::ReorderWindow(Direction::Up) => write!(f, "reorder-window up"),
+            Command::ReorderWindow(Direction::Down) => write!(f, "reorder-window down"),
+            Command::Undo => write!(f, "undo"),
+            Command::Redo => write!(f, "redo"),
+            Command::ListSlots => write!(f, "list-slots"),
+            Command::ListHotkeys => write!(f, "list-hotkeys"),
+            Command::Snap(ref region) => write!(f, "snap {}", region),
+            Command::Tile(layout, Some(ref slot)) => write!(f, "tile {} {}", layout, slot),
+            Command::Tile(layout, None) => write!(f, "tile {}", layout),
+            Command::Untile(Some(ref slot)) => write!(f, "untile {}", slot),
+            Command::Untile(None) => write!(f, "untile"),
+            Command::SaveLayout(ref name) => write!(f, "layout save {}", name),
+            Command::RestoreLayout(ref name) => write!(f, "layout restore {}", name),
+            Command::MoveToMonitor(Cycle::Next) => write!(f, "move-to-monitor next"),
+            Command::MoveToMonitor(Cycle::Previous) => write!(f, "move-to-monitor previous"),
+            Command::SendToScratchpad(ref name) => write!(f, "scratchpad send {}", name),
+            Command::ToggleScratchpad(ref name) => write!(f, "scratchpad toggle {}", name),
+            Command::RunScript(ref name) => write!(f, "script {}", name),
+            Command::Choose(ref item) => write!(f, "choose {}", item),
+            Command::AdjustWindow => write!(f, "adjust-window"),
+            Command::Bind(chord, ref command) => write!(f, "bind {} {}", chord, command),
+            Command::Unbind(chord) => write!(f, "unbind {}", chord),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tiling::Layout;
+    use super::{Command, Cycle, Direction};
+
+    #[test]
+    fn round_trip() {
+        let commands = vec![
+            Command::FocusSlot("editor".to_string()),
+            Command::GrabToSlot("1".to_string()),
+            Command::ClearSlot("1".to_string()),
+            Command::ActivateSlot("1".to_string()),
+            Command::ShowPopup,
+            Command::Quit,
+            Command::MoveWindow("1".to_string(), "editor".to_string()),
+            Command::SwapSlots("1".to_string(), "2".to_string()),
+            Command::MergeSlots("a".to_string(), "b".to_string()),
+            Command::RemoveWindow,
+            Command::ReorderWindow(Direction::Up),
+            Command::ReorderWindow(Direction::Down),
+            Command::Undo,
+            Command::Redo,
+            Command::ListSlots,
+            Command::ListHotkeys,
+            Command::Snap("top-left".to_string()),
+            Command::Tile(Layout::MasterStack, None),
+            Command::Tile(Layout::Spiral, Some("1".to_string())),
+            Command::Untile(None),
+            Command::Untile(Some("1".to_string())),
+            Command::SaveLayout("work".to_string()),
+            Command::RestoreLayout("work".to_string()),
+            Command::MoveToMonitor(Cycle::Next),
+            Command::MoveToMonitor(Cycle::Previous),
+            Command::SendToScratchpad("term".to_string()),
+            Command::ToggleScratchpad("term".to_string()),
+            Command::RunScript("arrange".to_string()),
+            Command::Choose("Visual Studio Code".to_string()),
+            Command::AdjustWindow,
+            Command::Bind("Alt+F5".parse().unwrap(), Box::new(Command::Tile(Layout::Spiral, Some("1".to_string())))),
+            Command::Unbind("Ctrl+Alt+Left".parse().unwrap()),
+        ];
+
+        for command in commands {
+            assert_eq!(Ok(command.clone()), command.to_string().parse());
+        }
+
+        assert_eq!(Ok(Command::GrabToSlot("3".to_string())), "grab-slot 3".parse());
+    }
+
+    #[test]
+    fn errors() {
+        assert!("".parse::<Command>().is_err());
+        assert!("swap-slots 1".parse::<Command>().is_err());
+        assert!("remove-window 1".parse::<Command>().is_err());
+        assert!("focus-slot".parse::<Command>().is_err());
+        assert!("quit now".parse::<Command>().is_err());
+        assert!("snap".parse::<Command>().is_err());
+        assert!("tile grid".parse::<Command>().is_err());
+        assert!("layout load work".parse::<Command>().is_err());
+        assert!("reorder-window sideways".parse::<Command>().is_err());
+        assert!("launch-rockets".parse::<Command>().is_err());
+        assert!("script".parse::<Command>().is_err());
+        assert!("choose".parse::<Command>().is_err());
+        assert!("bind Alt+F5".parse::<Command>().is_err());
+        assert!("bind Hyper+F5 quit".parse::<Command>().is_err());
+        assert!("bind Alt+F5 launch-rockets".parse::<Command>().is_err());
+    }
+}
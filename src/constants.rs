@@ -24,4 +24,22 @@ pub const VK_6: UINT = 0x36;
 pub const VK_7: UINT = 0x37;
 pub const VK_8: UINT = 0x38;
 pub const VK_9: UINT = 0x39;
-pub const VK_Q: UINT = 0x51;
\ No newline at end of file
+pub const VK_A: UINT = 0x41;
+pub const VK_Q: UINT = 0x51;
+pub const VK_Y: UINT = 0x59;
+pub const VK_Z: UINT = 0x5A;
+pub const VK_RETURN: UINT = 0x0D;
+pub const VK_ESCAPE: UINT = 0x1B;
+pub const VK_SPACE: UINT = 0x20;
+pub const VK_PRIOR: UINT = 0x21;
+pub const VK_NEXT: UINT = 0x22;
+pub const VK_HOME: UINT = 0x24;
+pub const VK_LEFT: UINT = 0x25;
+pub const VK_UP: UINT = 0x26;
+pub const VK_RIGHT: UINT = 0x27;
+pub const VK_DOWN: UINT = 0x28;
+pub const VK_DELETE: UINT = 0x2E;
+pub const VK_NUMPAD0: UINT = 0x60;
+pub const VK_NUMPAD9: UINT = 0x69;
+pub const VK_F1: UINT = 0x70;
+pub const VK_F24: UINT = 0x87;
\ No newline at end of file
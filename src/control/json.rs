@@ -0,0 +1,394 @@
+use std::fmt;
+
+// The part of JSON the control protocol needs. Numbers are integers, which
+// is all the protocol sends, and objects keep their key order:
+//
+//   {"version": 1, "command": "focus-slot 3"}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Boolean(bool),
+    Integer(i64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match *self {
+            Value::Object(ref members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref s) => Some(s),
+            _ => None
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match *self {
+            Value::Integer(i) => Some(i),
+            _ => None
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Boolean(b) => Some(b),
+            _ => None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match *self {
+            Value::Array(ref a) => Some(a),
+            _ => None
+        }
+    }
+}
+
+/// Compact JSON on one line, as `parse` reads it.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Null => write!(f, "null"),
+
+            Value::Boolean(b) => write!(f, "{}", b),
+
+            Value::Integer(i) => write!(f, "{}", i),
+
+            Value::String(ref s) => write_string(f, s),
+
+            Value::Array(ref items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            },
+
+            Value::Object(ref members) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            },
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '\\' => write!(f, "\\\\")?,
+            '"' => write!(f, "\\\"")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    /// Character offset of the error
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at character {}: {}", self.offset, self.message)
+    }
+}
+
+pub fn parse(input: &str) -> Result<Value, ParseError> {
+    let mut cursor = Cursor::new(input);
+
+    cursor.document().map_err(|message| ParseError {
+        offset: cursor.offset,
+        message: message.to_string(),
+    })
+}
+
+struct Cursor<'a> {
+    chars: ::std::iter::Peekable<::std::str::Chars<'a>>,
+    offset: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(s: &'a str) -> Self {
+        Cursor {
+            chars: s.chars().peekable(),
+            offset: 0,
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().cloned()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c.is_some() {
+            self.offset += 1;
+        }
+        c
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == ' ' || c == '\t' || c == '\r' || c == '\n' {
+                self.next();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// A single value with nothing but whitespace around it.
+    fn document(&mut self) -> Result<Value, &'static str> {
+        self.skip_whitespace();
+        let value = self.value()?;
+        self.skip_whitespace();
+
+        match self.peek() {
+            None => Ok(value),
+            Some(_) => Err("unexpected characters after value")
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, &'static str> {
+        match self.peek() {
+            Some('"') => self.string().map(Value::String),
+
+            Some('[') => {
+                self.next();
+                let mut items = Vec::new();
+
+                self.skip_whitespace();
+                if self.eat(']') {
+                    return Ok(Value::Array(items));
+                }
+
+                loop {
+                    self.skip_whitespace();
+                    items.push(self.value()?);
+                    self.skip_whitespace();
+
+                    if self.eat(']') {
+                        return Ok(Value::Array(items));
+                    }
+                    if !self.eat(',') {
+                        return Err("expected ',' or ']'");
+                    }
+                }
+            },
+
+            Some('{') => {
+                self.next();
+                let mut members = Vec::new();
+
+                self.skip_whitespace();
+                if self.eat('}') {
+                    return Ok(Value::Object(members));
+                }
+
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() != Some('"') {
+                        return Err("expected key");
+                    }
+                    let key = self.string()?;
+
+                    self.skip_whitespace();
+                    if !self.eat(':') {
+                        return Err("expected ':'");
+                    }
+
+                    self.skip_whitespace();
+                    members.push((key, self.value()?));
+                    self.skip_whitespace();
+
+                    if self.eat('}') {
+                        return Ok(Value::Object(members));
+                    }
+                    if !self.eat(',') {
+                        return Err("expected ',' or '}'");
+                    }
+                }
+            },
+
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let mut number = String::new();
+                number.push(c);
+                self.next();
+
+                while let Some(c) = self.peek() {
+                    if c.is_ascii_digit() {
+                        number.push(c);
+                        self.next();
+                    } else if c == '.' || c == 'e' || c == 'E' {
+                        return Err("only integers are supported");
+                    } else {
+                        break;
+                    }
+                }
+
+                number.parse().map(Value::Integer).map_err(|_| "invalid integer")
+            },
+
+            Some(_) => {
+                let mut word = String::new();
+                while let Some(c) = self.peek() {
+                    if c.is_ascii_alphabetic() {
+                        word.push(c);
+                        self.next();
+                    } else {
+                        break;
+                    }
+                }
+
+                match word.as_str() {
+                    "true" => Ok(Value::Boolean(true)),
+                    "false" => Ok(Value::Boolean(false)),
+                    "null" => Ok(Value::Null),
+                    _ => Err("invalid value")
+                }
+            },
+
+            None => Err("expected value")
+        }
+    }
+
+    fn string(&mut self) -> Result<String, &'static str> {
+        self.next();
+        let mut s = String::new();
+
+        loop {
+            match self.next() {
+                None => return Err("unterminated string"),
+                Some('"') => return Ok(s),
+                Some('\\') => {
+                    match self.next() {
+                        Some('"') => s.push('"'),
+                        Some('\\') => s.push('\\'),
+                        Some('/') => s.push('/'),
+                        Some('b') => s.push('\u{8}'),
+                        Some('f') => s.push('\u{c}'),
+                        Some('n') => s.push('\n'),
+                        Some('r') => s.push('\r'),
+                        Some('t') => s.push('\t'),
+                        Some('u') => s.push(self.unicode_escape()?),
+                        _ => return Err("invalid escape sequence")
+                    }
+                },
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    /// The character of a `\uXXXX` escape, which may be followed by the
+    /// low half of a surrogate pair.
+    fn unicode_escape(&mut self) -> Result<char, &'static str> {
+        let high = self.hex4()?;
+
+        if !(0xD800..=0xDBFF).contains(&high) {
+            return ::std::char::from_u32(high).ok_or("invalid unicode escape");
+        }
+
+        if !(self.eat('\\') && self.eat('u')) {
+            return Err("unpaired surrogate");
+        }
+
+        let low = self.hex4()?;
+        if !(0xDC00..=0xDFFF).contains(&low) {
+            return Err("unpaired surrogate");
+        }
+
+        ::std::char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)).ok_or("invalid unicode escape")
+    }
+
+    fn hex4(&mut self) -> Result<u32, &'static str> {
+        let mut n = 0;
+
+        for _ in 0..4 {
+            let digit = self.next().and_then(|c| c.to_digit(16)).ok_or("invalid unicode escape")?;
+            n = n * 16 + digit;
+        }
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Value};
+
+    #[test]
+    fn values() {
+        let value = parse(r#" {"version": 1, "ok": true, "error": null,
+            "slots": [{"name": "1", "windows": []}], "offset": -40} "#).unwrap();
+
+        assert_eq!(Some(1), value.get("version").and_then(|v| v.as_integer()));
+        assert_eq!(Some(true), value.get("ok").and_then(|v| v.as_bool()));
+        assert_eq!(Some(&Value::Null), value.get("error"));
+        assert_eq!(Some(-40), value.get("offset").and_then(|v| v.as_integer()));
+
+        let slots = value.get("slots").and_then(|v| v.as_array()).unwrap();
+        assert_eq!(Some("1"), slots[0].get("name").and_then(|v| v.as_str()));
+        assert_eq!(None, value.get("missing"));
+    }
+
+    #[test]
+    fn strings_round_trip() {
+        let value = Value::Object(vec![
+            ("title".to_string(), Value::String("C:\\src \"main.rs\"\n\t\u{1} ✓".to_string())),
+            ("list".to_string(), Value::Array(vec![Value::Integer(1), Value::Boolean(false)])),
+        ]);
+        let text = value.to_string();
+
+        assert_eq!(r#"{"title":"C:\\src \"main.rs\"\n\t\u0001 ✓","list":[1,false]}"#, text);
+        assert_eq!(Ok(value), parse(&text));
+
+        assert_eq!(Ok(Value::String("é😀/".to_string())), parse(r#""\u00e9\ud83d\ude00\/""#));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse("").is_err());
+        assert!(parse("{\"a\" 1}").is_err());
+        assert!(parse("[1, 2").is_err());
+        assert!(parse("1.5").is_err());
+        assert!(parse("\"\\ud83d\"").is_err());
+        assert!(parse("{} {}").is_err());
+        assert!(parse("[true, nope]").is_err());
+        assert_eq!(5, parse("[1, 2").unwrap_err().offset);
+    }
+}
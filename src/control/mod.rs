@@ -0,0 +1,580 @@
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use commands::Command;
+use hotkeys::Report;
+use keys;
+use reactor::{Event, Sender};
+use window_tracking::Config;
+
+use self::json::Value;
+
+pub use self::subscriptions::{mode_event, popup_event, state, window_event, Subscribers, Topic};
+pub use self::transport::default_endpoint;
+
+pub mod json;
+mod subscriptions;
+mod transport;
+
+// Other programs drive a running WinMan through a local endpoint, a named
+// pipe on Windows and a Unix socket elsewhere. A connection carries one
+// request and one response, each a line of JSON:
+//
+//   {"version":1,"command":"focus-slot 3"}
+//   {"version":1,"ok":true}
+//   {"version":1,"ok":true,"result":{"slots":[...]}}
+//   {"version":1,"ok":false,"error":"Unknown command 'nope'"}
+//
+// The command is the same text `Command` parses from the popup. A client
+// can instead subscribe to events, optionally only some types of them, and
+// then reads a line per event after the response, see `subscriptions`:
+//
+//   {"version":1,"subscribe":["slots","focus"]}
+
+/// Bumped when a change would confuse the other side.
+pub const PROTOCOL_VERSION: i64 = 1;
+
+/// How long a connection waits for the main loop to get to its command.
+const REPLY_TIMEOUT_MS: u64 = 5000;
+
+/// Longest request line that is read, a guard against clients that never
+/// send a newline.
+const MAX_REQUEST_LEN: u64 = 64 * 1024;
+
+#[derive(Debug, PartialEq)]
+pub enum Request {
+    Command(Command),
+    /// Events of these types, or all of them when empty
+    Subscribe(Vec<Topic>),
+}
+
+impl Request {
+    pub fn to_json(&self) -> Value {
+        let request = match *self {
+            Request::Command(ref command) => ("command".to_string(), Value::String(command.to_string())),
+            Request::Subscribe(ref topics) => {
+                let topics = topics.iter().map(|topic| Value::String(topic.to_string())).collect();
+                ("subscribe".to_string(), Value::Array(topics))
+            },
+        };
+
+        Value::Object(vec![
+            ("version".to_string(), Value::Integer(PROTOCOL_VERSION)),
+            request,
+        ])
+    }
+
+    pub fn from_json(value: &Value) -> Result<Self, String> {
+        check_version(value)?;
+
+        if let Some(topics) = value.get("subscribe") {
+            let topics = topics.as_array().ok_or_else(|| "subscribe takes a list of event types".to_string())?;
+
+            return topics.iter()
+                .map(|topic| topic.as_str().ok_or_else(|| "event types are strings".to_string()).and_then(str::parse))
+                .collect::<Result<Vec<Topic>, String>>()
+                .map(Request::Subscribe);
+        }
+
+        let command = value.get("command")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| "missing command".to_string())?;
+
+        command.parse::<Command>()
+            .map(Request::Command)
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Response {
+    /// The command ran, with anything it has to show for it
+    Ok(Option<Value>),
+    Error(String),
+}
+
+impl Response {
+    pub fn to_json(&self) -> Value {
+        let mut members = vec![("version".to_string(), Value::Integer(PROTOCOL_VERSION))];
+
+        match *self {
+            Response::Ok(ref result) => {
+                members.push(("ok".to_string(), Value::Boolean(true)));
+                if let Some(ref result) = *result {
+                    members.push(("result".to_string(), result.clone()));
+                }
+            },
+            Response::Error(ref message) => {
+                members.push(("ok".to_string(), Value::Boolean(false)));
+                members.push(("error".to_string(), Value::String(message.clone())));
+            },
+        }
+
+        Value::Object(members)
+    }
+
+    pub fn from_json(value: &Value) -> Result<Self, String> {
+        check_version(value)?;
+
+        match value.get("ok").and_then(|v| v.as_bool()) {
+            Some(true) => Ok(Response::Ok(value.get("result").cloned())),
+            Some(false) => {
+                let message = value.get("error").and_then(|v| v.as_str()).unwrap_or("unknown error");
+                Ok(Response::Error(message.to_string()))
+            },
+            None => Err("missing ok".to_string())
+        }
+    }
+}
+
+fn check_version(value: &Value) -> Result<(), String> {
+    match value.get("version").and_then(|v| v.as_integer()) {
+        Some(PROTOCOL_VERSION) => Ok(()),
+        Some(version) => Err(format!("unsupported protocol version {}, expected {}", version, PROTOCOL_VERSION)),
+        None => Err("missing protocol version".to_string())
+    }
+}
+
+/// The response to a command the main loop has dispatched. Commands that
+/// only print on the console answer with their data instead.
+pub fn response(command: &Command, config: &Config) -> Response {
+    match *command {
+        Command::ListSlots => Response::Ok(Some(slots_json(config))),
+        _ => Response::Ok(None)
+    }
+}
+
+/// `{"slots":[{"name":"1","key":"1","windows":[{"hwnd":1234,"title":"..."}]}]}`
+pub fn slots_json(config: &Config) -> Value {
+    let slots = config.slots().iter()
+        .map(|slot| {
+            let windows = slot.windows().iter()
+                .map(|window| Value::Object(vec![
+                    ("hwnd".to_string(), Value::Integer(window.hwnd() as i64)),
+                    ("title".to_string(), window.title().map(|t| Value::String(t.to_string())).unwrap_or(Value::Null)),
+                ]))
+                .collect();
+
+            let key = slot.key
+                .and_then(keys::key_name)
+                .map(Value::String)
+                .unwrap_or(Value::Null);
+
+            Value::Object(vec![
+                ("name".to_string(), Value::String(slot.name.clone())),
+                ("key".to_string(), key),
+                ("windows".to_string(), Value::Array(windows)),
+            ])
+        })
+        .collect();
+
+    Value::Object(vec![("slots".to_string(), Value::Array(slots))])
+}
+
+/// `{"hotkeys":[{"chord":"Ctrl+Alt+Q","command":"quit","working":true,"outcome":"ok"}]}`
+pub fn hotkeys_json(report: &Report) -> Value {
+    let hotkeys = report.entries.iter()
+        .map(|(hotkey, outcome)| Value::Object(vec![
+            ("chord".to_string(), Value::String(hotkey.chord.to_string())),
+            ("command".to_string(), Value::String(hotkey.command.to_string())),
+            ("working".to_string(), Value::Boolean(outcome.is_working())),
+            ("outcome".to_string(), Value::String(outcome.to_string())),
+        ]))
+        .collect();
+
+    Value::Object(vec![("hotkeys".to_string(), Value::Array(hotkeys))])
+}
+
+/// What a connection asks of WinMan. Each connection runs on a thread of
+/// its own with a clone.
+trait Handler: Clone + Send + 'static {
+    fn command(&self, command: Command) -> Response;
+
+    /// The subscriber's events, starting with the current state.
+    fn subscribe(&self, topics: Vec<Topic>) -> Result<mpsc::Receiver<Value>, String>;
+}
+
+/// Hands requests to the main loop as `Event`s and waits for its answer.
+#[derive(Clone)]
+struct MainLoop {
+    events: Sender<Event>,
+}
+
+impl Handler for MainLoop {
+    fn command(&self, command: Command) -> Response {
+        let (tx, rx) = mpsc::channel();
+
+        if !self.events.send(Event::Control(command, tx)) {
+            return Response::Error("WinMan is shutting down".to_string());
+        }
+
+        rx.recv_timeout(Duration::from_millis(REPLY_TIMEOUT_MS))
+            .unwrap_or_else(|_| Response::Error("WinMan did not answer in time".to_string()))
+    }
+
+    fn subscribe(&self, topics: Vec<Topic>) -> Result<mpsc::Receiver<Value>, String> {
+        let (tx, rx) = mpsc::channel();
+
+        if !self.events.send(Event::Subscribe(topics, tx)) {
+            return Err("WinMan is shutting down".to_string());
+        }
+
+        Ok(rx)
+    }
+}
+
+/// Serves `endpoint` on a thread of its own, handing each request to the
+/// main loop as an `Event::Control` or `Event::Subscribe`.
+///
+/// Fails straight away if the endpoint is taken, e.g. by another WinMan.
+pub fn serve(endpoint: &str, events: Sender<Event>) -> io::Result<()> {
+    serve_with(endpoint, MainLoop { events: events })
+}
+
+fn serve_with<H: Handler>(endpoint: &str, handler: H) -> io::Result<()> {
+    let mut listener = transport::Listener::bind(endpoint)?;
+
+    thread::spawn(move || {
+        loop {
+            match listener.accept() {
+                Ok(stream) => {
+                    // Subscriptions hold on to their connection
+                    let handler = handler.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = answer(stream, &handler) {
+                            println!("Control connection failed: {}", e);
+                        }
+                    });
+                },
+                Err(e) => {
+                    println!("Control endpoint failed, no longer listening: {}", e);
+                    return;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Reads one request from a connection and writes its response, then the
+/// events of a subscription until either side hangs up.
+fn answer<H: Handler>(mut stream: transport::Stream, handler: &H) -> io::Result<()> {
+    let mut line = String::new();
+    BufReader::new((&mut stream).take(MAX_REQUEST_LEN)).read_line(&mut line)?;
+
+    let request = json::parse(&line)
+        .map_err(|e| format!("invalid request {}", e))
+        .and_then(|value| Request::from_json(&value));
+
+    let events = match request {
+        Ok(Request::Command(command)) => return respond(&mut stream, &handler.command(command)),
+        Ok(Request::Subscribe(topics)) => handler.subscribe(topics),
+        Err(message) => Err(message)
+    };
+
+    let events = match events {
+        Ok(events) => events,
+        Err(message) => return respond(&mut stream, &Response::Error(message))
+    };
+
+    respond(&mut stream, &Response::Ok(None))?;
+    for event in events {
+        respond_line(&mut stream, &event)?;
+    }
+
+    Ok(())
+}
+
+fn respond(stream: &mut transport::Stream, response: &Response) -> io::Result<()> {
+    respond_line(stream, &response.to_json())
+}
+
+fn respond_line(stream: &mut transport::Stream, value: &Value) -> io::Result<()> {
+    writeln!(stream, "{}", value)?;
+    stream.flush()
+}
+
+/// Sends one request to a running WinMan, returning its response and the
+/// connection, which carries events after a subscription.
+fn send(endpoint: &str, request: &Request) -> io::Result<(Response, BufReader<transport::Stream>)> {
+    let mut stream = transport::connect(endpoint)?;
+    writeln!(stream, "{}", request.to_json())?;
+    stream.flush()?;
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+
+    let response = json::parse(&line)
+        .map_err(|e| e.to_string())
+        .and_then(|value| Response::from_json(&value))
+        .map_err(invalid_data)?;
+
+    Ok((response, reader))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid response {}", message))
+}
+
+/// Sends one request to a running WinMan.
+pub fn request(endpoint: &str, request: &Request) -> io::Result<Response> {
+    send(endpoint, request).map(|(response, _)| response)
+}
+
+/// Subscribes to a running WinMan's events, calling `on_event` for each
+/// until WinMan goes away.
+pub fn subscribe<F: FnMut(&Value)>(endpoint: &str, topics: Vec<Topic>, mut on_event: F) -> io::Result<()> {
+    let (response, reader) = send(endpoint, &Request::Subscribe(topics))?;
+
+    if let Response::Error(message) = response {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+    }
+
+    for line in reader.lines() {
+        let event = json::parse(&line?).map_err(|e| invalid_data(e.to_string()))?;
+        on_event(&event);
+    }
+
+    Ok(())
+}
+
+/// `winman ctl [--json] <command>`, returning the exit code. With `--json`
+/// the response is printed as it came, for scripts.
+///
+/// `winman ctl subscribe [<event type>...]` prints events as JSON Lines
+/// until WinMan exits.
+pub fn ctl(args: &[String]) -> i32 {
+    let raw = args.iter().any(|arg| arg == "--json");
+    let command: Vec<&str> = args.iter()
+        .filter(|arg| *arg != "--json")
+        .map(|arg| &arg[..])
+        .collect();
+
+    if command.first() == Some(&"subscribe") {
+        return ctl_subscribe(&command[1..]);
+    }
+
+    let command = match command.join(" ").parse::<Command>() {
+        Ok(command) => command,
+        Err(e) => {
+            println!("{}", e);
+            return 2;
+        }
+    };
+
+    let response = match request(&default_endpoint(), &Request::Command(command)) {
+        Ok(response) => response,
+        Err(e) => {
+            println!("Could not reach WinMan: {}", e);
+            return 1;
+        }
+    };
+
+    if raw {
+        println!("{}", response.to_json());
+    }
+
+    match response {
+        Response::Ok(result) => {
+            if let (false, Some(result)) = (raw, result) {
+                print_result(&result);
+            }
+            0
+        },
+        Response::Error(message) => {
+            if !raw {
+                println!("{}", message);
+            }
+            1
+        }
+    }
+}
+
+fn ctl_subscribe(topics: &[&str]) -> i32 {
+    let topics = match topics.iter().map(|topic| topic.parse()).collect() {
+        Ok(topics) => topics,
+        Err(e) => {
+            println!("{}", e);
+            return 2;
+        }
+    };
+
+    match subscribe(&default_endpoint(), topics, |event| println!("{}", event)) {
+        Ok(()) => 0,
+        Err(e) => {
+            println!("Subscription ended: {}", e);
+            1
+        }
+    }
+}
+
+fn print_result(result: &Value) {
+    if result.get("hotkeys").is_some() {
+        print_hotkeys(result);
+        return;
+    }
+
+    let slots = match result.get("slots").and_then(|v| v.as_array()) {
+        Some(slots) => slots,
+        None => {
+            println!("{}", result);
+            return;
+        }
+    };
+
+    for slot in slots {
+        println!("Slot {}:", slot.get("name").and_then(|v| v.as_str()).unwrap_or("?"));
+
+        for window in slot.get("windows").and_then(|v| v.as_array()).unwrap_or(&[]) {
+            println!("    {}: {}",
+                window.get("hwnd").and_then(|v| v.as_integer()).unwrap_or(0),
+                window.get("title").and_then(|v| v.as_str()).unwrap_or("No title"));
+        }
+    }
+}
+
+/// Prints a `hotkeys_json` result like `hotkeys::Report` prints itself,
+/// returning whether every hotkey works.
+pub fn print_hotkeys(result: &Value) -> bool {
+    let mut all_working = true;
+
+    for hotkey in result.get("hotkeys").and_then(|v| v.as_array()).unwrap_or(&[]) {
+        let text = |key: &str| hotkey.get(key).and_then(|v| v.as_str()).unwrap_or("?").to_string();
+        println!("{:<24} {:<28} {}", text("chord"), text("command"), text("outcome"));
+
+        if hotkey.get("working").and_then(|v| v.as_bool()) != Some(true) {
+            all_working = false;
+        }
+    }
+
+    all_working
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread;
+
+    use commands::Command;
+    use hotkeys::{Hotkey, Outcome, Report};
+    use window_tracking::{Config, Window};
+
+    use super::json::{parse, Value};
+    use super::{hotkeys_json, slots_json, Handler, Request, Response, Topic};
+
+    #[test]
+    fn requests_and_responses() {
+        let request = Request::Command(Command::FocusSlot("3".to_string()));
+        let text = request.to_json().to_string();
+
+        assert_eq!(r#"{"version":1,"command":"focus-slot 3"}"#, text);
+        assert_eq!(Ok(request), Request::from_json(&parse(&text).unwrap()));
+
+        let grab = parse(r#"{"version":1,"command":"grab 2"}"#).unwrap();
+        assert_eq!(Ok(Request::Command(Command::GrabToSlot("2".to_string()))), Request::from_json(&grab));
+
+        let subscribe = Request::Subscribe(vec![Topic::Slots, Topic::Focus]);
+        let text = subscribe.to_json().to_string();
+        assert_eq!(r#"{"version":1,"subscribe":["slots","focus"]}"#, text);
+        assert_eq!(Ok(subscribe), Request::from_json(&parse(&text).unwrap()));
+
+        let unknown = parse(r#"{"version":1,"subscribe":["slots","weather"]}"#).unwrap();
+        assert_eq!(Err("unknown event type 'weather'".to_string()), Request::from_json(&unknown));
+
+        for response in [Response::Ok(None), Response::Ok(Some(Value::Integer(1))), Response::Error("no".to_string())] {
+            let value = parse(&response.to_json().to_string()).unwrap();
+            assert_eq!(Ok(response), Response::from_json(&value));
+        }
+    }
+
+    #[test]
+    fn version_mismatch() {
+        let future = parse(r#"{"version":2,"command":"quit"}"#).unwrap();
+        assert_eq!(Err("unsupported protocol version 2, expected 1".to_string()), Request::from_json(&future));
+
+        let missing = parse(r#"{"command":"quit"}"#).unwrap();
+        assert!(Request::from_json(&missing).is_err());
+        assert!(Response::from_json(&missing).is_err());
+    }
+
+    #[test]
+    fn slots() {
+        let mut config = Config::new();
+        config.track_window("3", Window::new(0x1234 as _, "Editor".to_string()));
+
+        let value = slots_json(&config);
+        let slots = value.get("slots").and_then(|v| v.as_array()).unwrap();
+        let slot = slots.iter().find(|s| s.get("name").and_then(|v| v.as_str()) == Some("3")).unwrap();
+
+        assert_eq!(Some("3"), slot.get("key").and_then(|v| v.as_str()));
+        assert_eq!(r#"[{"hwnd":4660,"title":"Editor"}]"#, slot.get("windows").unwrap().to_string());
+    }
+
+    #[test]
+    fn hotkeys_as_json() {
+        let hotkey = Hotkey {
+            chord: "Ctrl+Alt+Q".parse().unwrap(),
+            command: Command::Quit,
+            repeat: false,
+            binding: false,
+        };
+        let report = Report { entries: vec![(hotkey, Outcome::Failed(1409))] };
+
+        assert_eq!(
+            r#"{"hotkeys":[{"chord":"Ctrl+Alt+Q","command":"quit","working":false,"outcome":"taken by another program"}]}"#,
+            hotkeys_json(&report).to_string());
+    }
+
+    /// Answers commands with their own text, and subscriptions with the
+    /// names of their topics. Only the socket test serves it.
+    #[cfg_attr(not(unix), allow(dead_code))]
+    #[derive(Clone)]
+    struct Echo;
+
+    impl Handler for Echo {
+        fn command(&self, command: Command) -> Response {
+            match command {
+                Command::Quit => Response::Error("not here".to_string()),
+                command => Response::Ok(Some(Value::String(command.to_string())))
+            }
+        }
+
+        fn subscribe(&self, topics: Vec<Topic>) -> Result<mpsc::Receiver<Value>, String> {
+            let (tx, rx) = mpsc::channel();
+            thread::spawn(move || {
+                for topic in topics {
+                    tx.send(Value::String(topic.to_string())).unwrap();
+                }
+            });
+            Ok(rx)
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn round_trip_over_a_socket() {
+        let path = ::std::env::temp_dir().join(format!("winman-test-{}.sock", ::std::process::id()));
+        let endpoint = path.to_string_lossy().into_owned();
+
+        super::serve_with(&endpoint, Echo).unwrap();
+
+        let focus = Request::Command(Command::FocusSlot("3".to_string()));
+        assert_eq!(Response::Ok(Some(Value::String("focus-slot 3".to_string()))), super::request(&endpoint, &focus).unwrap());
+
+        let quit = Request::Command(Command::Quit);
+        assert_eq!(Response::Error("not here".to_string()), super::request(&endpoint, &quit).unwrap());
+
+        // Events stream until the server side hangs up
+        let mut events = Vec::new();
+        super::subscribe(&endpoint, vec![Topic::Popup, Topic::Mode], |event| events.push(event.clone())).unwrap();
+        assert_eq!(vec![Value::String("popup".to_string()), Value::String("mode".to_string())], events);
+
+        // A second server on the same endpoint is refused
+        assert!(super::serve_with(&endpoint, Echo).is_err());
+    }
+}
@@ -0,0 +1,263 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::mpsc;
+
+use winapi::windef::HWND;
+
+use window_registry::{WindowEvent, WindowRegistry};
+use window_tracking::Config;
+
+use super::json::Value;
+use super::slots_json;
+
+// A subscriber is sent the current state once, then an event whenever
+// something it subscribed to changes, each a JSON object tagged "event":
+//
+//   {"event":"state","slots":[...],"focus":{...},"mode":"normal"}
+//   {"event":"slots","slots":[...]}
+//   {"event":"focus","window":{"hwnd":1234,"title":"Terminal","slot":"2"}}
+//   {"event":"window-created","window":{"hwnd":1234,"title":"Terminal","exe":"wt.exe","class":"..."}}
+//   {"event":"window-destroyed","hwnd":1234}
+//   {"event":"popup"}
+//   {"event":"mode","mode":"adjust"}
+
+/// What a subscriber can ask for, by the name used in requests.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Topic {
+    Slots,
+    Focus,
+    /// Windows created and destroyed
+    Windows,
+    Popup,
+    Mode,
+}
+
+impl fmt::Display for Topic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Topic::Slots => "slots",
+            Topic::Focus => "focus",
+            Topic::Windows => "windows",
+            Topic::Popup => "popup",
+            Topic::Mode => "mode",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Topic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "slots" => Ok(Topic::Slots),
+            "focus" => Ok(Topic::Focus),
+            "windows" => Ok(Topic::Windows),
+            "popup" => Ok(Topic::Popup),
+            "mode" => Ok(Topic::Mode),
+            _ => Err(format!("unknown event type '{}'", s))
+        }
+    }
+}
+
+/// The connections streaming events, owned by the main loop.
+pub struct Subscribers {
+    /// What each wants, all topics when empty
+    subscribers: Vec<(Vec<Topic>, mpsc::Sender<Value>)>,
+    /// The slots last published, so that only changes are sent
+    slots: Option<Value>,
+}
+
+impl Subscribers {
+    pub fn new() -> Self {
+        Subscribers {
+            subscribers: Vec::new(),
+            slots: None,
+        }
+    }
+
+    /// Sends `state` to a new subscriber, which then gets events for `topics`.
+    pub fn add(&mut self, topics: Vec<Topic>, tx: mpsc::Sender<Value>, state: Value) {
+        if tx.send(state).is_ok() {
+            self.subscribers.push((topics, tx));
+        }
+    }
+
+    /// Sends an event to everyone subscribed to its topic, dropping
+    /// subscribers that have hung up.
+    pub fn publish(&mut self, topic: Topic, event: Value) {
+        self.subscribers.retain(|(topics, tx)| {
+            if !topics.is_empty() && !topics.contains(&topic) {
+                return true;
+            }
+
+            tx.send(event.clone()).is_ok()
+        });
+    }
+
+    /// Publishes the slots if they changed since the last time.
+    pub fn update_slots(&mut self, config: &Config) {
+        if self.subscribers.is_empty() {
+            return;
+        }
+
+        let slots = slots_json(config);
+        if self.slots.as_ref() != Some(&slots) {
+            self.publish(Topic::Slots, event("slots", slots.get("slots").cloned().unwrap_or(Value::Null)));
+            self.slots = Some(slots);
+        }
+    }
+}
+
+/// `{"event":name,name:value}`, or just `{"event":name}` for `Value::Null`.
+fn event(name: &str, value: Value) -> Value {
+    let mut members = vec![("event".to_string(), Value::String(name.to_string()))];
+    if value != Value::Null {
+        members.push((name.to_string(), value));
+    }
+
+    Value::Object(members)
+}
+
+/// Everything a subscriber is told about from then on, as of now.
+pub fn state(config: &Config, registry: &WindowRegistry, mode: &str) -> Value {
+    let focus = registry.focused()
+        .map(|hwnd| window_json(hwnd, registry, config))
+        .unwrap_or(Value::Null);
+
+    Value::Object(vec![
+        ("event".to_string(), Value::String("state".to_string())),
+        ("slots".to_string(), slots_json(config).get("slots").cloned().unwrap_or(Value::Null)),
+        ("focus".to_string(), focus),
+        ("mode".to_string(), Value::String(mode.to_string())),
+    ])
+}
+
+pub fn popup_event() -> Value {
+    event("popup", Value::Null)
+}
+
+/// `mode` is "adjust" while moving a window with the keyboard, or "normal".
+pub fn mode_event(mode: &str) -> Value {
+    event("mode", Value::String(mode.to_string()))
+}
+
+/// The event for a registry change, if subscribers are told about it.
+/// Title changes show up in the slots instead.
+pub fn window_event(event: &WindowEvent, registry: &WindowRegistry, config: &Config) -> Option<(Topic, Value)> {
+    let tagged = |name: &str, key: &str, value: Value| {
+        Value::Object(vec![
+            ("event".to_string(), Value::String(name.to_string())),
+            (key.to_string(), value),
+        ])
+    };
+
+    match *event {
+        WindowEvent::Focused(hwnd) => {
+            Some((Topic::Focus, tagged("focus", "window", window_json(hwnd, registry, config))))
+        },
+
+        WindowEvent::Created(ref info) => {
+            let window = Value::Object(vec![
+                ("hwnd".to_string(), Value::Integer(info.hwnd as i64)),
+                ("title".to_string(), Value::String(info.title.clone())),
+                ("exe".to_string(), Value::String(info.exe.clone())),
+                ("class".to_string(), Value::String(info.class.clone())),
+            ]);
+
+            Some((Topic::Windows, tagged("window-created", "window", window)))
+        },
+
+        WindowEvent::Destroyed(hwnd) => {
+            Some((Topic::Windows, tagged("window-destroyed", "hwnd", Value::Integer(hwnd as i64))))
+        },
+
+        _ => None
+    }
+}
+
+/// A window with its title from the registry and the slot holding it.
+fn window_json(hwnd: HWND, registry: &WindowRegistry, config: &Config) -> Value {
+    let title = registry.windows().iter()
+        .find(|info| info.hwnd == hwnd)
+        .map(|info| Value::String(info.title.clone()))
+        .unwrap_or(Value::Null);
+
+    let slot = config.slots().iter()
+        .find(|slot| slot.windows().iter().any(|w| w.hwnd() == hwnd))
+        .map(|slot| Value::String(slot.name.clone()))
+        .unwrap_or(Value::Null);
+
+    Value::Object(vec![
+        ("hwnd".to_string(), Value::Integer(hwnd as i64)),
+        ("title".to_string(), title),
+        ("slot".to_string(), slot),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use window_registry::{WindowEvent, WindowRegistry};
+    use window_tracking::{Config, Window};
+
+    use super::super::json::Value;
+    use super::{popup_event, window_event, Subscribers, Topic};
+
+    #[test]
+    fn topics_filter_events() {
+        let mut subscribers = Subscribers::new();
+        let (all_tx, all_rx) = mpsc::channel();
+        let (focus_tx, focus_rx) = mpsc::channel();
+        let (gone_tx, gone_rx) = mpsc::channel();
+
+        subscribers.add(Vec::new(), all_tx, Value::Null);
+        subscribers.add(vec![Topic::Focus], focus_tx, Value::Null);
+        subscribers.add(vec![Topic::Popup], gone_tx, Value::Null);
+        drop(gone_rx);
+
+        subscribers.publish(Topic::Popup, popup_event());
+        assert_eq!(2, subscribers.subscribers.len());
+
+        let mut config = Config::new();
+        let registry = WindowRegistry::new();
+        config.track_window("2", Window::new(7 as _, "Terminal".to_string()));
+        let (topic, focus) = window_event(&WindowEvent::Focused(7 as _), &registry, &config).unwrap();
+        subscribers.publish(topic, focus);
+
+        assert_eq!(vec!["null", r#"{"event":"popup"}"#, r#"{"event":"focus","window":{"hwnd":7,"title":null,"slot":"2"}}"#],
+            all_rx.try_iter().map(|v| v.to_string()).collect::<Vec<_>>());
+        assert_eq!(2, focus_rx.try_iter().count());
+    }
+
+    #[test]
+    fn slots_only_when_changed() {
+        let mut subscribers = Subscribers::new();
+        let (tx, rx) = mpsc::channel();
+        subscribers.add(vec![Topic::Slots], tx, Value::Null);
+        rx.recv().unwrap();
+
+        let mut config = Config::new();
+        subscribers.update_slots(&config);
+        subscribers.update_slots(&config);
+        assert_eq!(1, rx.try_iter().count());
+
+        config.track_window("1", Window::new(7 as _, "Editor".to_string()));
+        subscribers.update_slots(&config);
+
+        let event = rx.try_recv().unwrap();
+        assert_eq!(Some("slots"), event.get("event").and_then(|v| v.as_str()));
+        assert!(event.to_string().contains(r#""title":"Editor""#));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn topic_names() {
+        for topic in [Topic::Slots, Topic::Focus, Topic::Windows, Topic::Popup, Topic::Mode] {
+            assert_eq!(Ok(topic), topic.to_string().parse());
+        }
+        assert!("keyboard".parse::<Topic>().is_err());
+    }
+}
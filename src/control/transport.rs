@@ -0,0 +1,349 @@
+// The control endpoint is a named pipe on Windows and a Unix socket
+// elsewhere. Either way a connection is a byte stream that carries one
+// request and its response.
+
+#[cfg(windows)]
+pub use self::pipe::{connect, default_endpoint, Listener, Stream};
+
+#[cfg(unix)]
+pub use self::unix::{connect, default_endpoint, Listener, Stream};
+
+#[cfg(windows)]
+mod pipe {
+    use std::io;
+    use std::io::{Read, Write};
+    use std::ptr;
+    use std::slice;
+
+    use kernel32;
+    use winapi::*;
+
+    use utils;
+
+    const PIPE_ACCESS_DUPLEX: DWORD = 0x3;
+    const FILE_FLAG_FIRST_PIPE_INSTANCE: DWORD = 0x0008_0000;
+    // Byte mode and blocking are the zero flags
+    const PIPE_REJECT_REMOTE_CLIENTS: DWORD = 0x8;
+    const PIPE_UNLIMITED_INSTANCES: DWORD = 255;
+    const BUFFER_SIZE: DWORD = 4096;
+
+    const ERROR_BROKEN_PIPE: DWORD = 109;
+    const ERROR_PIPE_BUSY: DWORD = 231;
+    const ERROR_PIPE_CONNECTED: DWORD = 535;
+
+    // Not in winapi 0.2
+    const TOKEN_USER_CLASS: DWORD = 1;
+    const SDDL_REVISION_1: DWORD = 1;
+
+    /// How long a client waits for a busy server
+    const CONNECT_TIMEOUT_MS: DWORD = 2000;
+
+    // From advapi32, which no dependency covers
+    #[link(name = "advapi32")]
+    extern "system" {
+        fn OpenProcessToken(process: HANDLE, access: DWORD, token: *mut HANDLE) -> BOOL;
+        fn GetTokenInformation(token: HANDLE, class: DWORD, information: LPVOID, length: DWORD, returned: *mut DWORD) -> BOOL;
+        fn ConvertSidToStringSidW(sid: PSID, string: *mut LPWSTR) -> BOOL;
+        fn ConvertStringSecurityDescriptorToSecurityDescriptorW(sddl: LPCWSTR, revision: DWORD, descriptor: *mut PSECURITY_DESCRIPTOR, size: *mut ULONG) -> BOOL;
+    }
+
+    /// One pipe per user and session, e.g. `\\.\pipe\winman-S-1-5-21-…-1001-1`.
+    /// Every WinMan on the machine shares the pipe namespace, and a user
+    /// logged on twice runs a WinMan in each session.
+    pub fn default_endpoint() -> String {
+        let mut session: DWORD = 0;
+        unsafe { kernel32::ProcessIdToSessionId(kernel32::GetCurrentProcessId(), &mut session); }
+
+        match current_user_sid() {
+            Ok(sid) => format!(r"\\.\pipe\winman-{}-{}", sid, session),
+            Err(_) => format!(r"\\.\pipe\winman-{}", session)
+        }
+    }
+
+    /// A pipe instance waiting for the next client. Handles are kept as
+    /// integers so that the listener can move to the thread serving it.
+    pub struct Listener {
+        name: Vec<u16>,
+        next: usize,
+        security: OwnerOnly,
+    }
+
+    impl Listener {
+        /// Fails if another WinMan is serving the same pipe. Only the user
+        /// running WinMan can connect.
+        pub fn bind(endpoint: &str) -> io::Result<Self> {
+            let name = utils::to_wide_chars(endpoint);
+            let security = OwnerOnly::new()?;
+            let first = create_instance(&name, FILE_FLAG_FIRST_PIPE_INSTANCE, &security)?;
+
+            Ok(Listener {
+                name: name,
+                next: first as usize,
+                security: security,
+            })
+        }
+
+        /// Waits for a client to connect.
+        pub fn accept(&mut self) -> io::Result<Stream> {
+            let handle = self.next as HANDLE;
+            let connected = unsafe { kernel32::ConnectNamedPipe(handle, ptr::null_mut()) } != FALSE
+                || unsafe { kernel32::GetLastError() } == ERROR_PIPE_CONNECTED;
+            let error = io::Error::last_os_error();
+
+            // Taking the instance, so the listener needs a new one
+            let stream = Stream {
+                handle: handle as usize,
+                server: true,
+            };
+            self.next = create_instance(&self.name, 0, &self.security)? as usize;
+
+            if connected { Ok(stream) } else { Err(error) }
+        }
+    }
+
+    impl Drop for Listener {
+        fn drop(&mut self) {
+            unsafe { kernel32::CloseHandle(self.next as HANDLE); }
+        }
+    }
+
+    fn create_instance(name: &[u16], flags: DWORD, security: &OwnerOnly) -> io::Result<HANDLE> {
+        let mut attributes = SECURITY_ATTRIBUTES {
+            nLength: ::std::mem::size_of::<SECURITY_ATTRIBUTES>() as DWORD,
+            lpSecurityDescriptor: security.0 as PSECURITY_DESCRIPTOR,
+            bInheritHandle: FALSE,
+        };
+
+        let handle = unsafe {
+            kernel32::CreateNamedPipeW(
+                name.as_ptr(),
+                PIPE_ACCESS_DUPLEX | flags,
+                PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                BUFFER_SIZE,
+                BUFFER_SIZE,
+                0,
+                &mut attributes)
+        };
+
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(handle)
+    }
+
+    /// A security descriptor whose DACL allows the current user and no one
+    /// else, not even administrators.
+    struct OwnerOnly(usize);
+
+    impl OwnerOnly {
+        fn new() -> io::Result<Self> {
+            let sddl = utils::to_wide_chars(&format!("D:P(A;;GA;;;{})", current_user_sid()?));
+            let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+
+            let ok = unsafe {
+                ConvertStringSecurityDescriptorToSecurityDescriptorW(sddl.as_ptr(), SDDL_REVISION_1, &mut descriptor, ptr::null_mut())
+            };
+
+            match ok {
+                FALSE => Err(io::Error::last_os_error()),
+                _ => Ok(OwnerOnly(descriptor as usize))
+            }
+        }
+    }
+
+    impl Drop for OwnerOnly {
+        fn drop(&mut self) {
+            unsafe { kernel32::LocalFree(self.0 as HLOCAL); }
+        }
+    }
+
+    /// The SID of the user running this process, e.g. `S-1-5-21-…-1001`.
+    fn current_user_sid() -> io::Result<String> {
+        unsafe {
+            let mut token: HANDLE = ptr::null_mut();
+            if OpenProcessToken(kernel32::GetCurrentProcess(), TOKEN_QUERY, &mut token) == FALSE {
+                return Err(io::Error::last_os_error());
+            }
+
+            // A TOKEN_USER, which starts with the user's SID_AND_ATTRIBUTES
+            // and keeps the SID after it. u64s keep the pointers aligned.
+            let mut length: DWORD = 0;
+            GetTokenInformation(token, TOKEN_USER_CLASS, ptr::null_mut(), 0, &mut length);
+            let mut buffer = vec![0u64; length as usize / 8 + 1];
+            let ok = GetTokenInformation(token, TOKEN_USER_CLASS, buffer.as_mut_ptr() as LPVOID, length, &mut length);
+            let error = io::Error::last_os_error();
+            kernel32::CloseHandle(token);
+
+            if ok == FALSE {
+                return Err(error);
+            }
+
+            let user = &*(buffer.as_ptr() as *const SID_AND_ATTRIBUTES);
+            let mut string: LPWSTR = ptr::null_mut();
+            if ConvertSidToStringSidW(user.Sid, &mut string) == FALSE {
+                return Err(io::Error::last_os_error());
+            }
+
+            let len = (0..).take_while(|&i| *string.offset(i) != 0).count();
+            let sid = utils::from_wide_slice(slice::from_raw_parts(string, len));
+            kernel32::LocalFree(string as HLOCAL);
+
+            Ok(sid)
+        }
+    }
+
+    pub fn connect(endpoint: &str) -> io::Result<Stream> {
+        let name = utils::to_wide_chars(endpoint);
+
+        loop {
+            let handle = unsafe {
+                kernel32::CreateFileW(
+                    name.as_ptr(),
+                    GENERIC_READ | GENERIC_WRITE,
+                    0,
+                    ptr::null_mut(),
+                    OPEN_EXISTING,
+                    0,
+                    ptr::null_mut())
+            };
+
+            if handle != INVALID_HANDLE_VALUE {
+                return Ok(Stream {
+                    handle: handle as usize,
+                    server: false,
+                });
+            }
+
+            // Every instance is taken, wait for the server to make another
+            if unsafe { kernel32::GetLastError() } != ERROR_PIPE_BUSY {
+                return Err(io::Error::last_os_error());
+            }
+            if unsafe { kernel32::WaitNamedPipeW(name.as_ptr(), CONNECT_TIMEOUT_MS) } == FALSE {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "WinMan is busy"));
+            }
+        }
+    }
+
+    pub struct Stream {
+        handle: usize,
+        server: bool,
+    }
+
+    impl Read for Stream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut read: DWORD = 0;
+            let ok = unsafe {
+                kernel32::ReadFile(self.handle as HANDLE, buf.as_mut_ptr() as LPVOID, buf.len() as DWORD, &mut read, ptr::null_mut())
+            };
+
+            match ok {
+                FALSE if unsafe { kernel32::GetLastError() } == ERROR_BROKEN_PIPE => Ok(0),
+                FALSE => Err(io::Error::last_os_error()),
+                _ => Ok(read as usize)
+            }
+        }
+    }
+
+    impl Write for Stream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut written: DWORD = 0;
+            let ok = unsafe {
+                kernel32::WriteFile(self.handle as HANDLE, buf.as_ptr() as LPCVOID, buf.len() as DWORD, &mut written, ptr::null_mut())
+            };
+
+            match ok {
+                FALSE => Err(io::Error::last_os_error()),
+                _ => Ok(written as usize)
+            }
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Drop for Stream {
+        fn drop(&mut self) {
+            let handle = self.handle as HANDLE;
+
+            // Let the client read the response before hanging up
+            unsafe {
+                if self.server {
+                    kernel32::FlushFileBuffers(handle);
+                    kernel32::DisconnectNamedPipe(handle);
+                }
+                kernel32::CloseHandle(handle);
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::env;
+    use std::fs;
+    use std::io;
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::PathBuf;
+
+    pub type Stream = UnixStream;
+
+    /// In the user's runtime directory, or else named after the user in
+    /// the shared temporary one.
+    pub fn default_endpoint() -> String {
+        let path = match env::var_os("XDG_RUNTIME_DIR") {
+            Some(dir) => PathBuf::from(dir).join("winman.sock"),
+            None => env::temp_dir().join(format!("winman-{}.sock", env::var("USER").unwrap_or_default()))
+        };
+
+        path.to_string_lossy().into_owned()
+    }
+
+    /// Removes the socket file when dropped.
+    pub struct Listener {
+        listener: UnixListener,
+        path: PathBuf,
+    }
+
+    impl Listener {
+        /// Fails if another WinMan is serving the same socket. A socket
+        /// file left behind by one that crashed is replaced. Only the user
+        /// running WinMan can connect.
+        pub fn bind(endpoint: &str) -> io::Result<Self> {
+            if UnixStream::connect(endpoint).is_ok() {
+                return Err(io::Error::new(io::ErrorKind::AddrInUse, "WinMan is already running"));
+            }
+
+            match fs::remove_file(endpoint) {
+                Err(ref e) if e.kind() != io::ErrorKind::NotFound => return Err(io::Error::new(e.kind(), e.to_string())),
+                _ => {}
+            }
+
+            let listener = UnixListener::bind(endpoint)?;
+            fs::set_permissions(endpoint, fs::Permissions::from_mode(0o600))?;
+
+            Ok(Listener {
+                listener: listener,
+                path: PathBuf::from(endpoint),
+            })
+        }
+
+        /// Waits for a client to connect.
+        pub fn accept(&mut self) -> io::Result<Stream> {
+            self.listener.accept().map(|(stream, _)| stream)
+        }
+    }
+
+    impl Drop for Listener {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    pub fn connect(endpoint: &str) -> io::Result<Stream> {
+        UnixStream::connect(endpoint)
+    }
+}
@@ -0,0 +1,747 @@
+use commands::Command;
+use keys::Chord;
+use monitors;
+use scratchpads::Scratchpads;
+use scripting;
+use scripting::{Action, ScriptList, ScriptWindow, Scripts};
+use settings::Settings;
+use snap;
+use snapshots;
+use snapshots::{RestoreReport, Snapshot};
+use tiling::{TileTarget, Tiler};
+use window_filter::WindowFilter;
+use window_registry::WindowRegistry;
+use window_system::WindowSystem;
+use window_tracking::{Config, ShowState, SlotMode, Window};
+use windows;
+
+/// What is left for the caller once a command has run, for the parts that
+/// need the UI.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Done,
+    /// The slot has no window and its program should be started
+    Launch(String),
+    ShowPopup,
+    /// A script showed a list in the popup, to be answered with `Command::Choose`
+    ShowList(Vec<String>),
+    /// Adjust mode takes over the keyboard
+    AdjustWindow,
+    /// The window that registered the hotkeys knows how it went
+    ListHotkeys,
+    /// The hotkeys change, see `hotkeys::BindingTable`
+    Bind(Chord, Command),
+    Unbind(Chord),
+    Quit,
+}
+
+/// Runs `Command`s against a `WindowSystem` and the slots, wherever they
+/// came from: hotkeys, the popup or the command line.
+pub struct Dispatcher<'a> {
+    settings: &'a Settings,
+    filter: &'a WindowFilter,
+    config: Config,
+    tiler: Tiler,
+    scratchpads: Scratchpads,
+    scripts: Scripts,
+    /// The list a script is waiting on an item from
+    list: Option<ScriptList>,
+}
+
+impl<'a> Dispatcher<'a> {
+    pub fn new(settings: &'a Settings, filter: &'a WindowFilter, config: Config) -> Self {
+        Dispatcher {
+            settings: settings,
+            filter: filter,
+            config: config,
+            tiler: Tiler::new(*settings.tiling()),
+            scratchpads: Scratchpads::new(),
+            scripts: Scripts::load(settings.scripts()),
+            list: None,
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn config_mut(&mut self) -> &mut Config {
+        &mut self.config
+    }
+
+    /// The popup is open over a script's list.
+    pub fn showing_list(&self) -> bool {
+        self.list.is_some()
+    }
+
+    /// The popup was closed without choosing from the list.
+    pub fn dismiss_list(&mut self) {
+        self.list = None;
+    }
+
+    /// Runs `command`. `current` is the window it applies to, usually the
+    /// foreground window.
+    pub fn dispatch<S: WindowSystem>(
+        &mut self,
+        system: &mut S,
+        registry: &WindowRegistry,
+        command: &Command,
+        current: Option<&Window>) -> Outcome {
+
+        println!("Running command: {}", command);
+
+        match *command {
+            Command::FocusSlot(ref slot) => {
+                if !self.toggle_slot(system, registry, slot) {
+                    return self.focus_slot(system, slot);
+                }
+            },
+
+            Command::GrabToSlot(ref slot) => {
+                if let Some(window) = current {
+                    println!("Tracking window {:?} in slot {}: {}",
+                        window.hwnd(),
+                        slot,
+                        window.title().unwrap_or("No title"));
+
+                    self.config.track_window(slot, window.clone());
+                }
+            },
+
+            Command::ClearSlot(ref slot) => {
+                println!("Clearing windows in slot {}", slot);
+                self.config.clear_windows(slot);
+            },
+
+            Command::ActivateSlot(ref slot) => {
+                self.activate_slot(system, slot);
+            },
+
+            Command::ShowPopup => {
+                self.list = None;
+                return Outcome::ShowPopup;
+            },
+
+            Command::Quit => return Outcome::Quit,
+
+            Command::MoveWindow(ref from, ref to) => {
+                if let Some(window) = self.config.move_window(from, to) {
+                    println!("Moved window {:?} from slot {} to slot {}", window.hwnd(), from, to);
+                }
+            },
+
+            Command::SwapSlots(ref a, ref b) => {
+                self.config.swap_slots(a, b);
+            },
+
+            Command::MergeSlots(ref from, ref into) => {
+                self.config.merge_slots(from, into);
+            },
+
+            Command::RemoveWindow => {
+                if let Some(window) = current {
+                    let slots = self.config.remove_window(window);
+                    println!("Removed window {:?} from slots {:?}", window.hwnd(), slots);
+                }
+            },
+
+            Command::ReorderWindow(direction) => {
+                let slot = current.and_then(|window| self.config.find_slot(window));
+
+                if let (Some(window), Some(slot)) = (current, slot) {
+                    self.config.reorder_window(&slot, window, direction.offset());
+                }
+            },
+
+            Command::Undo => {
+                if !self.config.undo() {
+                    println!("Nothing to undo");
+                }
+            },
+
+            Command::Redo => {
+                if !self.config.redo() {
+                    println!("Nothing to redo");
+                }
+            },
+
+            Command::ListHotkeys => return Outcome::ListHotkeys,
+
+            Command::ListSlots => {
+                self.config.refresh_titles();
+
+                for slot in self.config.slots() {
+                    println!("Slot {}:", slot.name);
+
+                    for window in slot.windows().iter() {
+                        println!("    {:?}: {}", window.hwnd(), window.title().unwrap_or("No title"));
+                    }
+                }
+            },
+
+            Command::Snap(ref name) => {
+                let region = match self.settings.region(name) {
+                    Some(region) => region,
+                    None => {
+                        println!("Unknown region '{}'", name);
+                        return Outcome::Done;
+                    }
+                };
+
+                if let Some(window) = current {
+                    if let Err(e) = snap::snap_window(system, window.hwnd(), &region) {
+                        println!("Could not snap window {:?}: error {}", window.hwnd(), e);
+                    }
+                }
+            },
+
+            Command::Tile(layout, ref slot) => {
+                if let Some(target) = tile_target(system, slot, current) {
+                    self.tiler.tile(target, layout);
+                }
+            },
+
+            Command::Untile(ref slot) => {
+                if let Some(target) = tile_target(system, slot, current) {
+                    if !self.tiler.untile(&target) {
+                        println!("{} is not tiled", target);
+                    }
+                }
+            },
+
+            Command::SaveLayout(ref name) => {
+                save_layout(system, self.filter, name);
+            },
+
+            Command::RestoreLayout(ref name) => {
+                restore_layout(system, self.filter, name);
+            },
+
+            Command::SendToScratchpad(ref name) => {
+                if let Some(window) = current {
+                    self.scratchpads.send(system, name, window.hwnd());
+                    self.save_scratchpads();
+                }
+            },
+
+            Command::ToggleScratchpad(ref name) => {
+                let area = monitors::active(system, self.settings.popup_monitor())
+                    .map(|monitor| monitor.work_area)
+                    .unwrap_or_else(windows::get_screen_bounds);
+
+                if self.scratchpads.toggle(system, name, area) {
+                    self.save_scratchpads();
+                }
+            },
+
+            Command::RunScript(ref name) => {
+                let snapshot = self.script_snapshot(system, registry, current);
+                let result = self.scripts.run(name, snapshot);
+
+                return self.finish_script(system, registry, name, result);
+            },
+
+            Command::Choose(ref item) => {
+                let list = match self.list.take() {
+                    Some(list) => list,
+                    None => {
+                        println!("No list to choose '{}' from", item);
+                        return Outcome::Done;
+                    }
+                };
+
+                // The item as typed, or else the first one it fuzzy matches
+                let chosen = scripting::matching_items(&list.items, item).first().map(|i| i.to_string());
+
+                match chosen {
+                    Some(chosen) => {
+                        let snapshot = self.script_snapshot(system, registry, current);
+                        let result = self.scripts.choose(&list, &chosen, snapshot);
+
+                        return self.finish_script(system, registry, &list.script, result);
+                    },
+                    None => println!("No item matches '{}'", item)
+                }
+            },
+
+            Command::AdjustWindow => return Outcome::AdjustWindow,
+
+            Command::Bind(chord, ref command) => return Outcome::Bind(chord, (**command).clone()),
+
+            Command::Unbind(chord) => return Outcome::Unbind(chord),
+
+            Command::MoveToMonitor(cycle) => {
+                if let Some(window) = current {
+                    if let Err(e) = monitors::move_window(system, window.hwnd(), cycle.offset()) {
+                        println!("Could not move window {:?}: error {}", window.hwnd(), e);
+                    }
+                }
+            },
+        }
+
+        Outcome::Done
+    }
+
+    /// Tiled groups follow windows joining and leaving.
+    pub fn update_tiles<S: WindowSystem>(&mut self, system: &mut S, registry: &WindowRegistry) {
+        self.tiler.update(system, &self.config, self.filter, registry.windows());
+    }
+
+    /// Puts every scratchpad window back on screen, before quitting.
+    pub fn restore_scratchpads<S: WindowSystem>(&mut self, system: &mut S) {
+        self.scratchpads.restore_all(system);
+        self.save_scratchpads();
+    }
+
+    /// Sends a toggling slot's window away if it has focus, going back to
+    /// the window used before it. Returns false when the slot should be
+    /// switched to as usual.
+    fn toggle_slot<S: WindowSystem>(&mut self, system: &mut S, registry: &WindowRegistry, slot: &str) -> bool {
+        let mode = match self.config.slots().iter().find(|s| s.name == slot) {
+            Some(slot) => slot.mode,
+            None => return false
+        };
+        let foreground = match system.foreground_window() {
+            Some(window) => window,
+            None => return false
+        };
+        let focused = self.config.get_windows(slot).map(|set| set.contains(&foreground)) == Some(true);
+
+        if mode == SlotMode::Cycle || !focused {
+            return false;
+        }
+
+        let hwnd = foreground.hwnd();
+        if mode == SlotMode::Minimize {
+            println!("Minimizing window {:?}", hwnd);
+            system.minimize(hwnd);
+        }
+
+        // Most recently focused first
+        let previous = self.filter.apply(registry.windows())
+            .into_iter()
+            .map(|info| info.hwnd)
+            .find(|&other| other != hwnd && system.placement(other).map(|p| p.state) != Some(ShowState::Minimized));
+
+        if let Some(previous) = previous {
+            let _ = system.set_foreground_window(previous);
+        }
+
+        true
+    }
+
+    fn focus_slot<S: WindowSystem>(&mut self, system: &mut S, slot: &str) -> Outcome {
+        if let Some(window_set) = self.config.get_windows(slot) {
+            while let Some(window) = window_set.cycle() {
+                println!("Switching to window {:?}: {}",
+                    window.hwnd(),
+                    window.title().unwrap_or("No title"));
+
+                match system.set_foreground_window(window.hwnd()) {
+                    Ok(_) => return Outcome::Done,
+                    Err(_) => {
+                        window_set.remove(&window);
+                    }
+                }
+            }
+        }
+
+        // Run-or-raise: adopt an open window matching the slot, or start one
+        let slot_settings = match self.settings.slot(slot) {
+            Some(slot_settings) => slot_settings,
+            None => return Outcome::Done
+        };
+
+        let existing = slot_settings.identity.as_ref().and_then(|rule| {
+            system.windows()
+                .into_iter()
+                .filter_map(|hwnd| system.window_info(hwnd))
+                .find(|info| info.visible && rule.matches(info))
+        });
+
+        if let Some(info) = existing {
+            println!("Adopting window {:?}: {}", info.hwnd, info.title);
+
+            let _ = system.set_foreground_window(info.hwnd);
+            self.config.track_window(slot, Window::new(info.hwnd, info.title));
+            return Outcome::Done;
+        }
+
+        match slot_settings.launch {
+            Some(_) => Outcome::Launch(slot.to_string()),
+            None => Outcome::Done
+        }
+    }
+
+    fn activate_slot<S: WindowSystem>(&mut self, system: &mut S, slot: &str) {
+        let windows = match self.config.get_windows(slot) {
+            Some(window_set) => window_set.z_order(),
+            None => return
+        };
+
+        // Raise bottom-most first so the last focused window ends up on top
+        for window in windows {
+            println!("Raising window {:?}: {}",
+                window.hwnd(),
+                window.title().unwrap_or("No title"));
+
+            // Windows may refuse to change the foreground, which says nothing
+            // about the window, so only closed ones are dropped
+            if system.set_foreground_window(window.hwnd()).is_err() && !system.is_window(window.hwnd()) {
+                self.config.remove_window(&window);
+            }
+        }
+    }
+
+    /// What a script sees of the windows and slots.
+    fn script_snapshot<S: WindowSystem>(&self, system: &S, registry: &WindowRegistry, current: Option<&Window>) -> scripting::Snapshot {
+        let windows = self.filter.apply(registry.windows())
+            .into_iter()
+            .map(|info| ScriptWindow {
+                hwnd: info.hwnd,
+                title: info.title.clone(),
+                exe: info.exe.clone(),
+                class: info.class.clone(),
+                bounds: system.window_bounds(info.hwnd),
+            })
+            .collect();
+
+        let slots = self.config.slots()
+            .iter()
+            .map(|slot| (slot.name.clone(), slot.windows().iter().map(|w| w.hwnd()).collect()))
+            .collect();
+
+        scripting::Snapshot {
+            windows: windows,
+            focused: current.map(|window| window.hwnd()),
+            slots: slots,
+        }
+    }
+
+    /// Carries out what a script asked for. Commands it ran apply to the
+    /// foreground window as it is by then.
+    fn finish_script<S: WindowSystem>(
+        &mut self,
+        system: &mut S,
+        registry: &WindowRegistry,
+        name: &str,
+        result: Result<Vec<Action>, String>) -> Outcome {
+
+        let actions = match result {
+            Ok(actions) => actions,
+            Err(e) => {
+                println!("Script {} failed: {}", name, e);
+                return Outcome::Done;
+            }
+        };
+
+        let mut outcome = Outcome::Done;
+        for action in actions {
+            match action {
+                Action::Focus(hwnd) => {
+                    if let Err(e) = system.set_foreground_window(hwnd) {
+                        println!("Could not focus window {:?}: error {}", hwnd, e);
+                    }
+                },
+
+                Action::SetBounds(hwnd, bounds) => {
+                    if let Err(e) = system.set_window_bounds(hwnd, bounds) {
+                        println!("Could not move window {:?}: error {}", hwnd, e);
+                    }
+                },
+
+                Action::AddToSlot(slot, hwnd) => {
+                    let title = system.window_info(hwnd).map(|info| info.title).unwrap_or_default();
+                    self.config.track_window(&slot, Window::new(hwnd, title));
+                },
+
+                Action::ClearSlot(slot) => {
+                    self.config.clear_windows(&slot);
+                },
+
+                Action::Command(command) => {
+                    let foreground = system.foreground_window();
+                    match self.dispatch(system, registry, &command, foreground.as_ref()) {
+                        Outcome::Done => {},
+                        other => outcome = other
+                    }
+                },
+
+                Action::ShowList(list) => {
+                    outcome = Outcome::ShowList(list.items.clone());
+                    self.list = Some(list);
+                },
+            }
+        }
+
+        outcome
+    }
+
+    /// Keeps the state file current, so that a crash does not lose windows.
+    fn save_scratchpads(&self) {
+        if let Err(e) = self.scratchpads.save() {
+            println!("Could not save scratchpads: {}", e);
+        }
+    }
+}
+
+/// The named slot, or else the monitor of `current`.
+fn tile_target<S: WindowSystem>(system: &S, slot: &Option<String>, current: Option<&Window>) -> Option<TileTarget> {
+    if let Some(ref slot) = *slot {
+        return Some(TileTarget::Slot(slot.clone()));
+    }
+
+    let monitors = system.monitors();
+    current.and_then(|window| monitors::containing(&monitors, system.window_bounds(window.hwnd())))
+           .map(TileTarget::Monitor)
+}
+
+pub fn save_layout<S: WindowSystem>(system: &S, filter: &WindowFilter, name: &str) -> bool {
+    let snapshot = Snapshot::capture(system, filter);
+
+    match snapshots::save(name, &snapshot) {
+        Ok(path) => {
+            println!("Saved {} windows to {}", snapshot.windows.len(), path.display());
+            true
+        },
+        Err(e) => {
+            println!("Could not save layout '{}': {}", name, e);
+            false
+        }
+    }
+}
+
+/// Windows in the layout that are no longer open are listed, not skipped.
+pub fn restore_layout<S: WindowSystem>(system: &mut S, filter: &WindowFilter, name: &str) -> bool {
+    let snapshot = match snapshots::load(name) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            println!("Could not load layout '{}': {}", name, e);
+            return false;
+        }
+    };
+
+    print_report(&snapshot.restore(system, filter));
+    true
+}
+
+pub fn print_report(report: &RestoreReport) {
+    println!("Restored {} windows", report.restored);
+
+    for window in &report.missing {
+        println!("    Not open: {}", window);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use commands::Command;
+    use settings;
+    use window_filter::WindowFilter;
+    use window_registry::WindowRegistry;
+    use window_system::fake::FakeWindowSystem;
+    use window_tracking::{Config, Slot, SlotMode};
+    use super::{Dispatcher, Outcome};
+
+    const SETTINGS: &'static str = r#"
+[slot.editor]
+title = "code"
+run = 'C:\Program Files\Editor\editor.exe'
+
+[slot.mail]
+run = 'mail.exe'
+"#;
+
+    fn config() -> Config {
+        let mut config = Config::new();
+        config.define_slot(Slot::new("editor"));
+        config.define_slot(Slot::new("mail"));
+        config
+    }
+
+    #[test]
+    fn toggling_slots() {
+        let settings = settings::parse("").unwrap();
+        let filter = WindowFilter::new(0);
+        let mut system = FakeWindowSystem::new();
+        let mut registry = WindowRegistry::new();
+
+        // Digit slots exist from the start, the settings only give them a mode
+        let mut config = Config::new();
+        for &(name, mode) in &[("1", SlotMode::Minimize), ("2", SlotMode::Previous)] {
+            let mut slot = Slot::new(name);
+            slot.mode = mode;
+            config.define_slot(slot);
+        }
+        let mut dispatcher = Dispatcher::new(&settings, &filter, config);
+
+        let other = system.open("other");
+        let a = system.open("a");
+        dispatcher.dispatch(&mut system, &registry, &Command::GrabToSlot("1".to_string()), Some(&a));
+        registry.refresh(&system);
+
+        // Minimize sends the focused window away and goes back
+        let focus_1 = Command::FocusSlot("1".to_string());
+        dispatcher.dispatch(&mut system, &registry, &focus_1, None);
+        assert!(system.window(a.hwnd()).minimized);
+        assert_eq!(Some(other.hwnd()), system.foreground);
+
+        // and the next press brings it back
+        registry.refresh(&system);
+        dispatcher.dispatch(&mut system, &registry, &focus_1, None);
+        assert!(!system.window(a.hwnd()).minimized);
+        assert_eq!(Some(a.hwnd()), system.foreground);
+
+        // Previous goes back without minimizing
+        let b = system.open("b");
+        dispatcher.dispatch(&mut system, &registry, &Command::GrabToSlot("2".to_string()), Some(&b));
+        registry.refresh(&system);
+        dispatcher.dispatch(&mut system, &registry, &Command::FocusSlot("2".to_string()), None);
+        assert!(!system.window(b.hwnd()).minimized);
+        assert_eq!(Some(a.hwnd()), system.foreground);
+
+        // Cycle stays on a slot's only window
+        let c = system.open("c");
+        dispatcher.dispatch(&mut system, &registry, &Command::GrabToSlot("3".to_string()), Some(&c));
+        registry.refresh(&system);
+        dispatcher.dispatch(&mut system, &registry, &Command::FocusSlot("3".to_string()), None);
+        assert!(!system.window(c.hwnd()).minimized);
+        assert_eq!(Some(c.hwnd()), system.foreground);
+    }
+
+    #[test]
+    fn slot_commands() {
+        let settings = settings::parse("").unwrap();
+        let filter = WindowFilter::new(0);
+        let mut system = FakeWindowSystem::new();
+        let registry = WindowRegistry::new();
+        let mut dispatcher = Dispatcher::new(&settings, &filter, config());
+
+        let a = system.open("a");
+        let b = system.open("b");
+
+        for window in &[a.clone(), b.clone()] {
+            let command = Command::GrabToSlot("editor".to_string());
+            assert_eq!(Outcome::Done, dispatcher.dispatch(&mut system, &registry, &command, Some(window)));
+        }
+
+        // Focusing cycles through the slot
+        let focus = Command::FocusSlot("editor".to_string());
+        dispatcher.dispatch(&mut system, &registry, &focus, None);
+        assert_eq!(Some(a.hwnd()), system.foreground);
+        dispatcher.dispatch(&mut system, &registry, &focus, None);
+        assert_eq!(Some(b.hwnd()), system.foreground);
+
+        dispatcher.dispatch(&mut system, &registry, &Command::ClearSlot("editor".to_string()), None);
+        assert!(dispatcher.config_mut().get_windows("editor").unwrap().front().is_none());
+
+        assert_eq!(Outcome::ShowPopup, dispatcher.dispatch(&mut system, &registry, &Command::ShowPopup, None));
+        assert_eq!(Outcome::Quit, dispatcher.dispatch(&mut system, &registry, &Command::Quit, None));
+
+        let bind = "bind Alt+F5 focus-slot editor".parse::<Command>().unwrap();
+        assert_eq!(Outcome::Bind("Alt+F5".parse().unwrap(), focus), dispatcher.dispatch(&mut system, &registry, &bind, None));
+    }
+
+    #[test]
+    fn activating_keeps_refused_windows() {
+        let settings = settings::parse("").unwrap();
+        let filter = WindowFilter::new(0);
+        let mut system = FakeWindowSystem::new();
+        let registry = WindowRegistry::new();
+        let mut dispatcher = Dispatcher::new(&settings, &filter, config());
+
+        let a = system.open("a");
+        let b = system.open("b");
+        for window in &[a.clone(), b.clone()] {
+            dispatcher.dispatch(&mut system, &registry, &Command::GrabToSlot("editor".to_string()), Some(window));
+        }
+        let activate = Command::ActivateSlot("editor".to_string());
+        let count = |dispatcher: &mut Dispatcher| dispatcher.config_mut().get_windows("editor").unwrap().z_order().len();
+
+        // Foreground changes refused by Windows leave the slot alone
+        system.foreground_locked = true;
+        dispatcher.dispatch(&mut system, &registry, &activate, None);
+        assert_eq!(2, count(&mut dispatcher));
+
+        // A closed window is dropped, and that can be undone
+        system.foreground_locked = false;
+        system.windows.retain(|w| w.info.hwnd != a.hwnd());
+        dispatcher.dispatch(&mut system, &registry, &activate, None);
+        assert_eq!(1, count(&mut dispatcher));
+        assert_eq!(Some(b.hwnd()), system.foreground);
+
+        dispatcher.dispatch(&mut system, &registry, &Command::Undo, None);
+        assert_eq!(2, count(&mut dispatcher));
+    }
+
+    #[test]
+    fn run_or_raise() {
+        let settings = settings::parse(SETTINGS).unwrap();
+        let filter = WindowFilter::new(0);
+        let mut system = FakeWindowSystem::new();
+        let registry = WindowRegistry::new();
+        let mut dispatcher = Dispatcher::new(&settings, &filter, config());
+
+        // Nothing open, so the programs are started
+        let editor = Command::FocusSlot("editor".to_string());
+        let mail = Command::FocusSlot("mail".to_string());
+        assert_eq!(Outcome::Launch("editor".to_string()), dispatcher.dispatch(&mut system, &registry, &editor, None));
+        assert_eq!(Outcome::Launch("mail".to_string()), dispatcher.dispatch(&mut system, &registry, &mail, None));
+
+        // An open window matching the slot is adopted instead
+        let code = system.open("main.rs - code").hwnd();
+        system.open("notes");
+        assert_eq!(Outcome::Done, dispatcher.dispatch(&mut system, &registry, &editor, None));
+        assert_eq!(Some(code), system.foreground);
+        assert_eq!(Some(code), dispatcher.config_mut().get_windows("editor").unwrap().front().map(|w| w.hwnd()));
+    }
+
+    #[test]
+    fn scripts() {
+        let path = env::temp_dir().join(format!("winman-test-{}.rhai", process::id()));
+        fs::write(&path, r#"
+            fn picked(slot) { run("focus-slot " + slot); }
+
+            let window = focused();
+            set_rect(window.hwnd, [0, 0, 500, 500]);
+            add_to_slot("editor", window.hwnd);
+            popup(["mail", "editor"], "picked");
+        "#).unwrap();
+
+        let settings = settings::parse(&format!("[script.arrange]\nfile = '{}'\n", path.display())).unwrap();
+        let filter = WindowFilter::new(0);
+        let mut system = FakeWindowSystem::new();
+        let mut registry = WindowRegistry::new();
+        let mut dispatcher = Dispatcher::new(&settings, &filter, config());
+        fs::remove_file(&path).unwrap();
+
+        let a = system.open("a");
+        registry.refresh(&system);
+
+        // What the script asks for is done, and its list shown
+        let arrange = Command::RunScript("arrange".to_string());
+        let list = Outcome::ShowList(vec!["mail".to_string(), "editor".to_string()]);
+        assert_eq!(list, dispatcher.dispatch(&mut system, &registry, &arrange, Some(&a)));
+        assert_eq!((0, 0, 500, 500), system.window(a.hwnd()).bounds);
+        assert_eq!(Some(a.hwnd()), dispatcher.config_mut().get_windows("editor").unwrap().front().map(|w| w.hwnd()));
+
+        // Picking from the list calls back into the script
+        system.open("b");
+        assert!(dispatcher.showing_list());
+        assert_eq!(Outcome::Done, dispatcher.dispatch(&mut system, &registry, &Command::Choose("edi".to_string()), None));
+        assert_eq!(Some(a.hwnd()), system.foreground);
+        assert!(!dispatcher.showing_list());
+
+        // Closing the popup drops the list, so a later pick goes nowhere
+        assert_eq!(list, dispatcher.dispatch(&mut system, &registry, &arrange, Some(&a)));
+        dispatcher.dismiss_list();
+        assert!(!dispatcher.showing_list());
+        assert_eq!(Outcome::Done, dispatcher.dispatch(&mut system, &registry, &Command::Choose("mail".to_string()), None));
+
+        // Failing scripts are only reported
+        let missing = Command::RunScript("missing".to_string());
+        assert_eq!(Outcome::Done, dispatcher.dispatch(&mut system, &registry, &missing, None));
+    }
+}
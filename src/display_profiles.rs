@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use monitors;
+use snapshots;
+use snapshots::{RestoreReport, Snapshot};
+use window_filter::WindowFilter;
+use window_system::WindowSystem;
+
+/// Remembers the layout of every monitor arrangement seen, and puts windows
+/// back when an arrangement returns, e.g. when a laptop is docked again.
+///
+/// Windows has usually moved windows around by the time it reports a display
+/// change, so layouts are recorded ahead of time by calling `update`
+/// regularly rather than when the change arrives.
+///
+/// Layouts are kept in the `layouts` folder as `display-<fingerprint>.toml`,
+/// so that they survive a restart.
+pub struct DisplayProfiles {
+    /// Fingerprint of the arrangement the last update saw
+    current: String,
+    layouts: HashMap<String, Snapshot>,
+    folder: PathBuf,
+}
+
+impl DisplayProfiles {
+    pub fn new<S: WindowSystem>(system: &S) -> Self {
+        DisplayProfiles::in_folder(system, snapshots::folder())
+    }
+
+    /// Keeps the layouts in `folder`, loading those already there.
+    pub fn in_folder<S: WindowSystem>(system: &S, folder: PathBuf) -> Self {
+        let mut layouts = HashMap::new();
+
+        // A missing folder just means nothing was saved yet
+        for entry in fs::read_dir(&folder).into_iter().flat_map(|entries| entries.filter_map(|e| e.ok())) {
+            let path = entry.path();
+            let fingerprint = match path.file_stem().and_then(|stem| stem.to_str()).and_then(fingerprint_of) {
+                Some(fingerprint) => fingerprint,
+                None => continue
+            };
+
+            match snapshots::load_file(&path) {
+                Ok(snapshot) => { layouts.insert(fingerprint, snapshot); },
+                Err(e) => println!("Could not load {}: {}", path.display(), e)
+            }
+        }
+
+        DisplayProfiles {
+            current: monitors::fingerprint(&system.monitors()),
+            layouts: layouts,
+            folder: folder,
+        }
+    }
+
+    /// Records the layout of the current arrangement. If the arrangement
+    /// changed since the last update, restores the layout remembered for the
+    /// new one instead, if any.
+    pub fn update<S: WindowSystem>(&mut self, system: &mut S, filter: &WindowFilter) -> Option<RestoreReport> {
+        let fingerprint = monitors::fingerprint(&system.monitors());
+
+        if fingerprint == self.current {
+            let snapshot = Snapshot::capture(system, filter);
+
+            // Only written when something moved
+            if self.layouts.get(&fingerprint) != Some(&snapshot) {
+                let path = self.folder.join(format!("{}.toml", file_stem(&fingerprint)));
+                if let Err(e) = snapshots::save_file(&path, &snapshot) {
+                    println!("Could not save {}: {}", path.display(), e);
+                }
+
+                self.layouts.insert(fingerprint, snapshot);
+            }
+
+            return None;
+        }
+
+        println!("Monitors changed from {} to {}", self.current, fingerprint);
+        self.current = fingerprint;
+
+        self.layouts
+            .get(&self.current)
+            .map(|snapshot| snapshot.restore(system, filter))
+    }
+}
+
+/// Fingerprints such as `1920x1080+0+0,1280x1024-1280+0` as layout names,
+/// which are limited to letters, digits, `-` and `_`.
+fn file_stem(fingerprint: &str) -> String {
+    let name: String = fingerprint.chars()
+        .map(|c| match c {
+            '+' => 'p',
+            '-' => 'm',
+            ',' => '_',
+            c => c
+        })
+        .collect();
+
+    format!("display-{}", name)
+}
+
+/// Inverse of `file_stem`, `None` for other layouts.
+fn fingerprint_of(stem: &str) -> Option<String> {
+    if !stem.starts_with("display-") {
+        return None;
+    }
+
+    Some(stem["display-".len()..].chars()
+        .map(|c| match c {
+            'p' => '+',
+            'm' => '-',
+            '_' => ',',
+            c => c
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+    use std::path::PathBuf;
+
+    use monitors;
+    use window_filter::WindowFilter;
+    use window_system::WindowSystem;
+    use window_system::fake::FakeWindowSystem;
+    use super::{fingerprint_of, file_stem, DisplayProfiles};
+
+    fn folder(test: &str) -> PathBuf {
+        let folder = env::temp_dir().join(format!("winman-test-{}-{}", test, process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        folder
+    }
+
+    #[test]
+    fn fingerprints() {
+        let mut system = FakeWindowSystem::new();
+        system.add_monitor((-1280, 0, 0, 1024), (-1280, 0, 0, 984));
+
+        let fingerprint = monitors::fingerprint(&system.monitors());
+        assert_eq!("1920x1080+0+0,1280x1024-1280+0", fingerprint);
+
+        assert_eq!("display-1920x1080p0p0_1280x1024m1280p0", file_stem(&fingerprint));
+        assert_eq!(Some(fingerprint.clone()), fingerprint_of(&file_stem(&fingerprint)));
+        assert_eq!(None, fingerprint_of("work"));
+    }
+
+    #[test]
+    fn restores_layout_when_arrangement_returns() {
+        let mut system = FakeWindowSystem::new();
+        system.add_monitor((1920, 0, 4480, 1440), (1920, 0, 4480, 1400));
+        let filter = WindowFilter::new(0);
+
+        let editor = system.open("editor").hwnd();
+        let mail = system.open("mail").hwnd();
+        system.window_mut(editor).bounds = (2000, 100, 3000, 900);
+        system.window_mut(mail).bounds = (0, 0, 960, 1040);
+
+        let folder = folder("profiles");
+        let mut profiles = DisplayProfiles::in_folder(&system, folder.clone());
+        assert!(profiles.update(&mut system, &filter).is_none());
+
+        // Undocked: nothing remembered for a single monitor yet
+        system.remove_monitor(1);
+        assert_eq!(0, profiles.update(&mut system, &filter).map_or(0, |r| r.restored));
+        assert_eq!((80, 100, 1080, 900), system.window(editor).bounds);
+
+        // Rearranged while undocked, and remembered for next time
+        system.window_mut(mail).bounds = (960, 0, 1920, 1040);
+        assert!(profiles.update(&mut system, &filter).is_none());
+
+        system.add_monitor((1920, 0, 4480, 1440), (1920, 0, 4480, 1400));
+        let report = profiles.update(&mut system, &filter).unwrap();
+        assert_eq!(2, report.restored);
+        assert_eq!((2000, 100, 3000, 900), system.window(editor).bounds);
+        assert_eq!((0, 0, 960, 1040), system.window(mail).bounds);
+
+        system.remove_monitor(1);
+        profiles.update(&mut system, &filter);
+        assert_eq!((960, 0, 1920, 1040), system.window(mail).bounds);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn layouts_outlive_a_restart() {
+        let mut system = FakeWindowSystem::new();
+        system.add_monitor((1920, 0, 4480, 1440), (1920, 0, 4480, 1400));
+        let filter = WindowFilter::new(0);
+
+        let editor = system.open("editor").hwnd();
+        system.window_mut(editor).bounds = (2000, 100, 3000, 900);
+
+        let folder = folder("restart");
+        let mut profiles = DisplayProfiles::in_folder(&system, folder.clone());
+        profiles.update(&mut system, &filter);
+        assert_eq!(1, fs::read_dir(&folder).unwrap().count());
+
+        // Restarted while undocked, then docked again
+        system.remove_monitor(1);
+        let mut profiles = DisplayProfiles::in_folder(&system, folder.clone());
+        profiles.update(&mut system, &filter);
+        assert_eq!((80, 100, 1080, 900), system.window(editor).bounds);
+
+        system.add_monitor((1920, 0, 4480, 1440), (1920, 0, 4480, 1400));
+        assert_eq!(1, profiles.update(&mut system, &filter).unwrap().restored);
+        assert_eq!((2000, 100, 3000, 900), system.window(editor).bounds);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,378 @@
+use std::fmt;
+
+use winapi::minwindef::*;
+
+use commands::{Command, Cycle, Direction};
+use constants::*;
+use keys::Chord;
+
+/// Another program, or a running WinMan, holds the chord
+const ERROR_HOTKEY_ALREADY_REGISTERED: DWORD = 1409;
+
+const MOD_QUIT: u32 = MOD_CONTROL | MOD_ALT;
+const MOD_POPUP: u32 = MOD_NOREPEAT | MOD_ALT;
+const MOD_GRAB: u32 = MOD_NOREPEAT| MOD_ALT | MOD_SHIFT;
+const MOD_SWITCH: u32 = MOD_NOREPEAT | MOD_ALT;
+const MOD_CLEAR: u32 = MOD_NOREPEAT | MOD_CONTROL | MOD_ALT | MOD_SHIFT;
+const MOD_ACTIVATE: u32 = MOD_NOREPEAT | MOD_CONTROL | MOD_ALT;
+const MOD_REMOVE: u32 = MOD_NOREPEAT | MOD_ALT | MOD_SHIFT;
+const MOD_REORDER: u32 = MOD_ALT | MOD_SHIFT;
+const MOD_UNDO: u32 = MOD_CONTROL | MOD_ALT;
+const MOD_SNAP: u32 = MOD_CONTROL | MOD_ALT;
+const MOD_MONITOR: u32 = MOD_CONTROL | MOD_ALT | MOD_SHIFT;
+const MOD_ADJUST: u32 = MOD_NOREPEAT | MOD_ALT | MOD_SHIFT;
+const MOD_SCRATCHPAD: u32 = MOD_NOREPEAT | MOD_CONTROL | MOD_SHIFT;
+const MOD_SCRIPT: u32 = MOD_NOREPEAT | MOD_CONTROL | MOD_ALT;
+
+/// A chord and the command it sends.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hotkey {
+    pub chord: Chord,
+    pub command: Command,
+    /// Whether holding the keys repeats it
+    pub repeat: bool,
+    /// Whether it comes from `[bindings]` or `bind`, which may take over
+    /// built-in chords
+    pub binding: bool,
+}
+
+impl Hotkey {
+    /// A built-in hotkey. With `MOD_NOREPEAT` in `modifiers` holding the
+    /// keys does not repeat it.
+    fn built_in(modifiers: u32, vk: UINT, command: Command) -> Self {
+        Hotkey {
+            chord: Chord {
+                modifiers: modifiers & !MOD_NOREPEAT,
+                vk: vk,
+            },
+            command: command,
+            repeat: modifiers & MOD_NOREPEAT == 0,
+            binding: false,
+        }
+    }
+}
+
+/// The hotkeys every WinMan has, whatever the settings.
+pub fn built_in_hotkeys() -> Vec<Hotkey> {
+    let mut hotkeys = Vec::new();
+    let mut add = |modifiers: u32, vk: UINT, command: Command| {
+        hotkeys.push(Hotkey::built_in(modifiers, vk, command));
+    };
+
+    // Virtual key codes: https://msdn.microsoft.com/en-us/library/windows/desktop/dd375731(v=vs.85).aspx
+    // CTRL-ALT-Q to quit
+    add(MOD_QUIT, VK_Q, Command::Quit);
+    add(MOD_POPUP, VK_SPACE, Command::ShowPopup);
+
+    // ALT-SHIFT-DELETE to remove the foreground window from its slots,
+    // ALT-SHIFT-PGUP/PGDN to move it within its slot
+    add(MOD_REMOVE, VK_DELETE, Command::RemoveWindow);
+    add(MOD_REORDER, VK_PRIOR, Command::ReorderWindow(Direction::Up));
+    add(MOD_REORDER, VK_NEXT, Command::ReorderWindow(Direction::Down));
+
+    // CTRL-ALT-Z to undo slot edits, CTRL-ALT-Y to redo
+    add(MOD_UNDO, VK_Z, Command::Undo);
+    add(MOD_UNDO, VK_Y, Command::Redo);
+
+    // CTRL-ALT-arrows to snap the foreground window to a half, repeat to
+    // cycle its size, CTRL-ALT-HOME to center it
+    let snaps = [(VK_LEFT, "left"), (VK_RIGHT, "right"), (VK_UP, "top"), (VK_DOWN, "bottom"), (VK_HOME, "center")];
+    for &(vk, region) in &snaps {
+        add(MOD_SNAP, vk, Command::Snap(region.to_string()));
+    }
+
+    // CTRL-ALT-SHIFT-LEFT/RIGHT to move the foreground window to the
+    // previous or next monitor
+    add(MOD_MONITOR, VK_LEFT, Command::MoveToMonitor(Cycle::Previous));
+    add(MOD_MONITOR, VK_RIGHT, Command::MoveToMonitor(Cycle::Next));
+
+    // ALT-SHIFT-SPACE to move and resize the foreground window with the
+    // arrow keys, until ENTER or ESC
+    add(MOD_ADJUST, VK_SPACE, Command::AdjustWindow);
+
+    hotkeys
+}
+
+/// CTRL-SHIFT-key to show or hide a scratchpad window
+pub fn scratchpad_hotkey(name: &str, vk: UINT) -> Hotkey {
+    Hotkey::built_in(MOD_SCRATCHPAD, vk, Command::ToggleScratchpad(name.to_string()))
+}
+
+/// CTRL-ALT-key to run a script
+pub fn script_hotkey(name: &str, vk: UINT) -> Hotkey {
+    Hotkey::built_in(MOD_SCRIPT, vk, Command::RunScript(name.to_string()))
+}
+
+/// For a slot key:
+/// ALT-SHIFT-key to grab windows,
+/// ALT-key to switch windows,
+/// CTRL-ALT-SHIFT-key to clear the slot,
+/// CTRL-ALT-key to raise every window in the set
+pub fn slot_hotkeys(name: &str, vk: UINT) -> Vec<Hotkey> {
+    vec![
+        Hotkey::built_in(MOD_GRAB, vk, Command::GrabToSlot(name.to_string())),
+        Hotkey::built_in(MOD_SWITCH, vk, Command::FocusSlot(name.to_string())),
+        Hotkey::built_in(MOD_CLEAR, vk, Command::ClearSlot(name.to_string())),
+        Hotkey::built_in(MOD_ACTIVATE, vk, Command::ActivateSlot(name.to_string())),
+    ]
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Registered,
+    /// A binding took the chord of this built-in hotkey
+    Overridden(String),
+    /// An earlier hotkey of ours has the same chord
+    Conflict(String),
+    /// `RegisterHotKey` failed with this error
+    Failed(DWORD),
+}
+
+impl Outcome {
+    pub fn is_working(&self) -> bool {
+        match *self {
+            Outcome::Registered => true,
+            // The binding works in its place
+            Outcome::Overridden(_) => true,
+            Outcome::Conflict(_) => false,
+            Outcome::Failed(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Outcome::Registered => write!(f, "ok"),
+            Outcome::Overridden(ref by) => write!(f, "replaced by binding '{}'", by),
+            Outcome::Conflict(ref with) => write!(f, "conflicts with '{}'", with),
+            Outcome::Failed(ERROR_HOTKEY_ALREADY_REGISTERED) => write!(f, "taken by another program"),
+            Outcome::Failed(error) => write!(f, "failed with error {}", error),
+        }
+    }
+}
+
+/// How registering each hotkey went, in the order they were registered.
+pub struct Report {
+    pub entries: Vec<(Hotkey, Outcome)>,
+}
+
+impl Report {
+    /// The hotkeys that are not working, other than those replaced by bindings.
+    pub fn failures(&self) -> Vec<&(Hotkey, Outcome)> {
+        self.entries.iter()
+            .filter(|(_, outcome)| !outcome.is_working())
+            .collect()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (hotkey, outcome) in &self.entries {
+            writeln!(f, "{:<24} {:<28} {}", hotkey.chord.to_string(), hotkey.command.to_string(), outcome)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Where hotkeys go, a window's `RegisterHotKey` outside of tests.
+pub trait Registrar {
+    /// Gives the Win32 error of a failed registration.
+    fn register(&mut self, id: i32, chord: Chord, repeat: bool) -> Result<(), DWORD>;
+    fn unregister(&mut self, id: i32);
+}
+
+/// The registered hotkeys by the id `WM_HOTKEY` reports them with. Every
+/// hotkey gets an id of its own and ids are not reused, so that a message
+/// for one removed since finds nothing.
+pub struct BindingTable {
+    entries: Vec<(i32, Hotkey)>,
+    next_id: i32,
+}
+
+impl BindingTable {
+    /// Ids are given out from `first_id` up.
+    pub fn new(first_id: i32) -> Self {
+        BindingTable {
+            entries: Vec::new(),
+            next_id: first_id,
+        }
+    }
+
+    /// Registers `hotkey` unless its chord is already bound.
+    pub fn add<R: Registrar>(&mut self, registrar: &mut R, hotkey: Hotkey) -> Result<i32, Outcome> {
+        if let Some((_, other)) = self.entries.iter().find(|(_, other)| other.chord == hotkey.chord) {
+            return Err(if other.binding && !hotkey.binding {
+                Outcome::Overridden(other.command.to_string())
+            } else {
+                Outcome::Conflict(other.command.to_string())
+            });
+        }
+
+        let id = self.next_id;
+        registrar.register(id, hotkey.chord, hotkey.repeat).map_err(Outcome::Failed)?;
+
+        self.next_id += 1;
+        self.entries.push((id, hotkey));
+        Ok(id)
+    }
+
+    /// Adds `hotkeys` in order, reporting how each went.
+    pub fn add_all<R: Registrar>(&mut self, registrar: &mut R, hotkeys: Vec<Hotkey>) -> Report {
+        let entries = hotkeys.into_iter()
+            .map(|hotkey| {
+                let outcome = match self.add(registrar, hotkey.clone()) {
+                    Ok(_) => Outcome::Registered,
+                    Err(outcome) => outcome
+                };
+
+                (hotkey, outcome)
+            })
+            .collect();
+
+        Report { entries: entries }
+    }
+
+    /// Unregisters whatever `chord` is bound to.
+    pub fn remove<R: Registrar>(&mut self, registrar: &mut R, chord: Chord) -> Option<Hotkey> {
+        let index = self.entries.iter().position(|(_, hotkey)| hotkey.chord == chord)?;
+        let (id, hotkey) = self.entries.remove(index);
+        registrar.unregister(id);
+
+        Some(hotkey)
+    }
+
+    /// Binds the chord of `hotkey` to it instead of whatever it had. The
+    /// old hotkey is put back if the new one cannot be registered.
+    pub fn replace<R: Registrar>(&mut self, registrar: &mut R, hotkey: Hotkey) -> Result<i32, Outcome> {
+        let old = self.remove(registrar, hotkey.chord);
+
+        let result = self.add(registrar, hotkey);
+        if let (Err(_), Some(old)) = (&result, old) {
+            let _ = self.add(registrar, old);
+        }
+
+        result
+    }
+
+    pub fn clear<R: Registrar>(&mut self, registrar: &mut R) {
+        for (id, _) in self.entries.drain(..) {
+            registrar.unregister(id);
+        }
+    }
+
+    pub fn command(&self, id: i32) -> Option<&Command> {
+        self.entries.iter()
+            .find(|&&(other, _)| other == id)
+            .map(|(_, hotkey)| &hotkey.command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use commands::Command;
+    use constants::*;
+    use keys::Chord;
+
+    use super::{BindingTable, Hotkey, Outcome, Registrar, ERROR_HOTKEY_ALREADY_REGISTERED};
+
+    /// Holds chords by id like Windows does, with some owned by another program.
+    struct FakeRegistrar {
+        registered: HashMap<i32, Chord>,
+        taken: Vec<Chord>,
+    }
+
+    impl Registrar for FakeRegistrar {
+        fn register(&mut self, id: i32, chord: Chord, _repeat: bool) -> Result<(), u32> {
+            if self.taken.contains(&chord) || self.registered.values().any(|&c| c == chord) {
+                return Err(ERROR_HOTKEY_ALREADY_REGISTERED);
+            }
+
+            self.registered.insert(id, chord);
+            Ok(())
+        }
+
+        fn unregister(&mut self, id: i32) {
+            self.registered.remove(&id);
+        }
+    }
+
+    fn registrar() -> FakeRegistrar {
+        FakeRegistrar {
+            registered: HashMap::new(),
+            taken: vec![Chord { modifiers: MOD_ALT, vk: VK_SPACE }],
+        }
+    }
+
+    fn hotkey(chord: &str, command: &str, binding: bool) -> Hotkey {
+        Hotkey {
+            chord: chord.parse().unwrap(),
+            command: command.parse().unwrap(),
+            repeat: false,
+            binding: binding,
+        }
+    }
+
+    #[test]
+    fn conflicts_are_not_registered() {
+        let hotkeys = vec![
+            hotkey("Alt+Shift+F3", "mark 3", true),
+            hotkey("Ctrl+Alt+Q", "quit", false),
+            hotkey("Alt+Space", "show-popup", false),
+            hotkey("alt+shift+f3", "mark editor", false),
+            hotkey("Ctrl+Alt+Q", "script arrange", false),
+        ];
+
+        let mut registrar = registrar();
+        let mut table = BindingTable::new(1);
+        let report = table.add_all(&mut registrar, hotkeys);
+
+        assert_eq!(2, registrar.registered.len());
+        let outcomes: Vec<_> = report.entries.iter().map(|(_, outcome)| outcome.clone()).collect();
+        assert_eq!(vec![
+            Outcome::Registered,
+            Outcome::Registered,
+            Outcome::Failed(ERROR_HOTKEY_ALREADY_REGISTERED),
+            Outcome::Overridden("mark 3".to_string()),
+            Outcome::Conflict("quit".to_string()),
+        ], outcomes);
+
+        let failed: Vec<String> = report.failures().iter().map(|(hotkey, _)| hotkey.command.to_string()).collect();
+        assert_eq!(vec!["show-popup", "script arrange"], failed);
+        assert!(report.to_string().contains("Alt+Space"));
+        assert!(report.to_string().contains("taken by another program"));
+    }
+
+    #[test]
+    fn runtime_changes() {
+        let mut registrar = registrar();
+        let mut table = BindingTable::new(1);
+        let undo = table.add(&mut registrar, hotkey("Ctrl+Alt+Z", "undo", false)).unwrap();
+        let snap = table.add(&mut registrar, hotkey("Ctrl+Alt+Left", "snap left", false)).unwrap();
+        assert!(undo != snap);
+        assert_eq!(Some(&Command::Undo), table.command(undo));
+
+        // Replacing gives the chord a new id, and the old one goes nowhere
+        let tile = table.replace(&mut registrar, hotkey("Ctrl+Alt+Z", "tile spiral", true)).unwrap();
+        assert_eq!(None, table.command(undo));
+        assert_eq!(Some("tile spiral".to_string()), table.command(tile).map(|c| c.to_string()));
+        assert_eq!(2, registrar.registered.len());
+
+        // A chord that cannot be had leaves the table as it was
+        let popup = hotkey("Alt+Space", "show-popup", true);
+        assert_eq!(Err(Outcome::Failed(ERROR_HOTKEY_ALREADY_REGISTERED)), table.replace(&mut registrar, popup));
+        assert_eq!(2, registrar.registered.len());
+
+        let left = "Ctrl+Alt+Left".parse().unwrap();
+        assert_eq!(Some(Command::Snap("left".to_string())), table.remove(&mut registrar, left).map(|h| h.command));
+        assert_eq!(None, table.command(snap));
+        assert_eq!(None, table.remove(&mut registrar, left));
+
+        table.clear(&mut registrar);
+        assert!(registrar.registered.is_empty());
+        assert_eq!(None, table.command(tile));
+    }
+}
@@ -0,0 +1,297 @@
+use std::fmt;
+use std::str::FromStr;
+
+use winapi::minwindef::*;
+
+use constants::*;
+
+// Names for the virtual keys other than letters, digits, F1-F24 and
+// Num0-Num9, which are named by pattern. The first name of a key is the
+// one it is shown with. Mouse buttons are left out, they cannot be hotkeys.
+// https://msdn.microsoft.com/en-us/library/windows/desktop/dd375731(v=vs.85).aspx
+const KEY_NAMES: &'static [(UINT, &'static [&'static str])] = &[
+    (0x03, &["Cancel", "Break"]),
+    (0x08, &["Backspace", "Back"]),
+    (0x09, &["Tab"]),
+    (0x0C, &["Clear"]),
+    (0x0D, &["Enter", "Return"]),
+    (0x10, &["ShiftKey"]),
+    (0x11, &["CtrlKey"]),
+    (0x12, &["AltKey", "Menu"]),
+    (0x13, &["Pause"]),
+    (0x14, &["CapsLock", "Capital"]),
+    (0x15, &["Kana", "Hangul"]),
+    (0x16, &["ImeOn"]),
+    (0x17, &["Junja"]),
+    (0x18, &["Final"]),
+    (0x19, &["Kanji", "Hanja"]),
+    (0x1A, &["ImeOff"]),
+    (0x1B, &["Escape", "Esc"]),
+    (0x1C, &["Convert"]),
+    (0x1D, &["NonConvert"]),
+    (0x1E, &["ImeAccept"]),
+    (0x1F, &["ModeChange"]),
+    (0x20, &["Space"]),
+    (0x21, &["PageUp", "PgUp", "Prior"]),
+    (0x22, &["PageDown", "PgDn", "Next"]),
+    (0x23, &["End"]),
+    (0x24, &["Home"]),
+    (0x25, &["Left"]),
+    (0x26, &["Up"]),
+    (0x27, &["Right"]),
+    (0x28, &["Down"]),
+    (0x29, &["Select"]),
+    (0x2A, &["Print"]),
+    (0x2B, &["Execute"]),
+    (0x2C, &["PrintScreen", "PrtSc", "Snapshot"]),
+    (0x2D, &["Insert", "Ins"]),
+    (0x2E, &["Delete", "Del"]),
+    (0x2F, &["Help"]),
+    (0x5B, &["LWin"]),
+    (0x5C, &["RWin"]),
+    (0x5D, &["Apps", "ContextMenu"]),
+    (0x5F, &["Sleep"]),
+    (0x6A, &["NumMultiply", "Num*"]),
+    (0x6B, &["NumAdd"]),
+    (0x6C, &["NumSeparator"]),
+    (0x6D, &["NumSubtract", "Num-"]),
+    (0x6E, &["NumDecimal", "Num."]),
+    (0x6F, &["NumDivide", "Num/"]),
+    (0x90, &["NumLock"]),
+    (0x91, &["ScrollLock", "Scroll"]),
+    (0xA0, &["LShift"]),
+    (0xA1, &["RShift"]),
+    (0xA2, &["LCtrl", "LControl"]),
+    (0xA3, &["RCtrl", "RControl"]),
+    (0xA4, &["LAlt", "LMenu"]),
+    (0xA5, &["RAlt", "RMenu", "AltGr"]),
+    (0xA6, &["BrowserBack"]),
+    (0xA7, &["BrowserForward"]),
+    (0xA8, &["BrowserRefresh"]),
+    (0xA9, &["BrowserStop"]),
+    (0xAA, &["BrowserSearch"]),
+    (0xAB, &["BrowserFavorites"]),
+    (0xAC, &["BrowserHome"]),
+    (0xAD, &["VolumeMute"]),
+    (0xAE, &["VolumeDown"]),
+    (0xAF, &["VolumeUp"]),
+    (0xB0, &["MediaNext"]),
+    (0xB1, &["MediaPrevious"]),
+    (0xB2, &["MediaStop"]),
+    (0xB3, &["MediaPlayPause"]),
+    (0xB4, &["LaunchMail"]),
+    (0xB5, &["LaunchMedia"]),
+    (0xB6, &["LaunchApp1"]),
+    (0xB7, &["LaunchApp2"]),
+    // Punctuation, as on a US layout
+    (0xBA, &["Semicolon", ";"]),
+    (0xBB, &["Equals", "=", "Plus"]),
+    (0xBC, &["Comma", ","]),
+    (0xBD, &["Minus", "-"]),
+    (0xBE, &["Period", "."]),
+    (0xBF, &["Slash", "/"]),
+    (0xC0, &["Backtick", "`", "Tilde"]),
+    (0xDB, &["LeftBracket", "["]),
+    (0xDC, &["Backslash", "\\"]),
+    (0xDD, &["RightBracket", "]"]),
+    (0xDE, &["Quote", "'"]),
+    (0xDF, &["Oem8"]),
+    (0xE2, &["Oem102"]),
+    (0xE5, &["ProcessKey"]),
+    (0xE7, &["Packet"]),
+    (0xF6, &["Attn"]),
+    (0xF7, &["CrSel"]),
+    (0xF8, &["ExSel"]),
+    (0xF9, &["EraseEof"]),
+    (0xFA, &["Play"]),
+    (0xFB, &["Zoom"]),
+    (0xFD, &["Pa1"]),
+    (0xFE, &["OemClear"]),
+];
+
+/// Modifiers in the order chords are written with.
+const MODIFIER_NAMES: &'static [(UINT, &'static [&'static str])] = &[
+    (MOD_CONTROL, &["Ctrl", "Control"]),
+    (MOD_ALT, &["Alt"]),
+    (MOD_SHIFT, &["Shift"]),
+    (MOD_WIN, &["Win", "Windows", "Super"]),
+];
+
+/// Parses a key name: a letter, a digit, `F1`-`F24`, `Num0`-`Num9`, a name
+/// such as `PageUp` or `;`, or a virtual key code such as `0xE2`. Names are
+/// case insensitive.
+pub fn parse_key(name: &str) -> Option<UINT> {
+    let name = name.trim();
+    let upper = name.to_uppercase();
+    let mut chars = upper.chars();
+
+    if let (Some(c @ 'A'..='Z'), None) | (Some(c @ '0'..='9'), None) = (chars.next(), chars.next()) {
+        return Some(c as UINT);
+    }
+
+    let numbered = |prefix: &str, first: UINT, last: UINT, base: UINT| {
+        if !upper.starts_with(prefix) {
+            return None;
+        }
+
+        match upper[prefix.len()..].parse::<UINT>() {
+            Ok(n) if n >= base && n - base <= last - first => Some(first + n - base),
+            _ => None
+        }
+    };
+
+    let code = || {
+        if !upper.starts_with("0X") {
+            return None;
+        }
+
+        match UINT::from_str_radix(&upper[2..], 16) {
+            Ok(vk) if vk > 0 && vk < 0xFF => Some(vk),
+            _ => None
+        }
+    };
+
+    numbered("F", VK_F1, VK_F24, 1)
+        .or_else(|| numbered("NUM", VK_NUMPAD0, VK_NUMPAD9, 0))
+        .or_else(|| lookup(KEY_NAMES, name))
+        .or_else(code)
+}
+
+/// Inverse of `parse_key`, giving the first name of a key.
+pub fn key_name(vk: UINT) -> Option<String> {
+    match vk {
+        VK_A..=VK_Z | VK_0..=VK_9 => Some(((vk as u8) as char).to_string()),
+        VK_F1..=VK_F24 => Some(format!("F{}", vk - VK_F1 + 1)),
+        VK_NUMPAD0..=VK_NUMPAD9 => Some(format!("Num{}", vk - VK_NUMPAD0)),
+        _ => KEY_NAMES.iter().find(|&&(key, _)| key == vk).map(|&(_, names)| names[0].to_string())
+    }
+}
+
+fn lookup(table: &[(UINT, &[&str])], name: &str) -> Option<UINT> {
+    table.iter()
+        .find(|&&(_, names)| names.iter().any(|n| n.eq_ignore_ascii_case(name)))
+        .map(|&(value, _)| value)
+}
+
+/// A key pressed with modifiers, written like `Ctrl+Alt+Left` or
+/// `Alt+Shift+F3`. `modifiers` are the `MOD_*` flags of `RegisterHotKey`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Chord {
+    pub modifiers: UINT,
+    pub vk: UINT,
+}
+
+impl FromStr for Chord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let (key, modifier_names) = match parts.split_last() {
+            Some((key, modifier_names)) if !key.is_empty() => (key, modifier_names),
+            _ => return Err(format!("expected a key after the modifiers in '{}'", s))
+        };
+
+        let mut modifiers = 0;
+        for name in modifier_names {
+            let modifier = lookup(MODIFIER_NAMES, name).ok_or_else(|| format!("unknown modifier '{}'", name))?;
+            if modifiers & modifier != 0 {
+                return Err(format!("{} is given twice", name));
+            }
+
+            modifiers |= modifier;
+        }
+
+        match parse_key(key) {
+            Some(vk) => Ok(Chord {
+                modifiers: modifiers,
+                vk: vk,
+            }),
+            None => Err(format!("unknown key '{}'", key))
+        }
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &(modifier, names) in MODIFIER_NAMES {
+            if self.modifiers & modifier != 0 {
+                write!(f, "{}+", names[0])?;
+            }
+        }
+
+        match key_name(self.vk) {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "0x{:02X}", self.vk)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use constants::*;
+
+    use super::{key_name, parse_key, Chord};
+
+    #[test]
+    fn every_key_round_trips() {
+        for vk in 1..0xFF {
+            if let Some(name) = key_name(vk) {
+                assert_eq!(Some(vk), parse_key(&name), "{}", name);
+                assert_eq!(Some(vk), parse_key(&name.to_lowercase()), "{}", name);
+            }
+        }
+
+        assert_eq!(Some("PageUp".to_string()), key_name(VK_PRIOR));
+        assert_eq!(Some("Num7".to_string()), key_name(VK_NUMPAD0 + 7));
+        assert_eq!(None, key_name(0x01));
+    }
+
+    #[test]
+    fn key_names() {
+        assert_eq!(Some(VK_A), parse_key("a"));
+        assert_eq!(Some(VK_F1 + 11), parse_key(" f12 "));
+        assert_eq!(Some(VK_PRIOR), parse_key("PgUp"));
+        assert_eq!(Some(VK_ESCAPE), parse_key("esc"));
+        assert_eq!(Some(0xBA), parse_key(";"));
+        assert_eq!(Some(0xDC), parse_key("\\"));
+        assert_eq!(Some(0x6B), parse_key("numadd"));
+        assert_eq!(Some(0xE2), parse_key("0xe2"));
+
+        assert_eq!(None, parse_key(""));
+        assert_eq!(None, parse_key("F25"));
+        assert_eq!(None, parse_key("Num10"));
+        assert_eq!(None, parse_key("0x1FF"));
+        assert_eq!(None, parse_key("Hyper"));
+    }
+
+    #[test]
+    fn chords_round_trip() {
+        let chords = vec![
+            ("Ctrl+Alt+Q", MOD_CONTROL | MOD_ALT, VK_Q),
+            ("Alt+Shift+F3", MOD_ALT | MOD_SHIFT, VK_F1 + 2),
+            ("Ctrl+Alt+Shift+Win+Left", MOD_CONTROL | MOD_ALT | MOD_SHIFT | MOD_WIN, VK_LEFT),
+            ("Space", 0, VK_SPACE),
+            ("Win+Equals", MOD_WIN, 0xBB),
+            ("Ctrl+0xE8", MOD_CONTROL, 0xE8),
+        ];
+
+        for (text, modifiers, vk) in chords {
+            let chord = Chord { modifiers: modifiers, vk: vk };
+            assert_eq!(Ok(chord), text.parse());
+            assert_eq!(text, chord.to_string());
+        }
+
+        // Any order, case and spacing, shown the usual way
+        let chord: Chord = "shift + control+alt+pgdn".parse().unwrap();
+        assert_eq!("Ctrl+Alt+Shift+PageDown", chord.to_string());
+    }
+
+    #[test]
+    fn chord_errors() {
+        assert!("".parse::<Chord>().is_err());
+        assert!("Ctrl+".parse::<Chord>().is_err());
+        assert!("Ctrl+Ctrl+A".parse::<Chord>().is_err());
+        assert!("Hyper+A".parse::<Chord>().is_err());
+        assert!("Ctrl+Alt+Nope".parse::<Chord>().is_err());
+    }
+}
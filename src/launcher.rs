@@ -0,0 +1,86 @@
+use std::io;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use user32;
+use winapi::*;
+
+use reactor::{Event, Sender};
+use settings::LaunchCommand;
+use utils::api_wrappers;
+use window_tracking::WindowMatch;
+use windows::main::AppMsg;
+
+const POLL_INTERVAL_MS: u64 = 100;
+
+/// Starts `command` and waits in the background for its first window,
+/// which is sent to `events` as `AppMsg::WindowLaunched`.
+///
+/// The window is recognised by `rule` if there is one, otherwise by the
+/// process id of the started program. Programs that hand off to another
+/// process (launcher stubs, single-instance apps) need an identity rule.
+pub fn run(events: &Sender<Event>, slot: &str, command: &LaunchCommand, rule: Option<&WindowMatch>) -> io::Result<()> {
+    let mut builder = process::Command::new(&command.program);
+    builder.args(&command.args);
+
+    if let Some(ref cwd) = command.cwd {
+        builder.current_dir(cwd);
+    }
+
+    for &(ref key, ref value) in &command.env {
+        builder.env(key, value);
+    }
+
+    let child = builder.spawn()?;
+    let pid = child.id();
+
+    let events = events.clone();
+    let slot = slot.to_string();
+    let rule = rule.cloned();
+    let timeout = command.timeout;
+
+    thread::spawn(move || {
+        match wait_for_window(pid, rule.as_ref(), timeout) {
+            Some(hwnd) => {
+                events.send(AppMsg::WindowLaunched(slot, hwnd));
+            },
+            None => {
+                println!("Gave up waiting for a window from process {} after {:?}", pid, timeout);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn wait_for_window(pid: DWORD, rule: Option<&WindowMatch>, timeout: Duration) -> Option<usize> {
+    let started = Instant::now();
+
+    while started.elapsed() < timeout {
+        let mut found = None;
+
+        let _ = api_wrappers::enum_windows(|hwnd| {
+            let visible = unsafe { user32::IsWindowVisible(hwnd) != 0 };
+            let is_match = match rule {
+                Some(rule) => rule.is_match(hwnd),
+                None => api_wrappers::get_window_process_id(hwnd) == pid,
+            };
+
+            if visible && is_match {
+                found = Some(hwnd as usize);
+                return FALSE;
+            }
+
+            TRUE
+        });
+
+        if found.is_some() {
+            return found;
+        }
+
+        thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+    }
+
+    None
+}
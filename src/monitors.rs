@@ -0,0 +1,403 @@
+use gdi32;
+use kernel32;
+use user32;
+use winapi::*;
+
+use utils;
+use utils::Win32Result;
+use window_system::WindowSystem;
+use window_tracking::Placement;
+use windows::Bounds;
+
+/// Pixels per inch at 100% scaling
+pub const DEFAULT_DPI: u32 = 96;
+
+// Not in winapi 0.2
+const DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2: isize = -4;
+
+/// `GetDpiForMonitor` from shcore.dll
+type GetDpiForMonitorFn = unsafe extern "system" fn(HMONITOR, MONITOR_DPI_TYPE, *mut UINT, *mut UINT) -> HRESULT;
+/// `SetProcessDpiAwarenessContext` from user32.dll, Windows 10 1703
+type SetProcessDpiAwarenessContextFn = unsafe extern "system" fn(HANDLE) -> BOOL;
+/// `SetProcessDpiAwareness` from shcore.dll, Windows 8.1
+type SetProcessDpiAwarenessFn = unsafe extern "system" fn(PROCESS_DPI_AWARENESS) -> HRESULT;
+/// `SetProcessDPIAware` from user32.dll, Vista
+type SetProcessDpiAwareFn = unsafe extern "system" fn() -> BOOL;
+
+// Not in user32-sys 0.1
+#[link(name = "user32")]
+extern "system" {
+    fn MonitorFromRect(rect: *const RECT, flags: DWORD) -> HMONITOR;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Monitor {
+    /// Display device name, e.g. `\\.\DISPLAY1`. Stays the same while
+    /// the monitor is plugged into the same port.
+    pub device: String,
+    pub bounds: Bounds,
+    /// Bounds minus the taskbar and docked toolbars
+    pub work_area: Bounds,
+    /// Pixels per inch, `DEFAULT_DPI` times the display scaling
+    pub dpi: u32,
+    pub primary: bool,
+}
+
+/// Which monitor the switcher opens on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Follow {
+    /// The monitor under the mouse
+    Cursor,
+    /// The monitor of the foreground window
+    Focus,
+}
+
+impl Default for Follow {
+    fn default() -> Follow {
+        Follow::Cursor
+    }
+}
+
+/// All monitors, ordered left to right then top to bottom.
+/// Settings number monitors from 1 in this order.
+pub fn enumerate() -> Vec<Monitor> {
+    unsafe extern "system" fn callback(hmonitor: HMONITOR, _: HDC, _: *mut RECT, lparam: LPARAM) -> BOOL {
+        let monitors = &mut *(lparam as *mut Vec<Monitor>);
+
+        if let Some(monitor) = get_monitor_info(hmonitor) {
+            monitors.push(monitor);
+        }
+
+        TRUE
+    }
+
+    let mut monitors: Vec<Monitor> = Vec::new();
+    unsafe {
+        user32::EnumDisplayMonitors(
+            0 as HDC,
+            ::std::ptr::null(),
+            Some(callback),
+            &mut monitors as *mut _ as LPARAM);
+    }
+
+    monitors.sort_by_key(|m| (m.bounds.0, m.bounds.1));
+    monitors
+}
+
+fn get_monitor_info(hmonitor: HMONITOR) -> Option<Monitor> {
+    let mut info: MONITORINFOEXW = unsafe { ::std::mem::zeroed() };
+    info.cbSize = ::std::mem::size_of::<MONITORINFOEXW>() as DWORD;
+
+    let result = unsafe {
+        user32::GetMonitorInfoW(hmonitor, &mut info as *mut MONITORINFOEXW as *mut MONITORINFO)
+    };
+    if result == FALSE {
+        return None;
+    }
+
+    let device_len = info.szDevice.iter().position(|&c| c == 0).unwrap_or(info.szDevice.len());
+
+    let to_bounds = |r: &RECT| (r.left, r.top, r.right, r.bottom);
+
+    Some(Monitor {
+        device: String::from_utf16_lossy(&info.szDevice[..device_len]),
+        bounds: to_bounds(&info.rcMonitor),
+        work_area: to_bounds(&info.rcWork),
+        dpi: get_dpi(hmonitor, info.szDevice.as_ptr()),
+        primary: info.dwFlags & MONITORINFOF_PRIMARY != 0,
+    })
+}
+
+/// Asks Windows for real pixels and each monitor's own DPI, instead of
+/// coordinates scaled as if every monitor ran at 96 DPI. Tries the newest
+/// API the system has, down to system DPI awareness before Windows 8.1.
+/// Must run before the first window is created.
+pub fn declare_dpi_awareness() {
+    unsafe {
+        if let Some(address) = find_function("user32.dll", b"SetProcessDpiAwarenessContext\0") {
+            let set_context: SetProcessDpiAwarenessContextFn = ::std::mem::transmute(address);
+            if set_context(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2 as HANDLE) != FALSE {
+                return;
+            }
+        }
+
+        if let Some(address) = find_function("shcore.dll", b"SetProcessDpiAwareness\0") {
+            let set_awareness: SetProcessDpiAwarenessFn = ::std::mem::transmute(address);
+            // Access is denied when a manifest has already set it
+            match set_awareness(Process_Per_Monitor_DPI_Aware) {
+                S_OK | E_ACCESSDENIED => return,
+                _ => {}
+            }
+        }
+
+        if let Some(address) = find_function("user32.dll", b"SetProcessDPIAware\0") {
+            let set_aware: SetProcessDpiAwareFn = ::std::mem::transmute(address);
+            set_aware();
+        }
+    }
+}
+
+/// Looks up a function that older Windows versions lack. The library
+/// stays loaded, as the process keeps using what it sets up.
+///
+/// `name` is null-terminated
+unsafe fn find_function(library: &str, name: &[u8]) -> Option<FARPROC> {
+    let module = kernel32::LoadLibraryW(utils::to_wide_chars(library).as_ptr());
+    if module.is_null() {
+        return None;
+    }
+
+    let address = kernel32::GetProcAddress(module, name.as_ptr() as LPCSTR);
+    if address.is_null() { None } else { Some(address) }
+}
+
+/// The DPI Windows scales `hmonitor` by. `GetDpiForMonitor` needs Windows
+/// 8.1, so it is looked up at runtime, and older systems get the system DPI
+/// for every monitor. Both only differ from 96 once `declare_dpi_awareness`
+/// has run.
+///
+/// `device` is a null-terminated display device name
+fn get_dpi(hmonitor: HMONITOR, device: LPCWSTR) -> u32 {
+    get_monitor_dpi(hmonitor).unwrap_or_else(|| get_system_dpi(device))
+}
+
+fn get_monitor_dpi(hmonitor: HMONITOR) -> Option<u32> {
+    unsafe {
+        let shcore = kernel32::LoadLibraryW(utils::to_wide_chars("shcore.dll").as_ptr());
+        if shcore.is_null() {
+            return None;
+        }
+
+        let mut dpi = None;
+        let address = kernel32::GetProcAddress(shcore, b"GetDpiForMonitor\0".as_ptr() as LPCSTR);
+
+        if !address.is_null() {
+            let get_dpi_for_monitor: GetDpiForMonitorFn = ::std::mem::transmute(address);
+            let (mut x, mut y) = (0, 0);
+
+            if get_dpi_for_monitor(hmonitor, MDT_EFFECTIVE_DPI, &mut x, &mut y) == S_OK && x > 0 {
+                dpi = Some(x);
+            }
+        }
+
+        kernel32::FreeLibrary(shcore);
+        dpi
+    }
+}
+
+/// Only tells the DPI of the primary monitor, which all others share
+/// before Windows 8.1
+fn get_system_dpi(device: LPCWSTR) -> u32 {
+    unsafe {
+        let hdc = gdi32::CreateDCW(device, device, 0 as LPCWSTR, ::std::ptr::null());
+        if hdc.is_null() {
+            return DEFAULT_DPI;
+        }
+
+        let dpi = gdi32::GetDeviceCaps(hdc, LOGPIXELSX);
+        gdi32::DeleteDC(hdc);
+
+        if dpi > 0 { dpi as u32 } else { DEFAULT_DPI }
+    }
+}
+
+pub fn cursor_position() -> Option<(i32, i32)> {
+    let mut point = POINT { x: 0, y: 0 };
+
+    match unsafe { user32::GetCursorPos(&mut point) } {
+        FALSE => None,
+        _ => Some((point.x, point.y))
+    }
+}
+
+/// The monitor to show UI on, falling back to the other kind of focus and
+/// then to the primary monitor.
+pub fn active<S: WindowSystem>(system: &S, follow: Follow) -> Option<Monitor> {
+    let monitors = system.monitors();
+
+    let cursor = || system.cursor_position()
+        .and_then(|(x, y)| containing(&monitors, (x, y, x + 1, y + 1)));
+    let focus = || system.foreground_window()
+        .and_then(|window| containing(&monitors, system.window_bounds(window.hwnd())));
+
+    let index = match follow {
+        Follow::Cursor => cursor().or_else(focus),
+        Follow::Focus => focus().or_else(cursor),
+    };
+
+    index.and_then(|i| monitors.get(i))
+         .or_else(|| monitors.iter().find(|m| m.primary))
+         .cloned()
+}
+
+/// Moves a window `offset` monitors along, in monitor order and wrapping
+/// around, keeping its position and size relative to the work area.
+/// Maximized and minimized windows keep their state.
+pub fn move_window<S: WindowSystem>(system: &mut S, hwnd: HWND, offset: isize) -> Win32Result<()> {
+    let monitors = system.monitors();
+    let placement = match system.placement(hwnd) {
+        Some(placement) => placement,
+        None => return Ok(())
+    };
+
+    let from = match containing(&monitors, placement.bounds) {
+        Some(index) => index,
+        None => return Ok(())
+    };
+    let to = (from as isize + offset).rem_euclid(monitors.len() as isize) as usize;
+
+    if to == from {
+        return Ok(());
+    }
+
+    system.set_placement(hwnd, &Placement {
+        state: placement.state,
+        bounds: scale(placement.bounds, monitors[from].work_area, monitors[to].work_area),
+    })
+}
+
+/// Window placements are in workspace coordinates, which start at the top
+/// left of the work area instead of the monitor. Adding this offset to them
+/// gives screen coordinates.
+pub fn workspace_offset(bounds: Bounds) -> (i32, i32) {
+    let (l, t, r, b) = bounds;
+    let rect = RECT { left: l, top: t, right: r, bottom: b };
+
+    let hmonitor = unsafe { MonitorFromRect(&rect, MONITOR_DEFAULTTONEAREST) };
+
+    match get_monitor_info(hmonitor) {
+        Some(monitor) => (monitor.work_area.0 - monitor.bounds.0, monitor.work_area.1 - monitor.bounds.1),
+        None => (0, 0)
+    }
+}
+
+/// Index of the monitor holding the center of `bounds`, or the nearest one.
+pub fn containing(monitors: &[Monitor], bounds: Bounds) -> Option<usize> {
+    let (l, t, r, b) = bounds;
+    let (x, y) = ((l + r) / 2, (t + b) / 2);
+
+    let distance = |m: &Monitor| {
+        let (ml, mt, mr, mb) = m.bounds;
+        let dx = if x < ml { ml - x } else if x >= mr { x - mr + 1 } else { 0 };
+        let dy = if y < mt { mt - y } else if y >= mb { y - mb + 1 } else { 0 };
+
+        dx as i64 * dx as i64 + dy as i64 * dy as i64
+    };
+
+    (0..monitors.len()).min_by_key(|&i| distance(&monitors[i]))
+}
+
+/// Moves `bounds` from one work area to another, keeping its offset from the
+/// top-left corner and its size, but keeping it inside the target area.
+pub fn translate(bounds: Bounds, from: Bounds, to: Bounds) -> Bounds {
+    let (l, t, r, b) = bounds;
+    let (w, h) = (r - l, b - t);
+    let (to_l, to_t, to_r, to_b) = to;
+
+    let w = w.min(to_r - to_l);
+    let h = h.min(to_b - to_t);
+    let x = (to_l + l - from.0).max(to_l).min(to_r - w);
+    let y = (to_t + t - from.1).max(to_t).min(to_b - h);
+
+    (x, y, x + w, y + h)
+}
+
+/// Maps `bounds` from one work area to another of a different size,
+/// scaling its position and size in proportion.
+pub fn scale(bounds: Bounds, from: Bounds, to: Bounds) -> Bounds {
+    let map = |v: i32, from_start: i32, from_len: i32, to_start: i32, to_len: i32| {
+        if from_len == 0 {
+            return to_start;
+        }
+
+        to_start + ((v - from_start) as i64 * to_len as i64 / from_len as i64) as i32
+    };
+
+    let (fw, fh) = (from.2 - from.0, from.3 - from.1);
+    let (tw, th) = (to.2 - to.0, to.3 - to.1);
+
+    (
+        map(bounds.0, from.0, fw, to.0, tw),
+        map(bounds.1, from.1, fh, to.1, th),
+        map(bounds.2, from.0, fw, to.0, tw),
+        map(bounds.3, from.1, fh, to.1, th),
+    )
+}
+
+/// Identifies a monitor arrangement by the size and position of each
+/// monitor, e.g. `1920x1080+0+0,2560x1440+1920+0`.
+pub fn fingerprint(monitors: &[Monitor]) -> String {
+    monitors.iter()
+        .map(|m| {
+            let (l, t, r, b) = m.bounds;
+            format!("{}x{}{:+}{:+}", r - l, b - t, l, t)
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use window_system::WindowSystem;
+    use window_system::fake::FakeWindowSystem;
+    use window_tracking::ShowState;
+    use super::{active, move_window, Follow, DEFAULT_DPI};
+
+    fn two_monitors() -> FakeWindowSystem {
+        let mut system = FakeWindowSystem::new();
+        system.add_monitor((1920, 0, 4480, 1440), (1920, 0, 4480, 1400));
+        system
+    }
+
+    #[test]
+    fn moves_keep_relative_position() {
+        let mut system = two_monitors();
+        let hwnd = system.open("editor").hwnd();
+        system.window_mut(hwnd).bounds = (960, 0, 1920, 520);
+
+        move_window(&mut system, hwnd, 1).unwrap();
+        assert_eq!((3200, 0, 4480, 700), system.window(hwnd).bounds);
+
+        // Wraps around, and stays maximized
+        system.window_mut(hwnd).maximized = true;
+        move_window(&mut system, hwnd, 1).unwrap();
+        assert_eq!((960, 0, 1920, 520), system.window(hwnd).bounds);
+        assert_eq!(Some(ShowState::Maximized), system.placement(hwnd).map(|p| p.state));
+    }
+
+    #[test]
+    fn active_monitor() {
+        let mut system = two_monitors();
+        let hwnd = system.open("editor").hwnd();
+        system.window_mut(hwnd).bounds = (2000, 100, 2800, 700);
+
+        let device = |system: &FakeWindowSystem, follow| active(system, follow).map(|m| m.device);
+
+        // Without a cursor position the focus decides
+        assert_eq!(Some("\\\\.\\DISPLAY2".to_string()), device(&system, Follow::Cursor));
+
+        system.cursor = Some((10, 10));
+        assert_eq!(Some("\\\\.\\DISPLAY1".to_string()), device(&system, Follow::Cursor));
+        assert_eq!(Some("\\\\.\\DISPLAY2".to_string()), device(&system, Follow::Focus));
+    }
+
+    #[test]
+    fn monitors_keep_their_own_dpi() {
+        let mut system = two_monitors();
+        system.set_dpi(1, 144);
+        let hwnd = system.open("editor").hwnd();
+        system.window_mut(hwnd).bounds = (960, 0, 1920, 520);
+
+        let dpi = |system: &FakeWindowSystem, follow| active(system, follow).map(|m| m.dpi);
+
+        system.cursor = Some((10, 10));
+        assert_eq!(Some(DEFAULT_DPI), dpi(&system, Follow::Cursor));
+        system.cursor = Some((3000, 10));
+        assert_eq!(Some(144), dpi(&system, Follow::Cursor));
+
+        // Bounds are in physical pixels, so moves scale by work area alone
+        move_window(&mut system, hwnd, 1).unwrap();
+        assert_eq!((3200, 0, 4480, 700), system.window(hwnd).bounds);
+        assert_eq!(Some(144), dpi(&system, Follow::Focus));
+    }
+}
@@ -0,0 +1,222 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::mpsc;
+
+use kernel32;
+use user32;
+use winapi::*;
+
+use commands::Command;
+use control::{Response, Topic};
+use control::json::Value;
+use utils::Win32Result;
+use windows::main::AppMsg;
+use windows::popup::PopupMsg;
+
+/// Posted to the UI thread when an event is queued, to get it out of
+/// `GetMessageW`. It carries nothing, the events are in the queue.
+pub const WM_APP_WAKE: UINT = WM_APP + 2;
+
+const CTRL_C_EVENT: DWORD = 0;
+const CTRL_BREAK_EVENT: DWORD = 1;
+
+// Where Ctrl+C sends its quit command, set once by `quit_on_ctrl_c`
+static CTRL_C_EVENTS: AtomicPtr<Sender<Event>> = AtomicPtr::new(::std::ptr::null_mut());
+
+/// Everything the main loop reacts to besides window messages.
+pub enum Event {
+    App(AppMsg),
+    Popup(PopupMsg),
+    /// A command from another thread, for the foreground window
+    Command(Command),
+    /// A command from the control endpoint, answered on the channel
+    Control(Command, mpsc::Sender<Response>),
+    /// A control connection streaming events, see `control::Subscribers`
+    Subscribe(Vec<Topic>, mpsc::Sender<Value>),
+}
+
+impl From<AppMsg> for Event {
+    fn from(msg: AppMsg) -> Self {
+        Event::App(msg)
+    }
+}
+
+impl From<PopupMsg> for Event {
+    fn from(msg: PopupMsg) -> Self {
+        Event::Popup(msg)
+    }
+}
+
+impl From<Command> for Event {
+    fn from(command: Command) -> Self {
+        Event::Command(command)
+    }
+}
+
+/// Gets the thread running the reactor's loop to look at its queue.
+pub trait Wake: Send + Sync {
+    fn wake(&self);
+}
+
+/// Wakes a thread blocked in `GetMessageW` with a thread message.
+pub struct ThreadWaker {
+    thread_id: DWORD,
+}
+
+impl ThreadWaker {
+    pub fn current() -> Self {
+        ThreadWaker {
+            thread_id: unsafe { kernel32::GetCurrentThreadId() },
+        }
+    }
+}
+
+impl Wake for ThreadWaker {
+    fn wake(&self) {
+        unsafe { user32::PostThreadMessageW(self.thread_id, WM_APP_WAKE, 0, 0); }
+    }
+}
+
+/// The queue between producers on any thread and the UI thread.
+///
+/// Sending wakes the UI thread, so events are handled as they come rather
+/// than with the next unrelated window message.
+pub struct Reactor<T> {
+    tx: Sender<T>,
+    rx: mpsc::Receiver<T>,
+}
+
+pub struct Sender<T> {
+    tx: mpsc::Sender<T>,
+    waker: Arc<dyn Wake>,
+}
+
+impl<T> Reactor<T> {
+    pub fn new<W: Wake + 'static>(waker: W) -> Self {
+        let (tx, rx) = mpsc::channel();
+
+        Reactor {
+            tx: Sender {
+                tx: tx,
+                waker: Arc::new(waker),
+            },
+            rx: rx,
+        }
+    }
+
+    pub fn sender(&self) -> Sender<T> {
+        self.tx.clone()
+    }
+
+    /// The next queued event, without blocking.
+    pub fn poll(&self) -> Option<T> {
+        self.rx.try_recv().ok()
+    }
+}
+
+impl<T> Sender<T> {
+    /// Queues an event and wakes the UI thread. Returns false once the
+    /// reactor is gone.
+    pub fn send<M: Into<T>>(&self, msg: M) -> bool {
+        if self.tx.send(msg.into()).is_err() {
+            return false;
+        }
+
+        self.waker.wake();
+        true
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Sender {
+            tx: self.tx.clone(),
+            waker: self.waker.clone(),
+        }
+    }
+}
+
+/// Makes Ctrl+C in the console send `Command::Quit` to `events`, so that
+/// WinMan leaves through the main loop's cleanup instead of being killed.
+pub fn quit_on_ctrl_c(events: Sender<Event>) -> Win32Result<()> {
+    let events = Box::into_raw(Box::new(events));
+
+    // The handler may be running already, so an old sender is leaked
+    CTRL_C_EVENTS.store(events, Ordering::SeqCst);
+
+    unsafe {
+        match kernel32::SetConsoleCtrlHandler(Some(ctrl_handler), TRUE) {
+            FALSE => Err(kernel32::GetLastError()),
+            _ => Ok(())
+        }
+    }
+}
+
+unsafe extern "system" fn ctrl_handler(ctrl_type: DWORD) -> BOOL {
+    let events = CTRL_C_EVENTS.load(Ordering::SeqCst);
+
+    match ctrl_type {
+        CTRL_C_EVENT | CTRL_BREAK_EVENT if !events.is_null() => {
+            println!("Quitting on Ctrl+C");
+            (*events).send(Command::Quit);
+            TRUE
+        },
+        _ => FALSE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    use super::{Reactor, Wake};
+
+    struct CountingWaker(Arc<AtomicUsize>);
+
+    impl Wake for CountingWaker {
+        fn wake(&self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn producers_on_other_threads_wake_the_loop() {
+        let wakes = Arc::new(AtomicUsize::new(0));
+        let reactor: Reactor<u32> = Reactor::new(CountingWaker(wakes.clone()));
+
+        let producers: Vec<_> = (0..4)
+            .map(|n| {
+                let sender = reactor.sender();
+                thread::spawn(move || {
+                    for i in 0..10u32 {
+                        assert!(sender.send(n * 10 + i));
+                    }
+                })
+            })
+            .collect();
+
+        for producer in producers {
+            producer.join().unwrap();
+        }
+
+        let mut events: Vec<u32> = Vec::new();
+        while let Some(event) = reactor.poll() {
+            events.push(event);
+        }
+        events.sort();
+
+        assert_eq!((0..40).collect::<Vec<u32>>(), events);
+        assert_eq!(40, wakes.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn senders_outlive_the_reactor() {
+        let reactor: Reactor<u32> = Reactor::new(CountingWaker(Arc::new(AtomicUsize::new(0))));
+        let sender = reactor.sender();
+
+        drop(reactor);
+        assert!(!sender.send(1u32));
+    }
+}
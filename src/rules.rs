@@ -0,0 +1,306 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::process;
+
+use winapi::windef::*;
+
+use monitors;
+use window_filter::WindowFilter;
+use window_registry::WindowEvent;
+use window_system::WindowSystem;
+use window_tracking::{Config, Window, WindowMatch};
+
+/// What a rule does to a matching window. Actions run in order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    AssignSlot(String),
+    /// Monitors are numbered from 1, left to right
+    MoveToMonitor(usize),
+    /// Width and height, keeping the top-left corner
+    Resize(i32, i32),
+    /// Offset from the top-left of the work area of the window's monitor
+    MoveTo(i32, i32),
+    AlwaysOnTop(bool),
+    Minimize,
+    /// Runs through `cmd /C`, with the window handle in WINMAN_HWND
+    Run(String),
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Action::AssignSlot(ref slot) => write!(f, "assign to slot {}", slot),
+            Action::MoveToMonitor(n) => write!(f, "move to monitor {}", n),
+            Action::Resize(w, h) => write!(f, "resize to {}x{}", w, h),
+            Action::MoveTo(x, y) => write!(f, "move to {},{}", x, y),
+            Action::AlwaysOnTop(true) => write!(f, "set always on top"),
+            Action::AlwaysOnTop(false) => write!(f, "clear always on top"),
+            Action::Minimize => write!(f, "minimize"),
+            Action::Run(ref command) => write!(f, "run `{}`", command),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Rule {
+    pub name: String,
+    pub matcher: WindowMatch,
+    pub actions: Vec<Action>,
+}
+
+/// A rule that newly matched a window and should be applied to it.
+#[derive(Debug, PartialEq)]
+pub struct Firing {
+    pub hwnd: HWND,
+    pub rule: String,
+    pub actions: Vec<Action>,
+}
+
+/// Runs rules against windows as they appear or change title.
+///
+/// A rule fires once when a window starts matching it. If a title change
+/// makes the window stop matching, the rule may fire again on a later match.
+/// Only windows the switcher would list are considered, so splash screens
+/// and tool windows do not trigger rules.
+pub struct RuleEngine {
+    rules: Vec<Rule>,
+    /// (rule index, window) pairs currently matching
+    applied: HashSet<(usize, HWND)>,
+    pub dry_run: bool,
+}
+
+impl RuleEngine {
+    pub fn new(rules: Vec<Rule>, dry_run: bool) -> Self {
+        RuleEngine {
+            rules: rules,
+            applied: HashSet::new(),
+            dry_run: dry_run,
+        }
+    }
+
+    /// Rules that fire because of `event`, in the order they were defined.
+    pub fn handle(&mut self, filter: &WindowFilter, event: &WindowEvent) -> Vec<Firing> {
+        let info = match *event {
+            WindowEvent::Created(ref info)
+            | WindowEvent::Shown(ref info)
+            | WindowEvent::TitleChanged(ref info) => info,
+            WindowEvent::Destroyed(hwnd) => {
+                self.applied.retain(|&(_, h)| h != hwnd);
+                return Vec::new();
+            },
+            WindowEvent::Hidden(_) | WindowEvent::Focused(_) => return Vec::new()
+        };
+
+        if !filter.accepts(info) {
+            return Vec::new();
+        }
+
+        let hwnd = info.hwnd;
+        let mut firings = Vec::new();
+
+        for (index, rule) in self.rules.iter().enumerate() {
+            if !rule.matcher.matches(info) {
+                self.applied.remove(&(index, hwnd));
+                continue;
+            }
+
+            if self.applied.insert((index, hwnd)) {
+                firings.push(Firing {
+                    hwnd: hwnd,
+                    rule: rule.name.clone(),
+                    actions: rule.actions.clone(),
+                });
+            }
+        }
+
+        firings
+    }
+
+    /// Applies a firing, or only logs it in dry-run mode.
+    pub fn execute<S: WindowSystem>(&self, system: &mut S, config: &mut Config, firing: &Firing) {
+        for action in &firing.actions {
+            if self.dry_run {
+                println!("[dry run] Rule '{}' would {} for window {:?}", firing.rule, action, firing.hwnd);
+                continue;
+            }
+
+            println!("Rule '{}': {} for window {:?}", firing.rule, action, firing.hwnd);
+
+            if let Err(e) = apply(system, config, firing.hwnd, action) {
+                println!("Rule '{}' could not {}: {}", firing.rule, action, e);
+            }
+        }
+    }
+}
+
+fn apply<S: WindowSystem>(system: &mut S, config: &mut Config, hwnd: HWND, action: &Action) -> Result<(), String> {
+    let monitors = system.monitors();
+    let bounds = system.window_bounds(hwnd);
+    let (l, t, r, b) = bounds;
+
+    let new_bounds = match *action {
+        Action::AssignSlot(ref slot) => {
+            let title = system.window_info(hwnd).map(|info| info.title).unwrap_or_default();
+            config.track_window(slot, Window::new(hwnd, title));
+            None
+        },
+
+        Action::MoveToMonitor(n) => {
+            let target = match n.checked_sub(1).and_then(|i| monitors.get(i)) {
+                Some(target) => target,
+                None => return Err(format!("there is no monitor {}", n))
+            };
+            let current = monitors::containing(&monitors, bounds)
+                .map(|i| monitors[i].work_area)
+                .unwrap_or(target.work_area);
+
+            Some(monitors::translate(bounds, current, target.work_area))
+        },
+
+        Action::Resize(w, h) => Some((l, t, l + w, t + h)),
+
+        Action::MoveTo(x, y) => {
+            let (wl, wt, _, _) = match monitors::containing(&monitors, bounds) {
+                Some(i) => monitors[i].work_area,
+                None => (0, 0, 0, 0)
+            };
+
+            Some((wl + x, wt + y, wl + x + (r - l), wt + y + (b - t)))
+        },
+
+        Action::AlwaysOnTop(topmost) => {
+            system.set_topmost(hwnd, topmost).map_err(|e| format!("error {}", e))?;
+            None
+        },
+
+        Action::Minimize => {
+            system.minimize(hwnd);
+            None
+        },
+
+        Action::Run(ref command) => {
+            process::Command::new("cmd")
+                .arg("/C")
+                .arg(command)
+                .env("WINMAN_HWND", format!("{}", hwnd as usize))
+                .spawn()
+                .map_err(|e| e.to_string())?;
+            None
+        },
+    };
+
+    match new_bounds {
+        Some(new_bounds) => system.set_window_bounds(hwnd, new_bounds).map_err(|e| format!("error {}", e)),
+        None => Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winapi::windef::*;
+
+    use window_filter::WindowFilter;
+    use window_registry::WindowEvent;
+    use window_system::WindowSystem;
+    use window_system::fake::FakeWindowSystem;
+    use window_tracking::{Config, WindowMatch};
+    use super::{Action, Rule, RuleEngine};
+
+    fn created(system: &FakeWindowSystem, hwnd: HWND) -> WindowEvent {
+        WindowEvent::Created(system.window_info(hwnd).unwrap())
+    }
+
+    fn title_changed(system: &FakeWindowSystem, hwnd: HWND) -> WindowEvent {
+        WindowEvent::TitleChanged(system.window_info(hwnd).unwrap())
+    }
+
+    fn title_rule(name: &str, title: &str, actions: Vec<Action>) -> Rule {
+        Rule {
+            name: name.to_string(),
+            matcher: WindowMatch {
+                title: Some(title.to_string()),
+                ..WindowMatch::default()
+            },
+            actions: actions,
+        }
+    }
+
+    #[test]
+    fn fires_once_per_match() {
+        let mut system = FakeWindowSystem::new();
+        let filter = WindowFilter::new(0);
+        let mut engine = RuleEngine::new(vec![title_rule("chat", "^Slack", vec![Action::Minimize])], false);
+
+        let hwnd = system.open("Loading...").hwnd();
+        assert!(engine.handle(&filter, &created(&system, hwnd)).is_empty());
+
+        system.window_mut(hwnd).info.title = "Slack - general".to_string();
+        assert_eq!(engine.handle(&filter, &title_changed(&system, hwnd)).len(), 1);
+
+        system.window_mut(hwnd).info.title = "Slack - random".to_string();
+        assert!(engine.handle(&filter, &title_changed(&system, hwnd)).is_empty());
+
+        // Matching again after a miss fires again
+        system.window_mut(hwnd).info.title = "Preferences".to_string();
+        assert!(engine.handle(&filter, &title_changed(&system, hwnd)).is_empty());
+        system.window_mut(hwnd).info.title = "Slack - general".to_string();
+        assert_eq!(engine.handle(&filter, &title_changed(&system, hwnd)).len(), 1);
+    }
+
+    #[test]
+    fn skips_filtered_windows() {
+        let mut system = FakeWindowSystem::new();
+        let filter = WindowFilter::new(0);
+        let mut engine = RuleEngine::new(vec![title_rule("chat", "Slack", vec![Action::Minimize])], false);
+
+        let hwnd = system.open("Slack").hwnd();
+        system.window_mut(hwnd).info.visible = false;
+
+        assert!(engine.handle(&filter, &created(&system, hwnd)).is_empty());
+    }
+
+    #[test]
+    fn applies_actions_in_order() {
+        let mut system = FakeWindowSystem::new();
+        system.add_monitor((1920, 0, 3840, 1200), (1920, 0, 3840, 1200));
+        let filter = WindowFilter::new(0);
+        let mut config = Config::new();
+        let mut engine = RuleEngine::new(vec![title_rule("chat", "Slack", vec![
+            Action::AssignSlot("5".to_string()),
+            Action::MoveToMonitor(2),
+            Action::Resize(800, 1000),
+            Action::MoveTo(10, 20),
+            Action::AlwaysOnTop(true),
+        ])], false);
+
+        let hwnd = system.open("Slack").hwnd();
+        for firing in engine.handle(&filter, &created(&system, hwnd)) {
+            engine.execute(&mut system, &mut config, &firing);
+        }
+
+        assert_eq!(system.window(hwnd).bounds, (1930, 20, 2730, 1020));
+        assert!(system.window(hwnd).topmost);
+        assert_eq!(config.get_windows("5").and_then(|set| set.front().map(|w| w.hwnd())), Some(hwnd));
+    }
+
+    #[test]
+    fn dry_run_changes_nothing() {
+        let mut system = FakeWindowSystem::new();
+        let filter = WindowFilter::new(0);
+        let mut config = Config::new();
+        let mut engine = RuleEngine::new(vec![title_rule("chat", "Slack", vec![
+            Action::AssignSlot("5".to_string()),
+            Action::Resize(800, 1000),
+            Action::Minimize,
+        ])], true);
+
+        let hwnd = system.open("Slack").hwnd();
+        for firing in engine.handle(&filter, &created(&system, hwnd)) {
+            engine.execute(&mut system, &mut config, &firing);
+        }
+
+        assert_eq!(system.window(hwnd).bounds, (100, 100, 900, 700));
+        assert!(!system.window(hwnd).minimized);
+        assert!(config.get_windows("5").and_then(|set| set.front()).is_none());
+    }
+}
@@ -0,0 +1,312 @@
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path::PathBuf;
+
+use winapi::windef::*;
+
+use monitors;
+use settings;
+use settings::parser;
+use settings::parser::Value;
+use window_system::WindowSystem;
+use windows::{calc_window_pos, Bounds, HorizontalAlignment, VerticalAlignment};
+
+// Hidden windows are written to this file while WinMan runs, so that they
+// can be shown again after a crash:
+//
+//   [scratchpad.terminal]
+//   hwnd = 657930
+//   exe = "WindowsTerminal.exe"
+//   class = "CASCADIA_HOSTING_WINDOW_CLASS"
+
+const STATE_FILE: &'static str = "scratchpads.toml";
+
+struct Stashed {
+    hwnd: HWND,
+    exe: String,
+    class: String,
+    /// Where it was last shown, and the work area it was on. None until the
+    /// first summon, which centers it.
+    last_shown: Option<(Bounds, Bounds)>,
+}
+
+/// Named hidden windows that come and go on a key, like i3's scratchpad.
+///
+/// Hidden windows are off the screen and the taskbar. A summoned window
+/// comes back where it was when it is on the same monitor, and otherwise
+/// centered on the current one with the same size.
+pub struct Scratchpads {
+    pads: Vec<(String, Stashed)>,
+}
+
+impl Scratchpads {
+    pub fn new() -> Self {
+        Scratchpads {
+            pads: Vec::new(),
+        }
+    }
+
+    /// Hides `hwnd` in the named scratchpad. A window already there is put
+    /// back on screen.
+    pub fn send<S: WindowSystem>(&mut self, system: &mut S, name: &str, hwnd: HWND) {
+        let info = match system.window_info(hwnd) {
+            Some(info) => info,
+            None => return
+        };
+
+        // A window lives in one scratchpad at a time
+        self.pads.retain(|(_, stashed)| stashed.hwnd != hwnd);
+
+        if let Some(index) = self.pads.iter().position(|(pad, _)| pad == name) {
+            let (_, old) = self.pads.remove(index);
+            if system.window_info(old.hwnd).is_some() {
+                system.set_visible(old.hwnd, true);
+            }
+        }
+
+        println!("Sending window {:?} to scratchpad {}", hwnd, name);
+        system.set_visible(hwnd, false);
+
+        self.pads.push((name.to_string(), Stashed {
+            hwnd: hwnd,
+            exe: info.exe,
+            class: info.class,
+            last_shown: None,
+        }));
+    }
+
+    /// Shows the scratchpad window on `area` if hidden, focuses it if shown
+    /// but behind other windows, and hides it if focused. An empty
+    /// scratchpad takes the foreground window. Returns false when there is
+    /// nothing to do.
+    pub fn toggle<S: WindowSystem>(&mut self, system: &mut S, name: &str, area: Bounds) -> bool {
+        // Forget windows that were closed
+        self.pads.retain(|(_, stashed)| system.window_info(stashed.hwnd).is_some());
+
+        let stashed = match self.pads.iter_mut().find(|(pad, _)| pad == name) {
+            Some((_, stashed)) => stashed,
+            None => {
+                return match system.foreground_window() {
+                    Some(window) => {
+                        self.send(system, name, window.hwnd());
+                        true
+                    },
+                    None => false
+                };
+            }
+        };
+
+        let hwnd = stashed.hwnd;
+        let visible = system.window_info(hwnd).map(|info| info.visible) == Some(true);
+        let focused = system.foreground_window().map(|w| w.hwnd()) == Some(hwnd);
+
+        if visible && focused {
+            let monitors = system.monitors();
+            let bounds = system.window_bounds(hwnd);
+
+            if let Some(index) = monitors::containing(&monitors, bounds) {
+                stashed.last_shown = Some((bounds, monitors[index].work_area));
+            }
+
+            system.set_visible(hwnd, false);
+            return true;
+        }
+
+        if !visible {
+            let bounds = match stashed.last_shown {
+                Some((bounds, shown_on)) if shown_on == area => bounds,
+                Some((bounds, _)) => center(bounds, area),
+                None => center(system.window_bounds(hwnd), area),
+            };
+
+            let _ = system.set_window_bounds(hwnd, bounds);
+            system.set_visible(hwnd, true);
+        }
+
+        let _ = system.set_foreground_window(hwnd);
+        true
+    }
+
+    /// Puts every hidden window back on screen, e.g. before quitting.
+    pub fn restore_all<S: WindowSystem>(&mut self, system: &mut S) {
+        for (name, stashed) in self.pads.drain(..) {
+            if system.window_info(stashed.hwnd).is_some() {
+                println!("Restoring window {:?} from scratchpad {}", stashed.hwnd, name);
+                system.set_visible(stashed.hwnd, true);
+            }
+        }
+    }
+
+    /// The state file form, read back by `recover`.
+    pub fn to_toml(&self) -> String {
+        let mut out = String::new();
+
+        for (name, stashed) in &self.pads {
+            out.push_str(&format!("[scratchpad.{}]\n", Value::String(name.clone())));
+            out.push_str(&format!("hwnd = {}\n", stashed.hwnd as usize));
+            out.push_str(&format!("exe = {}\n", Value::String(stashed.exe.clone())));
+            out.push_str(&format!("class = {}\n\n", Value::String(stashed.class.clone())));
+        }
+
+        out
+    }
+
+    /// Writes the hidden windows to the state file, or removes it when
+    /// there are none.
+    pub fn save(&self) -> io::Result<()> {
+        if self.pads.is_empty() {
+            return match fs::remove_file(state_path()) {
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                result => result
+            };
+        }
+
+        fs::write(state_path(), self.to_toml())
+    }
+}
+
+fn center(bounds: Bounds, area: Bounds) -> Bounds {
+    let (l, t, r, b) = bounds;
+    let (x, y, w, h) = calc_window_pos(
+        area,
+        Some((r - l).min(area.2 - area.0)),
+        Some((b - t).min(area.3 - area.1)),
+        None,
+        None,
+        HorizontalAlignment::Center,
+        VerticalAlignment::Center);
+
+    (area.0 + x, area.1 + y, area.0 + x + w, area.1 + y + h)
+}
+
+fn state_path() -> PathBuf {
+    settings::default_path().with_file_name(STATE_FILE)
+}
+
+/// Shows windows that a previous run left hidden, if they are still the
+/// same windows. Returns how many were shown.
+pub fn recover<S: WindowSystem>(system: &mut S, state: &str) -> usize {
+    let doc = match parser::parse(state) {
+        Ok(doc) => doc,
+        Err(e) => {
+            println!("Could not read scratchpad state: {}", e);
+            return 0;
+        }
+    };
+
+    let mut shown = 0;
+
+    for (name, table) in doc.subtables("scratchpad") {
+        let hwnd = match table.get("hwnd").and_then(|v| v.as_integer()) {
+            Some(hwnd) => hwnd as usize as HWND,
+            None => continue
+        };
+        let exe = table.get("exe").and_then(|v| v.as_str());
+        let class = table.get("class").and_then(|v| v.as_str());
+
+        // Handles are reused, so only show the window if it is the same one
+        let same = system.window_info(hwnd)
+            .map(|info| Some(&info.exe[..]) == exe && Some(&info.class[..]) == class && !info.visible) == Some(true);
+
+        if same {
+            println!("Recovering window {:?} from scratchpad {}", hwnd, name);
+            system.set_visible(hwnd, true);
+            shown += 1;
+        }
+    }
+
+    shown
+}
+
+/// Runs `recover` on the state file of a previous run, if there is one.
+pub fn recover_from_file<S: WindowSystem>(system: &mut S) {
+    let mut state = String::new();
+    let read = fs::File::open(state_path()).and_then(|mut file| file.read_to_string(&mut state));
+
+    if read.is_ok() {
+        recover(system, &state);
+        let _ = fs::remove_file(state_path());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use window_system::WindowSystem;
+    use window_system::fake::FakeWindowSystem;
+    use super::{recover, Scratchpads};
+
+    const AREA: (i32, i32, i32, i32) = (0, 0, 1920, 1040);
+
+    #[test]
+    fn toggle_hides_and_summons() {
+        let mut system = FakeWindowSystem::new();
+        let mut pads = Scratchpads::new();
+
+        let terminal = system.open("terminal").hwnd();
+        system.open("editor");
+
+        pads.send(&mut system, "term", terminal);
+        assert!(!system.window(terminal).info.visible);
+
+        // First summon centers it, keeping its size
+        assert!(pads.toggle(&mut system, "term", AREA));
+        assert!(system.window(terminal).info.visible);
+        assert_eq!((560, 220, 1360, 820), system.window(terminal).bounds);
+        assert_eq!(Some(terminal), system.foreground);
+
+        // Moved, hidden, and summoned back in place
+        system.window_mut(terminal).bounds = (0, 0, 800, 600);
+        assert!(pads.toggle(&mut system, "term", AREA));
+        assert!(!system.window(terminal).info.visible);
+        assert!(pads.toggle(&mut system, "term", AREA));
+        assert_eq!((0, 0, 800, 600), system.window(terminal).bounds);
+
+        // On another monitor it is centered there
+        pads.toggle(&mut system, "term", AREA);
+        pads.toggle(&mut system, "term", (1920, 0, 3840, 1200));
+        assert_eq!((2480, 300, 3280, 900), system.window(terminal).bounds);
+    }
+
+    #[test]
+    fn empty_scratchpad_takes_foreground_window() {
+        let mut system = FakeWindowSystem::new();
+        let mut pads = Scratchpads::new();
+
+        let notes = system.open("notes").hwnd();
+        assert!(pads.toggle(&mut system, "notes", AREA));
+        assert!(!system.window(notes).info.visible);
+
+        // Closed windows are forgotten
+        system.windows.clear();
+        assert!(!pads.toggle(&mut system, "notes", AREA));
+    }
+
+    #[test]
+    fn nothing_stays_hidden() {
+        let mut system = FakeWindowSystem::new();
+        let mut pads = Scratchpads::new();
+
+        let a = system.open("a").hwnd();
+        let b = system.open("b").hwnd();
+        pads.send(&mut system, "one", a);
+        pads.send(&mut system, "two", b);
+        let state = pads.to_toml();
+
+        // Replacing a scratchpad's window shows the old one
+        let c = system.open("c").hwnd();
+        pads.send(&mut system, "two", c);
+        assert!(system.window(b).info.visible);
+
+        pads.restore_all(&mut system);
+        assert!(system.windows().iter().all(|&hwnd| system.window(hwnd).info.visible));
+
+        // After a crash, only windows that are still the same are shown
+        system.set_visible(a, false);
+        system.set_visible(b, false);
+        system.window_mut(b).info.class = "Reused".to_string();
+        assert_eq!(1, recover(&mut system, &state));
+        assert!(system.window(a).info.visible);
+        assert!(!system.window(b).info.visible);
+    }
+}
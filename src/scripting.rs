@@ -0,0 +1,460 @@
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::io::Read;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use rhai::{Array, CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST, INT};
+use winapi::windef::HWND;
+
+use commands::Command;
+use fuzzy;
+use settings::ScriptSettings;
+use windows::Bounds;
+
+// User-defined commands, written in Rhai (https://rhai.rs):
+//
+//   // Puts the editors side by side
+//   let editors = windows().filter(|w| w.exe == "code.exe");
+//   for (w, i) in editors {
+//       set_rect(w.hwnd, [i * 960, 0, (i + 1) * 960, 1040]);
+//   }
+//
+//   windows()                  [#{hwnd, title, exe, class, rect: [left, top, right, bottom]}]
+//   focused()                  the window the script runs for, or ()
+//   focus(hwnd)
+//   set_rect(hwnd, rect)
+//   slots()                    #{name: [hwnd, ...]}
+//   add_to_slot(name, hwnd)
+//   clear_slot(name)
+//   run("snap left")           any command the popup takes, other than scripts
+//   popup(items, "picked")     shows the popup over a list, calling fn picked(item)
+//
+// Scripts do not touch windows themselves. What they ask for is carried
+// out in order once they finish, and the functions above answer as if it
+// already had been.
+
+/// A window as scripts see it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScriptWindow {
+    pub hwnd: HWND,
+    pub title: String,
+    pub exe: String,
+    pub class: String,
+    pub bounds: Bounds,
+}
+
+/// What scripts can query, taken before each run.
+#[derive(Default)]
+pub struct Snapshot {
+    /// Switchable windows, most recently focused first
+    pub windows: Vec<ScriptWindow>,
+    pub focused: Option<HWND>,
+    pub slots: Vec<(String, Vec<HWND>)>,
+}
+
+/// Something a script asked for, for the dispatcher to carry out.
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    Focus(HWND),
+    SetBounds(HWND, Bounds),
+    AddToSlot(String, HWND),
+    ClearSlot(String),
+    Command(Command),
+    ShowList(ScriptList),
+}
+
+/// Items shown in the popup by a script, and the function that gets the
+/// one picked.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScriptList {
+    pub script: String,
+    pub items: Vec<String>,
+    pub callback: String,
+}
+
+/// The items of a list left by `query`, the one typed exactly first and then
+/// those it fuzzy matches. The first is what choosing `query` picks.
+pub fn matching_items<'a>(items: &'a [String], query: &str) -> Vec<&'a str> {
+    if query.is_empty() {
+        return items.iter().map(|i| i.as_str()).collect();
+    }
+
+    let exact = items.iter().filter(|i| *i == query);
+    let fuzzy: Vec<&String> = fuzzy::Finder::new(query).ok()
+        .map(|finder| items.iter().filter(|i| *i != query && finder.is_match(i)).collect())
+        .unwrap_or_default();
+
+    exact.chain(fuzzy).map(|i| i.as_str()).collect()
+}
+
+struct Script {
+    name: String,
+    ast: AST,
+    timeout: Duration,
+}
+
+/// Shared with the functions scripts call.
+#[derive(Default)]
+struct State {
+    script: String,
+    snapshot: Snapshot,
+    actions: Vec<Action>,
+}
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// The compiled scripts and the engine running them.
+pub struct Scripts {
+    engine: Engine,
+    scripts: Vec<Script>,
+    state: Rc<RefCell<State>>,
+    /// When the running script is stopped
+    deadline: Rc<Cell<Option<Instant>>>,
+}
+
+impl Scripts {
+    pub fn new() -> Self {
+        let state = Rc::new(RefCell::new(State::default()));
+        let deadline: Rc<Cell<Option<Instant>>> = Rc::new(Cell::new(None));
+
+        let mut engine = Engine::new();
+        engine.on_print(|text| println!("Script: {}", text));
+
+        let timer = deadline.clone();
+        engine.on_progress(move |_| match timer.get() {
+            Some(deadline) if Instant::now() > deadline => Some(Dynamic::UNIT),
+            _ => None
+        });
+
+        register_api(&mut engine, &state);
+
+        Scripts {
+            engine: engine,
+            scripts: Vec::new(),
+            state: state,
+            deadline: deadline,
+        }
+    }
+
+    /// Loads the scripts named in the settings. One that cannot be read or
+    /// compiled is reported and left out.
+    pub fn load(settings: &[ScriptSettings]) -> Self {
+        let mut scripts = Scripts::new();
+
+        for script in settings {
+            let mut source = String::new();
+            let result = File::open(&script.file)
+                .and_then(|mut file| file.read_to_string(&mut source))
+                .map_err(|e| e.to_string())
+                .and_then(|_| scripts.add(&script.name, &source, script.timeout));
+
+            if let Err(e) = result {
+                println!("Could not load script {} from {}: {}", script.name, script.file.display(), e);
+            }
+        }
+
+        scripts
+    }
+
+    pub fn add(&mut self, name: &str, source: &str, timeout: Duration) -> Result<(), String> {
+        let ast = self.engine.compile(source).map_err(|e| e.to_string())?;
+
+        self.scripts.retain(|script| script.name != name);
+        self.scripts.push(Script {
+            name: name.to_string(),
+            ast: ast,
+            timeout: timeout,
+        });
+
+        Ok(())
+    }
+
+    /// Runs a script, returning what it asked for.
+    pub fn run(&self, name: &str, snapshot: Snapshot) -> Result<Vec<Action>, String> {
+        let script = self.script(name)?;
+
+        self.execute(script, snapshot, |engine, ast| engine.run_ast(ast))
+    }
+
+    /// Calls the function of a script that showed `list` with the item picked.
+    pub fn choose(&self, list: &ScriptList, item: &str, snapshot: Snapshot) -> Result<Vec<Action>, String> {
+        let script = self.script(&list.script)?;
+
+        self.execute(script, snapshot, |engine, ast| {
+            // Only the function, not the script around it again
+            let options = CallFnOptions::new().eval_ast(false);
+            engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), ast, &list.callback, (item.to_string(),))
+                .map(|_| ())
+        })
+    }
+
+    fn script(&self, name: &str) -> Result<&Script, String> {
+        self.scripts
+            .iter()
+            .find(|script| script.name == name)
+            .ok_or_else(|| format!("unknown script '{}'", name))
+    }
+
+    fn execute<F>(&self, script: &Script, snapshot: Snapshot, f: F) -> Result<Vec<Action>, String>
+        where F: FnOnce(&Engine, &AST) -> ScriptResult<()>
+    {
+        *self.state.borrow_mut() = State {
+            script: script.name.clone(),
+            snapshot: snapshot,
+            actions: Vec::new(),
+        };
+        self.deadline.set(Some(Instant::now() + script.timeout));
+
+        let result = f(&self.engine, &script.ast);
+
+        self.deadline.set(None);
+        let actions = ::std::mem::take(&mut self.state.borrow_mut().actions);
+
+        match result {
+            Ok(()) => Ok(actions),
+            Err(e) => match *e {
+                EvalAltResult::ErrorTerminated(..) => {
+                    Err(format!("stopped after {} ms", script.timeout.as_millis()))
+                },
+                ref e => Err(e.to_string())
+            }
+        }
+    }
+}
+
+fn register_api(engine: &mut Engine, state: &Rc<RefCell<State>>) {
+    let s = state.clone();
+    engine.register_fn("windows", move || -> Array {
+        s.borrow().snapshot.windows.iter().map(window_map).collect()
+    });
+
+    let s = state.clone();
+    engine.register_fn("focused", move || -> Dynamic {
+        let state = s.borrow();
+        state.snapshot.focused
+            .and_then(|hwnd| state.snapshot.windows.iter().find(|w| w.hwnd == hwnd))
+            .map(window_map)
+            .unwrap_or(Dynamic::UNIT)
+    });
+
+    let s = state.clone();
+    engine.register_fn("focus", move |hwnd: INT| -> ScriptResult<()> {
+        let mut state = s.borrow_mut();
+        let hwnd = known_window(&state, hwnd)?;
+
+        state.snapshot.focused = Some(hwnd);
+        state.actions.push(Action::Focus(hwnd));
+        Ok(())
+    });
+
+    let s = state.clone();
+    engine.register_fn("set_rect", move |hwnd: INT, rect: Array| -> ScriptResult<()> {
+        let mut state = s.borrow_mut();
+        let hwnd = known_window(&state, hwnd)?;
+        let bounds = to_bounds(&rect)?;
+
+        if let Some(window) = state.snapshot.windows.iter_mut().find(|w| w.hwnd == hwnd) {
+            window.bounds = bounds;
+        }
+        state.actions.push(Action::SetBounds(hwnd, bounds));
+        Ok(())
+    });
+
+    let s = state.clone();
+    engine.register_fn("slots", move || -> Map {
+        s.borrow().snapshot.slots.iter()
+            .map(|(name, hwnds)| {
+                let hwnds: Array = hwnds.iter().map(|&hwnd| Dynamic::from(hwnd as INT)).collect();
+                (name.as_str().into(), Dynamic::from(hwnds))
+            })
+            .collect()
+    });
+
+    let s = state.clone();
+    engine.register_fn("add_to_slot", move |name: &str, hwnd: INT| -> ScriptResult<()> {
+        let mut state = s.borrow_mut();
+        let hwnd = known_window(&state, hwnd)?;
+        let slot = find_slot(&mut state, name)?;
+
+        if !slot.contains(&hwnd) {
+            slot.push(hwnd);
+        }
+        state.actions.push(Action::AddToSlot(name.to_string(), hwnd));
+        Ok(())
+    });
+
+    let s = state.clone();
+    engine.register_fn("clear_slot", move |name: &str| -> ScriptResult<()> {
+        let mut state = s.borrow_mut();
+        find_slot(&mut state, name)?.clear();
+
+        state.actions.push(Action::ClearSlot(name.to_string()));
+        Ok(())
+    });
+
+    let s = state.clone();
+    engine.register_fn("run", move |text: &str| -> ScriptResult<()> {
+        let command = text.parse::<Command>().map_err(|e| e.to_string())?;
+
+        match command {
+            Command::RunScript(_) | Command::Choose(_) => Err(format!("'{}' cannot be run from a script", command).into()),
+            command => {
+                s.borrow_mut().actions.push(Action::Command(command));
+                Ok(())
+            }
+        }
+    });
+
+    let s = state.clone();
+    engine.register_fn("popup", move |items: Array, callback: &str| {
+        let mut state = s.borrow_mut();
+        let list = ScriptList {
+            script: state.script.clone(),
+            items: items.iter().map(|item| item.to_string()).collect(),
+            callback: callback.to_string(),
+        };
+
+        state.actions.push(Action::ShowList(list));
+    });
+}
+
+fn window_map(window: &ScriptWindow) -> Dynamic {
+    let (left, top, right, bottom) = window.bounds;
+    let rect: Array = vec![left, top, right, bottom].into_iter().map(|n| Dynamic::from(n as INT)).collect();
+
+    let mut map = Map::new();
+    map.insert("hwnd".into(), Dynamic::from(window.hwnd as INT));
+    map.insert("title".into(), Dynamic::from(window.title.clone()));
+    map.insert("exe".into(), Dynamic::from(window.exe.clone()));
+    map.insert("class".into(), Dynamic::from(window.class.clone()));
+    map.insert("rect".into(), Dynamic::from(rect));
+
+    Dynamic::from(map)
+}
+
+fn known_window(state: &State, hwnd: INT) -> ScriptResult<HWND> {
+    state.snapshot.windows
+        .iter()
+        .map(|w| w.hwnd)
+        .find(|&known| known as INT == hwnd)
+        .ok_or_else(|| format!("no window {}", hwnd).into())
+}
+
+fn find_slot<'a>(state: &'a mut State, name: &str) -> ScriptResult<&'a mut Vec<HWND>> {
+    state.snapshot.slots
+        .iter_mut()
+        .find(|(slot, _)| slot == name)
+        .map(|(_, hwnds)| hwnds)
+        .ok_or_else(|| format!("no slot '{}'", name).into())
+}
+
+fn to_bounds(rect: &[Dynamic]) -> ScriptResult<Bounds> {
+    let numbers: Vec<INT> = rect.iter().filter_map(|n| n.as_int().ok()).collect();
+
+    match numbers[..] {
+        [left, top, right, bottom] if numbers.len() == rect.len() => {
+            Ok((left as i32, top as i32, right as i32, bottom as i32))
+        },
+        _ => Err("expected a rect of [left, top, right, bottom]".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use commands::Command;
+
+    use super::{matching_items, Action, ScriptList, ScriptWindow, Scripts, Snapshot};
+
+    fn snapshot() -> Snapshot {
+        let window = |hwnd: usize, title: &str, exe: &str| ScriptWindow {
+            hwnd: hwnd as _,
+            title: title.to_string(),
+            exe: exe.to_string(),
+            class: "Window".to_string(),
+            bounds: (0, 0, 800, 600),
+        };
+
+        Snapshot {
+            windows: vec![window(1, "main.rs - Code", "code.exe"), window(2, "Terminal", "wt.exe")],
+            focused: Some(2 as _),
+            slots: vec![("1".to_string(), vec![1 as _]), ("2".to_string(), Vec::new())],
+        }
+    }
+
+    fn run(source: &str) -> Result<Vec<Action>, String> {
+        let mut scripts = Scripts::new();
+        scripts.add("test", source, Duration::from_millis(200)).unwrap();
+        scripts.run("test", snapshot())
+    }
+
+    #[test]
+    fn queries_and_actions() {
+        let actions = run(r#"
+            let code = windows().filter(|w| w.exe == "code.exe")[0];
+            set_rect(code.hwnd, [0, 0, 960, 1040]);
+            focus(code.hwnd);
+
+            if focused().rect[2] != 960 { throw "focus and rect not applied"; }
+
+            add_to_slot("2", focused().hwnd);
+            if slots()["2"].len() != 1 { throw "slot not applied"; }
+
+            clear_slot("1");
+            run("snap left");
+        "#);
+
+        assert_eq!(Ok(vec![
+            Action::SetBounds(1 as _, (0, 0, 960, 1040)),
+            Action::Focus(1 as _),
+            Action::AddToSlot("2".to_string(), 1 as _),
+            Action::ClearSlot("1".to_string()),
+            Action::Command(Command::Snap("left".to_string())),
+        ]), actions);
+    }
+
+    #[test]
+    fn popup_lists_call_back() {
+        let mut scripts = Scripts::new();
+        let source = r#"
+            fn picked(item) { run("focus-slot " + item); }
+            popup(["1", "2"], "picked");
+        "#;
+        scripts.add("menu", source, Duration::from_millis(200)).unwrap();
+
+        let list = ScriptList {
+            script: "menu".to_string(),
+            items: vec!["1".to_string(), "2".to_string()],
+            callback: "picked".to_string(),
+        };
+        assert_eq!(Ok(vec![Action::ShowList(list.clone())]), scripts.run("menu", snapshot()));
+
+        // The script body does not run again
+        assert_eq!(Ok(vec![Action::Command(Command::FocusSlot("2".to_string()))]), scripts.choose(&list, "2", snapshot()));
+    }
+
+    #[test]
+    fn list_items_match_as_typed() {
+        let items: Vec<String> = vec!["editor", "edit", "terminal"].into_iter().map(String::from).collect();
+
+        assert_eq!(vec!["editor", "edit", "terminal"], matching_items(&items, ""));
+        assert_eq!(vec!["edit", "editor"], matching_items(&items, "edit"));
+        assert_eq!(vec!["terminal"], matching_items(&items, "min"));
+        assert!(matching_items(&items, "xyz").is_empty());
+    }
+
+    #[test]
+    fn errors_are_reported() {
+        assert!(run("focus(99)").unwrap_err().contains("no window 99"));
+        assert!(run(r#"run("script test")"#).unwrap_err().contains("cannot be run from a script"));
+        assert!(run(r#"run("launch-rockets")"#).unwrap_err().contains("unknown command"));
+        assert!(run("set_rect(1, [0, 0])").is_err());
+        assert_eq!(Err("stopped after 200 ms".to_string()), run("loop { }"));
+
+        let mut scripts = Scripts::new();
+        assert!(scripts.add("broken", "let = ;", Duration::from_millis(200)).is_err());
+        assert_eq!(Err("unknown script 'broken'".to_string()), scripts.run("broken", snapshot()));
+    }
+}
@@ -0,0 +1,797 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use winapi::minwindef::*;
+
+use commands::Command;
+use constants::*;
+use hotkeys::{built_in_hotkeys, script_hotkey, slot_hotkeys};
+use keys;
+use keys::Chord;
+use monitors::Follow;
+use rules::{Action, Rule};
+use snap::{Fraction, Region};
+use tiling::LayoutOptions;
+use window_tracking::{SlotMode, WindowMatch};
+
+use self::parser::{Document, ParseError, Table, Value};
+
+pub mod parser;
+
+const FILE_NAME: &'static str = "winman.toml";
+const DEFAULT_LAUNCH_TIMEOUT_MS: u64 = 10000;
+const DEFAULT_SCRIPT_TIMEOUT_MS: u64 = 1000;
+
+// Example settings file:
+//
+//   [slot.editor]
+//   key = "E"   # dropped if a built-in hotkey or script has its chords
+//   mode = "minimize"   # or "previous" to toggle, default "cycle"
+//   description = "Code editors"
+//   icon = 'C:\icons\code.ico'
+//
+//   [slot.1]
+//   exe = "code.exe"
+//   run = 'C:\Program Files\Microsoft VS Code\Code.exe'
+//   args = ["--new-window"]
+//   cwd = 'C:\src'
+//   timeout = 15000
+//
+//   [slot.1.env]
+//   RUST_BACKTRACE = "1"
+//
+//   [filter]
+//   allow = ["exe=paint.exe"]
+//   deny = ["class=ApplicationFrameWindow", "exe=TextInputHost.exe; title=^$"]
+//
+//   [rules]
+//   dry_run = true
+//
+//   [rule.slack]
+//   exe = "slack.exe"
+//   slot = "5"
+//   monitor = 2
+//   size = "800x1000"
+//   position = "0,0"
+//   always_on_top = false
+//   minimize = false
+//   run = 'notify.cmd'
+//
+//   [regions]
+//   # left, top, width and height as fractions of the monitor work area
+//   wide-center = "0.1, 0, 0.8, 1"
+//
+//   [tiling]
+//   gap = 8
+//   padding = 8
+//   master = 60   # percent of the width for master-stack
+//
+//   [layouts]
+//   auto_restore = true   # put windows back when monitors are plugged in or out
+//
+//   [popup]
+//   monitor = "focus"     # or "cursor", where the switcher opens
+//
+//   [scratchpads]
+//   # Ctrl+Shift+key shows or hides the window, taking the focused one first
+//   terminal = "F12"
+//
+//   [script.arrange]
+//   file = 'C:\scripts\arrange.rhai'   # relative to the settings file
+//   key = "F9"        # Ctrl+Alt+key runs it, as does the popup's :script arrange
+//   timeout = 500     # milliseconds before it is stopped, default 1000
+//
+//   [bindings]
+//   # any command the popup takes, these win over built-in hotkeys
+//   "Alt+Shift+F3" = "grab-slot 3"
+//   "Win+Ctrl+Enter" = "script arrange"
+
+#[derive(Default)]
+pub struct Settings {
+    slots: Vec<SlotSettings>,
+    filter: FilterSettings,
+    rules: Vec<Rule>,
+    dry_run: bool,
+    regions: Vec<(String, Fraction)>,
+    tiling: LayoutOptions,
+    auto_restore: bool,
+    popup_monitor: Follow,
+    scratchpads: Vec<(String, UINT)>,
+    scripts: Vec<ScriptSettings>,
+    bindings: Vec<(Chord, Command)>,
+    warnings: Vec<String>,
+}
+
+pub struct SlotSettings {
+    pub name: String,
+    /// Slots named after a digit default to that digit's key
+    pub key: Option<UINT>,
+    pub mode: SlotMode,
+    pub description: Option<String>,
+    pub icon: Option<String>,
+    pub identity: Option<WindowMatch>,
+    pub launch: Option<LaunchCommand>,
+}
+
+/// User rules for the switcher list, on top of the built-in filters.
+#[derive(Default)]
+pub struct FilterSettings {
+    pub allow: Vec<WindowMatch>,
+    pub deny: Vec<WindowMatch>,
+}
+
+pub struct LaunchCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    pub cwd: Option<String>,
+    pub env: Vec<(String, String)>,
+    pub timeout: Duration,
+}
+
+/// A user-defined command written in Rhai, see `scripting`.
+pub struct ScriptSettings {
+    pub name: String,
+    pub file: PathBuf,
+    pub key: Option<UINT>,
+    pub timeout: Duration,
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Io(io::Error),
+    Parse(ParseError),
+    Invalid(String),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SettingsError::Io(ref e) => write!(f, "{}", e),
+            SettingsError::Parse(ref e) => write!(f, "{}", e),
+            SettingsError::Invalid(ref s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl Settings {
+    pub fn slots(&self) -> &[SlotSettings] {
+        &self.slots
+    }
+
+    pub fn slot(&self, name: &str) -> Option<&SlotSettings> {
+        self.slots.iter().find(|s| s.name == name)
+    }
+
+    pub fn filter(&self) -> &FilterSettings {
+        &self.filter
+    }
+
+    /// Window rules, ordered by name
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Rules only log what they would do
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn tiling(&self) -> &LayoutOptions {
+        &self.tiling
+    }
+
+    /// Layouts are remembered per monitor arrangement and restored with it
+    pub fn auto_restore(&self) -> bool {
+        self.auto_restore
+    }
+
+    pub fn popup_monitor(&self) -> Follow {
+        self.popup_monitor
+    }
+
+    /// Scratchpad names with their keys.
+    pub fn scratchpads(&self) -> &[(String, UINT)] {
+        &self.scratchpads
+    }
+
+    pub fn scripts(&self) -> &[ScriptSettings] {
+        &self.scripts
+    }
+
+    /// Commands bound to key chords in `[bindings]`, in file order.
+    pub fn bindings(&self) -> &[(Chord, Command)] {
+        &self.bindings
+    }
+
+    /// Problems that did not stop the settings from loading, such as slot
+    /// keys that were dropped.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// A snap region from the settings, or else a built-in one.
+    pub fn region(&self, name: &str) -> Option<Region> {
+        self.regions
+            .iter()
+            .find(|region| region.0 == name)
+            .map(|region| Region::Custom(region.1))
+            .or_else(|| Region::from_name(name))
+    }
+}
+
+/// The settings file lives next to the executable.
+pub fn default_path() -> PathBuf {
+    ::std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(FILE_NAME)))
+        .unwrap_or(PathBuf::from(FILE_NAME))
+}
+
+/// Loads settings from `path`. A missing file yields the defaults.
+pub fn load(path: &Path) -> Result<Settings, SettingsError> {
+    let mut contents = String::new();
+
+    match File::open(path) {
+        Ok(mut file) => {
+            file.read_to_string(&mut contents).map_err(SettingsError::Io)?;
+        },
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(Settings::default());
+        },
+        Err(e) => return Err(SettingsError::Io(e))
+    }
+
+    let mut settings = parse(&contents)?;
+
+    // Script files are found next to the settings file
+    if let Some(dir) = path.parent() {
+        for script in &mut settings.scripts {
+            script.file = dir.join(&script.file);
+        }
+    }
+
+    Ok(settings)
+}
+
+/// Reads settings from the contents of a settings file.
+pub fn parse(contents: &str) -> Result<Settings, SettingsError> {
+    let doc = parser::parse(contents).map_err(SettingsError::Parse)?;
+
+    from_document(&doc)
+}
+
+fn from_document(doc: &Document) -> Result<Settings, SettingsError> {
+    let mut settings = Settings::default();
+
+    for (name, table) in doc.subtables("slot") {
+        let env = table.get("env").and_then(|value| value.as_table());
+        let slot = read_slot(name, table, env)?;
+
+        settings.slots.push(slot);
+    }
+
+    if let Some(table) = doc.table("filter") {
+        for rule in get_string_list(table, "filter", "allow")? {
+            settings.filter.allow.push(parse_match("filter", "allow", &rule)?);
+        }
+
+        for rule in get_string_list(table, "filter", "deny")? {
+            settings.filter.deny.push(parse_match("filter", "deny", &rule)?);
+        }
+    }
+
+    if let Some(table) = doc.table("rules") {
+        settings.dry_run = match table.get("dry_run") {
+            Some(value) => match value.as_bool() {
+                Some(b) => b,
+                None => return Err(invalid("[rules] dry_run: expected true or false"))
+            },
+            None => false
+        };
+    }
+
+    for (name, table) in doc.subtables("rule") {
+        settings.rules.push(read_rule(name, table)?);
+    }
+
+    if let Some(table) = doc.table("regions") {
+        for (name, value) in table {
+            match value.as_str().and_then(parse_fraction) {
+                Some(fraction) => settings.regions.push((name.clone(), fraction)),
+                None => return Err(invalid(&format!("[regions] {}: expected \"left, top, width, height\" as fractions of the screen", name)))
+            }
+        }
+    }
+
+    if let Some(table) = doc.table("tiling") {
+        let pixels = |key: &str| match table.get(key) {
+            Some(value) => match value.as_integer() {
+                Some(n) if n >= 0 => Ok(Some(n as i32)),
+                _ => Err(invalid(&format!("[tiling] {}: expected a number of pixels", key)))
+            },
+            None => Ok(None)
+        };
+
+        if let Some(gap) = pixels("gap")? {
+            settings.tiling.gap = gap;
+        }
+
+        if let Some(padding) = pixels("padding")? {
+            settings.tiling.padding = padding;
+        }
+
+        if let Some(value) = table.get("master") {
+            match value.as_integer() {
+                Some(percent) if (10..=90).contains(&percent) => settings.tiling.master_ratio = percent as f64 / 100.0,
+                _ => return Err(invalid("[tiling] master: expected a percentage from 10 to 90"))
+            }
+        }
+    }
+
+    if let Some(table) = doc.table("layouts") {
+        settings.auto_restore = match table.get("auto_restore") {
+            Some(value) => match value.as_bool() {
+                Some(b) => b,
+                None => return Err(invalid("[layouts] auto_restore: expected true or false"))
+            },
+            None => false
+        };
+    }
+
+    if let Some(table) = doc.table("scratchpads") {
+        for (name, value) in table {
+            match value.as_str().and_then(keys::parse_key) {
+                Some(vk) => settings.scratchpads.push((name.clone(), vk)),
+                None => return Err(invalid(&format!("[scratchpads] {}: expected a key such as \"F12\"", name)))
+            }
+        }
+    }
+
+    if let Some(value) = doc.table("popup").and_then(|table| table.get("monitor")) {
+        settings.popup_monitor = match value.as_str() {
+            Some("cursor") => Follow::Cursor,
+            Some("focus") => Follow::Focus,
+            _ => return Err(invalid("[popup] monitor: expected \"cursor\" or \"focus\""))
+        };
+    }
+
+    for (name, table) in doc.subtables("script") {
+        settings.scripts.push(read_script(name, table)?);
+    }
+
+    if let Some(table) = doc.table("bindings") {
+        for (name, value) in table {
+            let chord = name.parse::<Chord>()
+                .map_err(|e| invalid(&format!("[bindings] {}: {}", name, e)))?;
+            if settings.bindings.iter().any(|&(bound, _)| bound == chord) {
+                return Err(invalid(&format!("[bindings] {}: {} is bound twice", name, chord)));
+            }
+
+            let command = match value.as_str() {
+                Some(command) => command.parse::<Command>()
+                    .map_err(|e| invalid(&format!("[bindings] {}: {}", name, e)))?,
+                None => return Err(invalid(&format!("[bindings] {}: expected a command such as \"focus-slot 1\"", name)))
+            };
+
+            settings.bindings.push((chord, command));
+        }
+    }
+
+    drop_taken_slot_keys(&mut settings);
+
+    Ok(settings)
+}
+
+/// A slot key stands for four chords, and one a built-in hotkey or a
+/// script already has would never reach the slot, e.g. Ctrl+Alt+Z for
+/// undo. Such slots go without a key. Bindings are left to override
+/// slot chords, as they do built-in ones.
+fn drop_taken_slot_keys(settings: &mut Settings) {
+    let mut taken = built_in_hotkeys();
+    taken.extend(settings.scripts.iter().filter_map(|script| script.key.map(|vk| script_hotkey(&script.name, vk))));
+
+    for slot in &mut settings.slots {
+        let vk = match slot.key {
+            Some(vk) => vk,
+            None => continue
+        };
+
+        let clash = slot_hotkeys(&slot.name, vk)
+            .into_iter()
+            .filter_map(|hotkey| taken.iter().find(|other| other.chord == hotkey.chord))
+            .next();
+
+        if let Some(other) = clash {
+            settings.warnings.push(format!("[slot.{}] key: {} is taken by '{}', the slot has no key", slot.name, other.chord, other.command));
+            slot.key = None;
+        }
+    }
+}
+
+fn read_script(name: &str, table: &Table) -> Result<ScriptSettings, SettingsError> {
+    let section = format!("script.{}", name);
+
+    let file = match get_string(table, &section, "file")? {
+        Some(file) => PathBuf::from(file),
+        None => return Err(invalid(&format!("[{}] file: expected the path of a .rhai script", section)))
+    };
+
+    let key = match get_string(table, &section, "key")? {
+        Some(key) => match keys::parse_key(&key) {
+            Some(vk) => Some(vk),
+            None => return Err(invalid(&format!("[{}] key: unknown key '{}'", section, key)))
+        },
+        None => None
+    };
+
+    let timeout = match table.get("timeout") {
+        Some(value) => match value.as_integer() {
+            Some(ms) if ms > 0 => ms as u64,
+            _ => return Err(invalid(&format!("[{}] timeout: expected a positive number of milliseconds", section)))
+        },
+        None => DEFAULT_SCRIPT_TIMEOUT_MS
+    };
+
+    Ok(ScriptSettings {
+        name: name.to_string(),
+        file: file,
+        key: key,
+        timeout: Duration::from_millis(timeout),
+    })
+}
+
+fn read_rule(name: &str, table: &Table) -> Result<Rule, SettingsError> {
+    let section = format!("rule.{}", name);
+
+    let matcher = WindowMatch {
+        exe: get_string(table, &section, "exe")?,
+        class: get_string(table, &section, "class")?,
+        title: get_string(table, &section, "title")?,
+    };
+    if matcher.exe.is_none() && matcher.class.is_none() && matcher.title.is_none() {
+        return Err(invalid(&format!("[{}] expected at least one of exe, class or title", section)));
+    }
+
+    let mut actions = Vec::new();
+
+    if let Some(slot) = get_string(table, &section, "slot")? {
+        actions.push(Action::AssignSlot(slot));
+    }
+
+    if let Some(value) = table.get("monitor") {
+        match value.as_integer() {
+            Some(n) if n > 0 => actions.push(Action::MoveToMonitor(n as usize)),
+            _ => return Err(invalid(&format!("[{}] monitor: expected a monitor number from 1", section)))
+        }
+    }
+
+    if let Some(size) = get_string(table, &section, "size")? {
+        match parse_pair(&size, 'x') {
+            Some((w, h)) if w > 0 && h > 0 => actions.push(Action::Resize(w, h)),
+            _ => return Err(invalid(&format!("[{}] size: expected WIDTHxHEIGHT, got '{}'", section, size)))
+        }
+    }
+
+    if let Some(position) = get_string(table, &section, "position")? {
+        match parse_pair(&position, ',') {
+            Some((x, y)) => actions.push(Action::MoveTo(x, y)),
+            None => return Err(invalid(&format!("[{}] position: expected X,Y, got '{}'", section, position)))
+        }
+    }
+
+    if let Some(value) = table.get("always_on_top") {
+        match value.as_bool() {
+            Some(topmost) => actions.push(Action::AlwaysOnTop(topmost)),
+            None => return Err(invalid(&format!("[{}] always_on_top: expected true or false", section)))
+        }
+    }
+
+    match table.get("minimize").map(|value| value.as_bool()) {
+        Some(Some(true)) => actions.push(Action::Minimize),
+        Some(Some(false)) | None => {},
+        Some(None) => return Err(invalid(&format!("[{}] minimize: expected true or false", section)))
+    }
+
+    if let Some(command) = get_string(table, &section, "run")? {
+        actions.push(Action::Run(command));
+    }
+
+    Ok(Rule {
+        name: name.to_string(),
+        matcher: matcher,
+        actions: actions,
+    })
+}
+
+/// Parses `0.1, 0, 0.8, 1`, which must lie within the unit square.
+fn parse_fraction(s: &str) -> Option<Fraction> {
+    let parts: Vec<f64> = s.split(',')
+        .map(|part| part.trim().parse().ok())
+        .collect::<Option<_>>()?;
+
+    if parts.len() != 4 || parts.iter().any(|p| !(0.0..=1.0).contains(p)) {
+        return None;
+    }
+
+    let fraction = (parts[0], parts[1], parts[2], parts[3]);
+    if fraction.2 == 0.0 || fraction.3 == 0.0 || fraction.0 + fraction.2 > 1.0 || fraction.1 + fraction.3 > 1.0 {
+        return None;
+    }
+
+    Some(fraction)
+}
+
+/// Parses `800x1000` or `10,20`.
+fn parse_pair(s: &str, separator: char) -> Option<(i32, i32)> {
+    let mut split = s.splitn(2, separator);
+    let a = split.next()?.trim().parse().ok()?;
+    let b = split.next()?.trim().parse().ok()?;
+
+    Some((a, b))
+}
+
+fn read_slot(name: &str, table: &Table, env: Option<&Table>) -> Result<SlotSettings, SettingsError> {
+    let section = format!("slot.{}", name);
+
+    let key = match get_string(table, &section, "key")? {
+        Some(key) => match keys::parse_key(&key) {
+            Some(vk) => Some(vk),
+            None => return Err(invalid(&format!("[{}] key: unknown key '{}'", section, key)))
+        },
+        None => match name.parse::<u32>() {
+            Ok(n) if n <= 9 => Some(VK_0 + n),
+            _ => None
+        }
+    };
+
+    let mode = match get_string(table, &section, "mode")? {
+        None => SlotMode::Cycle,
+        Some(ref mode) => match mode.as_str() {
+            "cycle" => SlotMode::Cycle,
+            "minimize" => SlotMode::Minimize,
+            "previous" => SlotMode::Previous,
+            _ => return Err(invalid(&format!("[{}] mode: expected cycle, minimize or previous", section)))
+        }
+    };
+
+    let identity = WindowMatch {
+        exe: get_string(table, &section, "exe")?,
+        class: get_string(table, &section, "class")?,
+        title: get_string(table, &section, "title")?,
+    };
+    let has_identity = identity.exe.is_some()
+        || identity.class.is_some()
+        || identity.title.is_some();
+
+    let launch = match get_string(table, &section, "run")? {
+        Some(program) => {
+            let timeout = match table.get("timeout") {
+                Some(value) => match value.as_integer() {
+                    Some(ms) if ms > 0 => ms as u64,
+                    _ => return Err(invalid(&format!("[{}] timeout: expected a positive number of milliseconds", section)))
+                },
+                None => DEFAULT_LAUNCH_TIMEOUT_MS
+            };
+
+            let mut vars = Vec::new();
+            if let Some(env) = env {
+                for (key, value) in env {
+                    match value.as_str() {
+                        Some(value) => vars.push((key.clone(), value.to_string())),
+                        None => return Err(invalid(&format!("[{}.env] {}: expected a string", section, key)))
+                    }
+                }
+            }
+
+            Some(LaunchCommand {
+                program: program,
+                args: get_string_list(table, &section, "args")?,
+                cwd: get_string(table, &section, "cwd")?,
+                env: vars,
+                timeout: Duration::from_millis(timeout),
+            })
+        },
+        None => None
+    };
+
+    Ok(SlotSettings {
+        name: name.to_string(),
+        key: key,
+        mode: mode,
+        description: get_string(table, &section, "description")?,
+        icon: get_string(table, &section, "icon")?,
+        identity: if has_identity { Some(identity) } else { None },
+        launch: launch,
+    })
+}
+
+fn get_string(table: &Table, section: &str, key: &str) -> Result<Option<String>, SettingsError> {
+    match table.get(key) {
+        Some(value) => match value.as_str() {
+            Some(s) => Ok(Some(s.to_string())),
+            None => Err(invalid(&format!("[{}] {}: expected a string", section, key)))
+        },
+        None => Ok(None)
+    }
+}
+
+fn get_string_list(table: &Table, section: &str, key: &str) -> Result<Vec<String>, SettingsError> {
+    let items = match table.get(key) {
+        Some(&Value::Array(ref items)) => items,
+        Some(_) => return Err(invalid(&format!("[{}] {}: expected a list of strings", section, key))),
+        None => return Ok(Vec::new())
+    };
+
+    items.iter()
+        .map(|item| match item.as_str() {
+            Some(s) => Ok(s.to_string()),
+            None => Err(invalid(&format!("[{}] {}: expected a list of strings", section, key)))
+        })
+        .collect()
+}
+
+/// Parses a window rule of the form `exe=code.exe; title=^Untitled`. A
+/// semicolon within a value, such as a title regex, is written `;;`.
+fn parse_match(section: &str, key: &str, rule: &str) -> Result<WindowMatch, SettingsError> {
+    let mut result = WindowMatch::default();
+
+    for part in split_rule(rule).iter().map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        let mut split = part.splitn(2, '=');
+        let field = split.next().unwrap_or("").trim();
+        let value = match split.next() {
+            Some(value) => Some(value.trim().to_string()),
+            None => return Err(invalid(&format!("[{}] {}: expected field=value in '{}'", section, key, rule)))
+        };
+
+        match field {
+            "exe" => result.exe = value,
+            "class" => result.class = value,
+            "title" => result.title = value,
+            _ => return Err(invalid(&format!("[{}] {}: unknown field '{}', expected exe, class or title", section, key, field)))
+        }
+    }
+
+    // A rule without fields would match every window
+    if result.exe.is_none() && result.class.is_none() && result.title.is_none() {
+        return Err(invalid(&format!("[{}] {}: expected at least one of exe, class or title in '{}'", section, key, rule)));
+    }
+
+    Ok(result)
+}
+
+/// Splits a window rule at single semicolons, leaving `;;` as one.
+fn split_rule(rule: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = rule.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ';' if chars.peek() == Some(&';') => {
+                chars.next();
+                parts.last_mut().unwrap().push(';');
+            },
+            ';' => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+
+    parts
+}
+
+fn invalid(message: &str) -> SettingsError {
+    SettingsError::Invalid(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use keys;
+    use rules::Action;
+
+    use super::{parse, parse_match};
+
+    #[test]
+    fn rules() {
+        let settings = parse(r#"
+            [rule.editor]
+            exe = "code.exe"
+            title = 'main\.rs'
+            slot = "e"
+            monitor = 2
+            size = "800x1000"
+            position = "10,-20"
+            always_on_top = true
+            minimize = false
+            run = "echo %WINMAN_HWND%"
+
+            [rule.chat]
+            class = "Chat"
+            minimize = true
+        "#).unwrap();
+
+        let rules = settings.rules();
+        assert_eq!(2, rules.len());
+
+        let editor = rules.iter().find(|r| r.name == "editor").unwrap();
+        assert_eq!(Some("code.exe".to_string()), editor.matcher.exe);
+        assert_eq!(Some(r"main\.rs".to_string()), editor.matcher.title);
+        assert_eq!(None, editor.matcher.class);
+        assert_eq!(vec![
+            Action::AssignSlot("e".to_string()),
+            Action::MoveToMonitor(2),
+            Action::Resize(800, 1000),
+            Action::MoveTo(10, -20),
+            Action::AlwaysOnTop(true),
+            Action::Run("echo %WINMAN_HWND%".to_string()),
+        ], editor.actions);
+
+        let chat = rules.iter().find(|r| r.name == "chat").unwrap();
+        assert_eq!(vec![Action::Minimize], chat.actions);
+    }
+
+    #[test]
+    fn rule_errors() {
+        let invalid = |rule: &str| parse(&format!("[rule.r]\n{}", rule)).is_err();
+
+        assert!(invalid("slot = \"1\""));
+        assert!(invalid("exe = \"a.exe\"\nsize = \"800\""));
+        assert!(invalid("exe = \"a.exe\"\nsize = \"0x600\""));
+        assert!(invalid("exe = \"a.exe\"\nmonitor = 0"));
+        assert!(invalid("exe = \"a.exe\"\nminimize = \"yes\""));
+    }
+
+    #[test]
+    fn window_matches() {
+        let rule = parse_match("filter", "allow", " exe=code.exe ; title=^Untitled;class=Chrome_WidgetWin_1;").unwrap();
+        assert_eq!(Some("code.exe".to_string()), rule.exe);
+        assert_eq!(Some("^Untitled".to_string()), rule.title);
+        assert_eq!(Some("Chrome_WidgetWin_1".to_string()), rule.class);
+
+        // A doubled semicolon stays in the value
+        let rule = parse_match("filter", "allow", "title=a;;b; exe=x.exe").unwrap();
+        assert_eq!(Some("a;b".to_string()), rule.title);
+        assert_eq!(Some("x.exe".to_string()), rule.exe);
+
+        // A lone semicolon in a regex is taken as a separator, and what follows rejected
+        assert!(parse_match("filter", "allow", "title=a;b").is_err());
+        assert!(parse_match("filter", "allow", "name=code").is_err());
+
+        // Nothing to match on would match every window
+        assert!(parse_match("filter", "deny", "").is_err());
+        assert!(parse_match("filter", "deny", " ; ").is_err());
+        assert!(parse("[filter]\ndeny = [\"\"]").is_err());
+    }
+
+    #[test]
+    fn taken_slot_keys_are_dropped() {
+        let settings = parse(r#"
+            [slot.undo]
+            key = "Z"
+
+            [slot.left]
+            key = "Left"
+
+            [slot.arrange]
+            key = "A"
+
+            [slot.editor]
+            key = "E"
+
+            [script.arrange]
+            file = "arrange.rhai"
+            key = "A"
+        "#).unwrap();
+
+        let keys: Vec<_> = ["undo", "left", "arrange", "editor"].iter()
+            .map(|name| settings.slot(name).unwrap().key)
+            .collect();
+        assert_eq!(vec![None, None, None, keys::parse_key("E")], keys);
+
+        assert_eq!(3, settings.warnings().len());
+        assert!(settings.warnings().iter().any(|w| w == "[slot.undo] key: Ctrl+Alt+Z is taken by 'undo', the slot has no key"));
+        assert!(settings.warnings().iter().any(|w| w == "[slot.arrange] key: Ctrl+Alt+A is taken by 'script arrange', the slot has no key"));
+    }
+}
@@ -0,0 +1,127 @@
+use toml;
+
+// Settings and state files are TOML. This keeps the lookups the readers
+// use (tables by dotted name, the children of a table) in one place.
+
+pub use toml::Value;
+pub use toml::de::Error as ParseError;
+
+pub type Table = toml::value::Table;
+
+/// A parsed file. Keys before the first header live in the root table,
+/// named "".
+#[derive(Debug, Default)]
+pub struct Document {
+    root: Table,
+}
+
+impl Document {
+    /// The table behind a dotted header name, e.g. `table("slot.1")` for
+    /// `[slot.1]`.
+    pub fn table(&self, name: &str) -> Option<&Table> {
+        if name.is_empty() {
+            return Some(&self.root);
+        }
+
+        let mut table = &self.root;
+        for part in name.split('.') {
+            table = table.get(part)?.as_table()?;
+        }
+        Some(table)
+    }
+
+    /// Direct children of a table, e.g. `subtables("slot")` yields
+    /// `("1", ..)` for a `[slot.1]` header.
+    pub fn subtables<'a>(&'a self, parent: &str) -> Vec<(&'a str, &'a Table)> {
+        match self.table(parent) {
+            Some(table) => table
+                .iter()
+                .filter_map(|(name, value)| value.as_table().map(|t| (name.as_str(), t)))
+                .collect(),
+            None => Vec::new()
+        }
+    }
+}
+
+/// Parses through `Value` rather than straight into a `Table`, which
+/// would let a repeated key silently replace the first.
+pub fn parse(input: &str) -> Result<Document, ParseError> {
+    match input.parse::<Value>()? {
+        Value::Table(root) => Ok(Document { root: root }),
+        _ => unreachable!("a TOML document is always a table")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Value};
+
+    fn error_line(input: &str) -> usize {
+        parse(input).unwrap_err().line_col().unwrap().0 + 1
+    }
+
+    #[test]
+    fn tables_and_values() {
+        let doc = parse(r#"
+            top = 1
+
+            # Slot 1
+            [slot.1]
+            exe = "code.exe"   # trailing comment
+            path = 'C:\Program Files\Code.exe'
+            args = ["--new-window", "C:\\src"]
+            timeout = 5_000
+            enabled = true
+        "#).unwrap();
+
+        assert_eq!(Some(&Value::Integer(1)), doc.table("").unwrap().get("top"));
+
+        let slot = doc.table("slot.1").unwrap();
+        assert_eq!(Some("code.exe"), slot["exe"].as_str());
+        assert_eq!(Some(r"C:\Program Files\Code.exe"), slot["path"].as_str());
+        assert_eq!(Some(5000), slot["timeout"].as_integer());
+        assert_eq!(Some(true), slot["enabled"].as_bool());
+        assert_eq!(Some(r"C:\src"), slot["args"].as_array().unwrap()[1].as_str());
+    }
+
+    #[test]
+    fn subtables() {
+        let doc = parse("[slot.1]\n[slot.2]\nexe = \"a\"\n[slot.2.env]\n[other]").unwrap();
+        let names: Vec<_> = doc.subtables("slot").iter().map(|&(n, _)| n).collect();
+
+        assert_eq!(vec!["1", "2"], names);
+        assert!(doc.table("slot.2.env").is_some());
+        assert!(doc.table("slot.2.exe").is_none());
+        assert!(doc.subtables("missing").is_empty());
+    }
+
+    #[test]
+    fn quoted_keys() {
+        let doc = parse(r#""Alt+Shift+F3" = "grab-slot 3""#).unwrap();
+
+        assert_eq!(Some("grab-slot 3"), doc.table("").unwrap()["Alt+Shift+F3"].as_str());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(1, error_line("key"));
+        assert_eq!(2, error_line("\n[table"));
+        assert_eq!(1, error_line("key = \"open"));
+        assert_eq!(1, error_line("a = 1 2"));
+        assert_eq!(2, error_line("[x]\n[x]"));
+        assert!(parse("a = 1\na = 2").is_err());
+    }
+
+    #[test]
+    fn display_round_trip() {
+        let value = Value::Array(vec![
+            Value::String("C:\\src \"quoted\"\n".to_string()),
+            Value::Integer(-32000),
+            Value::Boolean(true),
+        ]);
+
+        let doc = parse(&format!("key = {}", value)).unwrap();
+
+        assert_eq!(Some(&value), doc.table("").unwrap().get("key"));
+    }
+}
@@ -0,0 +1,209 @@
+use winapi::windef::*;
+
+use monitors;
+use utils::Win32Result;
+use window_system::WindowSystem;
+use windows::{calc_window_pos, Bounds, HorizontalAlignment, VerticalAlignment};
+
+/// A rectangle in fractions of the work area: left, top, width, height.
+pub type Fraction = (f64, f64, f64, f64);
+
+/// Sizes an edge snap steps through on repeated presses.
+pub const CYCLE: [f64; 3] = [1.0 / 2.0, 1.0 / 3.0, 2.0 / 3.0];
+
+/// Share of the work area taken by the centered box.
+pub const CENTER_SIZE: f64 = 0.6;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// A named place on the monitor to snap windows to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Region {
+    /// Halves and quarters. Repeated snaps cycle through the `CYCLE` widths,
+    /// or heights for the top and bottom edges.
+    Edge(Edge),
+    /// One of three columns, counted from the left
+    Third(usize),
+    Center,
+    Custom(Fraction),
+}
+
+impl Region {
+    /// Built-in regions: left, right, top, bottom, top-left, top-right,
+    /// bottom-left, bottom-right, left-third, center-third, right-third, center.
+    pub fn from_name(name: &str) -> Option<Region> {
+        let region = match name {
+            "left" => Region::Edge(Edge::Left),
+            "right" => Region::Edge(Edge::Right),
+            "top" => Region::Edge(Edge::Top),
+            "bottom" => Region::Edge(Edge::Bottom),
+            "top-left" => Region::Edge(Edge::TopLeft),
+            "top-right" => Region::Edge(Edge::TopRight),
+            "bottom-left" => Region::Edge(Edge::BottomLeft),
+            "bottom-right" => Region::Edge(Edge::BottomRight),
+            "left-third" => Region::Third(0),
+            "center-third" => Region::Third(1),
+            "right-third" => Region::Third(2),
+            "center" => Region::Center,
+            _ => return None
+        };
+
+        Some(region)
+    }
+
+    /// The sizes this region steps through, in order.
+    fn steps(&self) -> Vec<Fraction> {
+        match *self {
+            Region::Edge(edge) => CYCLE.iter()
+                .map(|&s| match edge {
+                    Edge::Left => (0.0, 0.0, s, 1.0),
+                    Edge::Right => (1.0 - s, 0.0, s, 1.0),
+                    Edge::Top => (0.0, 0.0, 1.0, s),
+                    Edge::Bottom => (0.0, 1.0 - s, 1.0, s),
+                    Edge::TopLeft => (0.0, 0.0, s, 0.5),
+                    Edge::TopRight => (1.0 - s, 0.0, s, 0.5),
+                    Edge::BottomLeft => (0.0, 0.5, s, 0.5),
+                    Edge::BottomRight => (1.0 - s, 0.5, s, 0.5),
+                })
+                .collect(),
+
+            Region::Third(n) => vec![(n.min(2) as f64 / 3.0, 0.0, 1.0 / 3.0, 1.0)],
+
+            Region::Center => {
+                let margin = (1.0 - CENTER_SIZE) / 2.0;
+                vec![(margin, margin, CENTER_SIZE, CENTER_SIZE)]
+            },
+
+            Region::Custom(fraction) => vec![fraction],
+        }
+    }
+}
+
+/// Bounds of `fraction` within `area`. Edges are rounded on their own
+/// rather than sizes, so that neighbouring regions meet without gaps.
+pub fn fraction_bounds(area: Bounds, fraction: Fraction) -> Bounds {
+    let (l, t, r, b) = area;
+    let (w, h) = ((r - l) as f64, (b - t) as f64);
+    let (fx, fy, fw, fh) = fraction;
+
+    let left = (fx * w).round() as i32;
+    let top = (fy * h).round() as i32;
+    let right = ((fx + fw) * w).round() as i32;
+    let bottom = ((fy + fh) * h).round() as i32;
+
+    let (x, y, width, height) = calc_window_pos(
+        area,
+        Some(right - left),
+        Some(bottom - top),
+        Some((left, top, 0, 0)),
+        None,
+        HorizontalAlignment::Left,
+        VerticalAlignment::Top);
+
+    (l + x, t + y, l + x + width, t + y + height)
+}
+
+/// Where a window at `current` goes when snapped to `region`. A window that
+/// already fills one of the region's sizes moves on to the next size.
+pub fn snap(region: &Region, area: Bounds, current: Bounds) -> Bounds {
+    let steps: Vec<Bounds> = region.steps()
+        .into_iter()
+        .map(|fraction| fraction_bounds(area, fraction))
+        .collect();
+
+    let next = match steps.iter().position(|&bounds| bounds == current) {
+        Some(index) => (index + 1) % steps.len(),
+        None => 0
+    };
+
+    steps[next]
+}
+
+/// Snaps a window within the work area of the monitor it is on.
+pub fn snap_window<S: WindowSystem>(system: &mut S, hwnd: HWND, region: &Region) -> Win32Result<()> {
+    let current = system.window_bounds(hwnd);
+    let monitors = system.monitors();
+
+    let area = match monitors::containing(&monitors, current) {
+        Some(index) => monitors[index].work_area,
+        None => return Ok(())
+    };
+
+    system.set_window_bounds(hwnd, snap(region, area, current))
+}
+
+#[cfg(test)]
+mod tests {
+    use window_system::fake::FakeWindowSystem;
+    use super::{fraction_bounds, snap, snap_window, Edge, Region};
+
+    const AREA: (i32, i32, i32, i32) = (0, 0, 1920, 1040);
+
+    #[test]
+    fn halves_and_quarters() {
+        assert_eq!((0, 0, 960, 1040), snap(&Region::Edge(Edge::Left), AREA, (0, 0, 0, 0)));
+        assert_eq!((960, 0, 1920, 1040), snap(&Region::Edge(Edge::Right), AREA, (0, 0, 0, 0)));
+        assert_eq!((0, 520, 1920, 1040), snap(&Region::Edge(Edge::Bottom), AREA, (0, 0, 0, 0)));
+        assert_eq!((960, 520, 1920, 1040), snap(&Region::Edge(Edge::BottomRight), AREA, (0, 0, 0, 0)));
+    }
+
+    #[test]
+    fn repeated_snaps_cycle_sizes() {
+        let region = Region::Edge(Edge::Left);
+
+        let half = snap(&region, AREA, (100, 100, 900, 700));
+        let third = snap(&region, AREA, half);
+        let two_thirds = snap(&region, AREA, third);
+
+        assert_eq!((0, 0, 960, 1040), half);
+        assert_eq!((0, 0, 640, 1040), third);
+        assert_eq!((0, 0, 1280, 1040), two_thirds);
+        assert_eq!(half, snap(&region, AREA, two_thirds));
+    }
+
+    #[test]
+    fn thirds_and_center() {
+        assert_eq!((640, 0, 1280, 1040), snap(&Region::Third(1), AREA, (0, 0, 0, 0)));
+        assert_eq!((1280, 0, 1920, 1040), snap(&Region::Third(2), AREA, (0, 0, 0, 0)));
+        assert_eq!((384, 208, 1536, 832), snap(&Region::Center, AREA, (0, 0, 0, 0)));
+
+        // Single-size regions stay put
+        assert_eq!((384, 208, 1536, 832), snap(&Region::Center, AREA, (384, 208, 1536, 832)));
+    }
+
+    #[test]
+    fn neighbours_share_edges() {
+        let area = (0, 0, 1001, 500);
+        let left = fraction_bounds(area, (0.0, 0.0, 1.0 / 3.0, 1.0));
+        let center = fraction_bounds(area, (1.0 / 3.0, 0.0, 1.0 / 3.0, 1.0));
+        let right = fraction_bounds(area, (2.0 / 3.0, 0.0, 1.0 / 3.0, 1.0));
+
+        assert_eq!(left.2, center.0);
+        assert_eq!(center.2, right.0);
+        assert_eq!(1001, right.2);
+    }
+
+    #[test]
+    fn uses_work_area_of_window_monitor() {
+        let mut system = FakeWindowSystem::new();
+        system.add_monitor((1920, 0, 3840, 1200), (1920, 40, 3840, 1200));
+
+        let hwnd = system.open("editor").hwnd();
+        system.window_mut(hwnd).bounds = (2000, 100, 2800, 700);
+
+        let region = Region::Custom((0.25, 0.0, 0.5, 0.5));
+        snap_window(&mut system, hwnd, &region).unwrap();
+
+        assert_eq!((2400, 40, 3360, 620), system.window(hwnd).bounds);
+    }
+}
@@ -0,0 +1,425 @@
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use winapi::windef::*;
+
+use monitors;
+use monitors::Monitor;
+use settings;
+use settings::SettingsError;
+use settings::parser;
+use settings::parser::{Document, Table, Value};
+use window_filter::WindowFilter;
+use window_system::WindowSystem;
+use window_tracking::{Placement, ShowState};
+use windows::Bounds;
+
+// Example layout file, one per name in the `layouts` folder:
+//
+//   [monitor.1]
+//   device = "\\\\.\\DISPLAY1"
+//   work_area = [0, 0, 1920, 1040]
+//
+//   [window.1]
+//   exe = "code.exe"
+//   class = "Chrome_WidgetWin_1"
+//   title = "main.rs - winman"
+//   monitor = 1
+//   bounds = [0, 0, 960, 1040]
+//   state = "maximized"
+
+const HEADER: &'static str = "\
+# Window layout saved by WinMan. Windows are recognised by exe and class,
+# and by title when several windows share those. Monitors are numbered
+# from 1, bounds are [left, top, right, bottom] and state is normal,
+# minimized or maximized.
+";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SavedMonitor {
+    pub device: String,
+    pub work_area: Bounds,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SavedWindow {
+    pub exe: String,
+    pub class: String,
+    pub title: String,
+    /// Index into `Snapshot::monitors`
+    pub monitor: usize,
+    pub placement: Placement,
+}
+
+impl fmt::Display for SavedWindow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({}) \"{}\"", self.exe, self.class, self.title)
+    }
+}
+
+/// Where every switchable window was, and on which monitor.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    pub monitors: Vec<SavedMonitor>,
+    pub windows: Vec<SavedWindow>,
+}
+
+pub struct RestoreReport {
+    pub restored: usize,
+    /// Saved windows with no open window to put there
+    pub missing: Vec<SavedWindow>,
+}
+
+impl Snapshot {
+    pub fn capture<S: WindowSystem>(system: &S, filter: &WindowFilter) -> Snapshot {
+        let monitors = system.monitors();
+        let infos: Vec<_> = system.windows()
+            .into_iter()
+            .filter_map(|hwnd| system.window_info(hwnd))
+            .collect();
+
+        let windows = filter.apply(&infos)
+            .into_iter()
+            .filter_map(|info| {
+                let placement = system.placement(info.hwnd)?;
+                let monitor = monitors::containing(&monitors, placement.bounds).unwrap_or(0);
+
+                Some(SavedWindow {
+                    exe: info.exe.clone(),
+                    class: info.class.clone(),
+                    title: info.title.clone(),
+                    monitor: monitor,
+                    placement: placement,
+                })
+            })
+            .collect();
+
+        Snapshot {
+            monitors: monitors.into_iter()
+                .map(|m| SavedMonitor {
+                    device: m.device,
+                    work_area: m.work_area,
+                })
+                .collect(),
+            windows: windows,
+        }
+    }
+
+    /// Puts open windows back where the snapshot has them.
+    ///
+    /// Each saved window takes an unused open window with the same exe and
+    /// class, preferring one with the same title. Rects move to the monitor
+    /// with the same device name, or the same position in the monitor order,
+    /// and are scaled when its work area changed size.
+    pub fn restore<S: WindowSystem>(&self, system: &mut S, filter: &WindowFilter) -> RestoreReport {
+        let monitors = system.monitors();
+        let infos: Vec<_> = system.windows()
+            .into_iter()
+            .filter_map(|hwnd| system.window_info(hwnd))
+            .collect();
+        let open = filter.apply(&infos);
+
+        let mut used: Vec<HWND> = Vec::new();
+        let mut targets: Vec<Option<HWND>> = vec![None; self.windows.len()];
+
+        // Exact titles first, so that a loose match does not take a window
+        // another entry describes exactly
+        for &exact_title in &[true, false] {
+            for (index, saved) in self.windows.iter().enumerate() {
+                if targets[index].is_some() {
+                    continue;
+                }
+
+                let found = open.iter().find(|info| {
+                    !used.contains(&info.hwnd)
+                        && info.exe.eq_ignore_ascii_case(&saved.exe)
+                        && info.class == saved.class
+                        && (!exact_title || info.title == saved.title)
+                });
+
+                if let Some(info) = found {
+                    used.push(info.hwnd);
+                    targets[index] = Some(info.hwnd);
+                }
+            }
+        }
+
+        let mut report = RestoreReport {
+            restored: 0,
+            missing: Vec::new(),
+        };
+
+        for (saved, target) in self.windows.iter().zip(targets) {
+            let hwnd = match target {
+                Some(hwnd) => hwnd,
+                None => {
+                    report.missing.push(saved.clone());
+                    continue;
+                }
+            };
+
+            let placement = Placement {
+                state: saved.placement.state,
+                bounds: self.map_bounds(saved, &monitors),
+            };
+
+            match system.set_placement(hwnd, &placement) {
+                Ok(_) => report.restored += 1,
+                Err(e) => println!("Could not restore {}: error {}", saved, e)
+            }
+        }
+
+        report
+    }
+
+    fn map_bounds(&self, saved: &SavedWindow, monitors: &[Monitor]) -> Bounds {
+        let bounds = saved.placement.bounds;
+        let from = match self.monitors.get(saved.monitor) {
+            Some(monitor) => monitor,
+            None => return bounds
+        };
+
+        let to = monitors.iter().find(|m| m.device == from.device)
+            .or_else(|| monitors.get(saved.monitor))
+            .or_else(|| monitors.iter().find(|m| m.primary));
+
+        match to {
+            Some(to) => monitors::scale(bounds, from.work_area, to.work_area),
+            None => bounds
+        }
+    }
+
+    /// The human-editable file form, read back by `from_document`.
+    pub fn to_toml(&self) -> String {
+        let mut out = HEADER.to_string();
+
+        for (index, monitor) in self.monitors.iter().enumerate() {
+            out.push_str(&format!("\n[monitor.{}]\n", index + 1));
+            out.push_str(&format!("device = {}\n", Value::String(monitor.device.clone())));
+            out.push_str(&format!("work_area = {}\n", bounds_value(monitor.work_area)));
+        }
+
+        for (index, window) in self.windows.iter().enumerate() {
+            let state = match window.placement.state {
+                ShowState::Normal => "normal",
+                ShowState::Minimized => "minimized",
+                ShowState::Maximized => "maximized",
+            };
+
+            out.push_str(&format!("\n[window.{}]\n", index + 1));
+            out.push_str(&format!("exe = {}\n", Value::String(window.exe.clone())));
+            out.push_str(&format!("class = {}\n", Value::String(window.class.clone())));
+            out.push_str(&format!("title = {}\n", Value::String(window.title.clone())));
+            out.push_str(&format!("monitor = {}\n", window.monitor + 1));
+            out.push_str(&format!("bounds = {}\n", bounds_value(window.placement.bounds)));
+            out.push_str(&format!("state = {}\n", Value::String(state.to_string())));
+        }
+
+        out
+    }
+
+    pub fn from_document(doc: &Document) -> Result<Snapshot, SettingsError> {
+        let mut snapshot = Snapshot::default();
+
+        for (_, section, table) in numbered(doc, "monitor")? {
+            snapshot.monitors.push(SavedMonitor {
+                device: get_string(table, &section, "device")?,
+                work_area: get_bounds(table, &section, "work_area")?,
+            });
+        }
+
+        for (_, section, table) in numbered(doc, "window")? {
+            let monitor = match table.get("monitor").and_then(|v| v.as_integer()) {
+                Some(n) if n >= 1 && (n as usize) <= snapshot.monitors.len() => n as usize - 1,
+                _ => return Err(invalid(&section, "monitor", "expected the number of a [monitor.N] section"))
+            };
+
+            let state = match get_string(table, &section, "state")?.as_str() {
+                "normal" => ShowState::Normal,
+                "minimized" => ShowState::Minimized,
+                "maximized" => ShowState::Maximized,
+                _ => return Err(invalid(&section, "state", "expected normal, minimized or maximized"))
+            };
+
+            snapshot.windows.push(SavedWindow {
+                exe: get_string(table, &section, "exe")?,
+                class: get_string(table, &section, "class")?,
+                title: get_string(table, &section, "title")?,
+                monitor: monitor,
+                placement: Placement {
+                    state: state,
+                    bounds: get_bounds(table, &section, "bounds")?,
+                },
+            });
+        }
+
+        Ok(snapshot)
+    }
+}
+
+/// Layouts live in a `layouts` folder next to the settings file.
+pub fn folder() -> PathBuf {
+    settings::default_path().with_file_name("layouts")
+}
+
+pub fn path(name: &str) -> Result<PathBuf, SettingsError> {
+    let valid = !name.is_empty()
+        && name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+
+    if !valid {
+        return Err(SettingsError::Invalid(format!("invalid layout name '{}', use letters, digits, '-' and '_'", name)));
+    }
+
+    Ok(folder().join(format!("{}.toml", name)))
+}
+
+pub fn save(name: &str, snapshot: &Snapshot) -> Result<PathBuf, SettingsError> {
+    let path = path(name)?;
+    save_file(&path, snapshot)?;
+
+    Ok(path)
+}
+
+pub fn load(name: &str) -> Result<Snapshot, SettingsError> {
+    load_file(&path(name)?)
+}
+
+/// Writes `snapshot` to `path`, creating its folder if needed.
+pub fn save_file(path: &Path, snapshot: &Snapshot) -> Result<(), SettingsError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(SettingsError::Io)?;
+    }
+
+    fs::write(path, snapshot.to_toml()).map_err(SettingsError::Io)
+}
+
+pub fn load_file(path: &Path) -> Result<Snapshot, SettingsError> {
+    let mut contents = String::new();
+    fs::File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(SettingsError::Io)?;
+
+    let doc = parser::parse(&contents).map_err(SettingsError::Parse)?;
+
+    Snapshot::from_document(&doc)
+}
+
+fn bounds_value(bounds: Bounds) -> Value {
+    let (l, t, r, b) = bounds;
+
+    Value::Array(vec![
+        Value::Integer(l as i64),
+        Value::Integer(t as i64),
+        Value::Integer(r as i64),
+        Value::Integer(b as i64),
+    ])
+}
+
+/// `[name.N]` sections in numeric order.
+fn numbered<'a>(doc: &'a Document, name: &str) -> Result<Vec<(usize, String, &'a Table)>, SettingsError> {
+    let mut sections = Vec::new();
+
+    for (key, table) in doc.subtables(name) {
+        let section = format!("{}.{}", name, key);
+
+        match key.parse::<usize>() {
+            Ok(n) => sections.push((n, section, table)),
+            Err(_) => return Err(SettingsError::Invalid(format!("[{}] expected a number after '{}.'", section, name)))
+        }
+    }
+
+    sections.sort_by_key(|&(n, _, _)| n);
+    Ok(sections)
+}
+
+fn get_string(table: &Table, section: &str, key: &str) -> Result<String, SettingsError> {
+    match table.get(key).and_then(|v| v.as_str()) {
+        Some(s) => Ok(s.to_string()),
+        None => Err(invalid(section, key, "expected a string"))
+    }
+}
+
+fn get_bounds(table: &Table, section: &str, key: &str) -> Result<Bounds, SettingsError> {
+    let numbers: Option<Vec<i32>> = table.get(key)
+        .and_then(|v| v.as_array())
+        .map(|items| items.iter().filter_map(|item| item.as_integer()).map(|n| n as i32).collect());
+
+    match numbers {
+        Some(ref n) if n.len() == 4 => Ok((n[0], n[1], n[2], n[3])),
+        _ => Err(invalid(section, key, "expected [left, top, right, bottom]"))
+    }
+}
+
+fn invalid(section: &str, key: &str, message: &str) -> SettingsError {
+    SettingsError::Invalid(format!("[{}] {}: {}", section, key, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use settings::parser;
+    use window_filter::WindowFilter;
+    use window_system::WindowSystem;
+    use window_system::fake::FakeWindowSystem;
+    use window_tracking::ShowState;
+    use super::Snapshot;
+
+    #[test]
+    fn file_round_trip() {
+        let mut system = FakeWindowSystem::new();
+        system.add_monitor((1920, 0, 3840, 1200), (1920, 0, 3840, 1160));
+
+        let editor = system.open("main.rs - \"winman\"").hwnd();
+        system.window_mut(editor).bounds = (1930, 10, 2900, 1000);
+        system.window_mut(editor).maximized = true;
+        system.open("C:\\Users");
+
+        let snapshot = Snapshot::capture(&system, &WindowFilter::new(0));
+        let doc = parser::parse(&snapshot.to_toml()).unwrap();
+
+        assert_eq!(2, snapshot.windows.len());
+        assert_eq!(1, snapshot.windows[1].monitor);
+        assert_eq!(snapshot, Snapshot::from_document(&doc).unwrap());
+    }
+
+    #[test]
+    fn restore_matches_by_identity_and_reports_missing() {
+        let mut system = FakeWindowSystem::new();
+        let filter = WindowFilter::new(0);
+
+        let notes = system.open("notes.txt").hwnd();
+        let todo = system.open("todo.txt").hwnd();
+        system.window_mut(notes).bounds = (0, 0, 500, 500);
+        system.window_mut(todo).bounds = (500, 0, 1000, 500);
+        system.window_mut(todo).minimized = true;
+        let snapshot = Snapshot::capture(&system, &filter);
+
+        // Reopened in a different order, one window gone
+        let mut system = FakeWindowSystem::new();
+        let todo = system.open("todo.txt").hwnd();
+        let report = snapshot.restore(&mut system, &filter);
+
+        assert_eq!(1, report.restored);
+        assert_eq!(vec!["notes.txt"], report.missing.iter().map(|w| &w.title[..]).collect::<Vec<_>>());
+        assert_eq!((500, 0, 1000, 500), system.window(todo).bounds);
+        assert!(system.window(todo).minimized);
+    }
+
+    #[test]
+    fn restore_scales_to_resized_monitor() {
+        let mut system = FakeWindowSystem::new();
+        let filter = WindowFilter::new(0);
+
+        let hwnd = system.open("editor").hwnd();
+        system.window_mut(hwnd).bounds = (0, 0, 960, 520);
+        let snapshot = Snapshot::capture(&system, &filter);
+
+        system.monitors[0].work_area = (0, 0, 2560, 1400);
+        let report = snapshot.restore(&mut system, &filter);
+
+        assert_eq!(1, report.restored);
+        assert_eq!((0, 0, 1280, 700), system.window(hwnd).bounds);
+        assert!(system.placement(hwnd).map_or(false, |p| p.state == ShowState::Normal));
+    }
+}
@@ -0,0 +1,385 @@
+use std::fmt;
+use std::str::FromStr;
+
+use winapi::windef::*;
+
+use monitors;
+use window_filter::WindowFilter;
+use window_system::WindowSystem;
+use window_tracking::{Config, WindowInfo};
+use windows::Bounds;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Layout {
+    /// The first window on the left, the others stacked on the right
+    MasterStack,
+    Columns,
+    Rows,
+    /// Each window takes half of what is left, turning clockwise
+    Spiral,
+    /// Every window fills the whole area
+    Monocle,
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "master-stack" => Ok(Layout::MasterStack),
+            "columns" => Ok(Layout::Columns),
+            "rows" => Ok(Layout::Rows),
+            "spiral" => Ok(Layout::Spiral),
+            "monocle" => Ok(Layout::Monocle),
+            _ => Err(format!("unknown layout '{}', expected master-stack, columns, rows, spiral or monocle", s))
+        }
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Layout::MasterStack => "master-stack",
+            Layout::Columns => "columns",
+            Layout::Rows => "rows",
+            Layout::Spiral => "spiral",
+            Layout::Monocle => "monocle",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LayoutOptions {
+    /// Pixels between neighbouring windows
+    pub gap: i32,
+    /// Pixels between the windows and the edges of the work area
+    pub padding: i32,
+    /// Share of the width taken by the master window
+    pub master_ratio: f64,
+}
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        LayoutOptions {
+            gap: 0,
+            padding: 0,
+            master_ratio: 0.5,
+        }
+    }
+}
+
+/// Rects for `count` windows in `area`, in window order.
+pub fn arrange(layout: Layout, count: usize, area: Bounds, options: &LayoutOptions) -> Vec<Bounds> {
+    let (l, t, r, b) = area;
+    let p = options.padding;
+    let inner = (l + p, t + p, (r - p).max(l + p), (b - p).max(t + p));
+
+    let cells = match layout {
+        Layout::Monocle => vec![inner; count],
+        Layout::Columns => split_columns(inner, count),
+        Layout::Rows => split_rows(inner, count),
+        Layout::MasterStack => master_stack(inner, count, options.master_ratio),
+        Layout::Spiral => spiral(inner, count),
+    };
+
+    if layout == Layout::Monocle {
+        return cells;
+    }
+
+    cells.into_iter()
+         .map(|cell| apply_gap(cell, inner, options.gap))
+         .collect()
+}
+
+/// Splits `len` pixels from `start` into `n` runs, rounding edges rather
+/// than sizes so the runs meet exactly.
+fn split(start: i32, len: i32, n: usize) -> Vec<(i32, i32)> {
+    (0..n)
+        .map(|i| {
+            let from = start + (len as i64 * i as i64 / n as i64) as i32;
+            let to = start + (len as i64 * (i + 1) as i64 / n as i64) as i32;
+            (from, to)
+        })
+        .collect()
+}
+
+fn split_columns(area: Bounds, n: usize) -> Vec<Bounds> {
+    let (l, t, r, b) = area;
+
+    split(l, r - l, n).into_iter()
+        .map(|(from, to)| (from, t, to, b))
+        .collect()
+}
+
+fn split_rows(area: Bounds, n: usize) -> Vec<Bounds> {
+    let (l, t, r, b) = area;
+
+    split(t, b - t, n).into_iter()
+        .map(|(from, to)| (l, from, r, to))
+        .collect()
+}
+
+fn master_stack(area: Bounds, n: usize, ratio: f64) -> Vec<Bounds> {
+    if n <= 1 {
+        return vec![area; n];
+    }
+
+    let (l, t, r, b) = area;
+    let edge = l + ((r - l) as f64 * ratio.clamp(0.1, 0.9)).round() as i32;
+
+    let mut cells = vec![(l, t, edge, b)];
+    cells.extend(split_rows((edge, t, r, b), n - 1));
+    cells
+}
+
+fn spiral(area: Bounds, n: usize) -> Vec<Bounds> {
+    let mut cells = Vec::new();
+    let (mut l, mut t, mut r, mut b) = area;
+
+    for i in 0..n {
+        if i == n - 1 {
+            cells.push((l, t, r, b));
+            break;
+        }
+
+        let (mid_x, mid_y) = ((l + r) / 2, (t + b) / 2);
+
+        match i % 4 {
+            0 => { cells.push((l, t, mid_x, b)); l = mid_x; },
+            1 => { cells.push((l, t, r, mid_y)); t = mid_y; },
+            2 => { cells.push((mid_x, t, r, b)); r = mid_x; },
+            _ => { cells.push((l, mid_y, r, b)); b = mid_y; },
+        }
+    }
+
+    cells
+}
+
+/// Shrinks the inner edges of a cell so that neighbours are `gap` apart.
+/// Edges on the border of the area are left alone, padding covers those.
+fn apply_gap(cell: Bounds, area: Bounds, gap: i32) -> Bounds {
+    let (l, t, r, b) = cell;
+    let (before, after) = (gap - gap / 2, gap / 2);
+
+    (
+        if l == area.0 { l } else { l + before },
+        if t == area.1 { t } else { t + before },
+        if r == area.2 { r } else { r - after },
+        if b == area.3 { b } else { b - after },
+    )
+}
+
+/// What a tiled group is made of.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TileTarget {
+    /// Every switchable window on a monitor, by index into `monitors::enumerate`
+    Monitor(usize),
+    /// A slot's windows, on the monitor of its current window
+    Slot(String),
+}
+
+impl fmt::Display for TileTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TileTarget::Monitor(index) => write!(f, "monitor {}", index + 1),
+            TileTarget::Slot(ref name) => write!(f, "slot {}", name),
+        }
+    }
+}
+
+struct TileGroup {
+    target: TileTarget,
+    layout: Layout,
+    /// Windows in layout order, as of the last layout
+    windows: Vec<HWND>,
+}
+
+/// Opt-in tiling. Groups are laid out again whenever windows join or leave.
+pub struct Tiler {
+    groups: Vec<TileGroup>,
+    pub options: LayoutOptions,
+}
+
+impl Tiler {
+    pub fn new(options: LayoutOptions) -> Self {
+        Tiler {
+            groups: Vec::new(),
+            options: options,
+        }
+    }
+
+    /// Starts tiling `target`, or switches its layout.
+    pub fn tile(&mut self, target: TileTarget, layout: Layout) {
+        self.untile(&target);
+        self.groups.push(TileGroup {
+            target: target,
+            layout: layout,
+            windows: Vec::new(),
+        });
+    }
+
+    /// Stops tiling `target`. Windows stay where they are.
+    pub fn untile(&mut self, target: &TileTarget) -> bool {
+        let count = self.groups.len();
+        self.groups.retain(|g| g.target != *target);
+        self.groups.len() != count
+    }
+
+    /// Lays out every group whose windows changed. `windows` are the current
+    /// top-level windows, e.g. from the registry.
+    pub fn update<S: WindowSystem>(&mut self, system: &mut S, config: &Config, filter: &WindowFilter, windows: &[WindowInfo]) {
+        if self.groups.is_empty() {
+            return;
+        }
+
+        let monitors = system.monitors();
+        let accepted: Vec<HWND> = filter.apply(windows).iter().map(|info| info.hwnd).collect();
+
+        for group in self.groups.iter_mut() {
+            let (members, monitor) = match group.target {
+                TileTarget::Monitor(index) => {
+                    let members = accepted.iter()
+                        .cloned()
+                        .filter(|&hwnd| monitors::containing(&monitors, system.window_bounds(hwnd)) == Some(index))
+                        .collect();
+
+                    (members, Some(index))
+                },
+
+                TileTarget::Slot(ref name) => {
+                    let members: Vec<HWND> = config.slots()
+                        .iter()
+                        .find(|s| s.name == *name)
+                        .map(|s| s.windows().iter().map(|w| w.hwnd()).collect())
+                        .unwrap_or_default();
+                    let members: Vec<HWND> = members.into_iter()
+                        .filter(|hwnd| accepted.contains(hwnd))
+                        .collect();
+
+                    // Tiled where the slot's current window is
+                    let monitor = members.first()
+                        .and_then(|&hwnd| monitors::containing(&monitors, system.window_bounds(hwnd)));
+
+                    (members, monitor)
+                },
+            };
+
+            // Windows keep their places, newcomers go at the end
+            let mut ordered: Vec<HWND> = group.windows
+                .iter()
+                .cloned()
+                .filter(|hwnd| members.contains(hwnd))
+                .collect();
+            for hwnd in members {
+                if !ordered.contains(&hwnd) {
+                    ordered.push(hwnd);
+                }
+            }
+
+            if ordered == group.windows {
+                continue;
+            }
+            group.windows = ordered;
+
+            let area = match monitor.and_then(|index| monitors.get(index)) {
+                Some(monitor) => monitor.work_area,
+                None => continue
+            };
+
+            println!("Tiling {} windows on {} as {}", group.windows.len(), group.target, group.layout);
+
+            let rects = arrange(group.layout, group.windows.len(), area, &self.options);
+            for (&hwnd, &rect) in group.windows.iter().zip(rects.iter()) {
+                if let Err(e) = system.set_window_bounds(hwnd, rect) {
+                    println!("Could not tile window {:?}: error {}", hwnd, e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use window_filter::WindowFilter;
+    use window_registry::WindowRegistry;
+    use window_system::fake::FakeWindowSystem;
+    use window_tracking::Config;
+    use super::{arrange, Layout, LayoutOptions, TileTarget, Tiler};
+
+    const AREA: (i32, i32, i32, i32) = (0, 0, 1200, 800);
+
+    fn no_gaps() -> LayoutOptions {
+        LayoutOptions::default()
+    }
+
+    #[test]
+    fn columns_and_rows() {
+        assert_eq!(
+            vec![(0, 0, 400, 800), (400, 0, 800, 800), (800, 0, 1200, 800)],
+            arrange(Layout::Columns, 3, AREA, &no_gaps()));
+        assert_eq!(
+            vec![(0, 0, 1200, 400), (0, 400, 1200, 800)],
+            arrange(Layout::Rows, 2, AREA, &no_gaps()));
+        assert!(arrange(Layout::Columns, 0, AREA, &no_gaps()).is_empty());
+    }
+
+    #[test]
+    fn master_stack() {
+        assert_eq!(vec![AREA], arrange(Layout::MasterStack, 1, AREA, &no_gaps()));
+        assert_eq!(
+            vec![(0, 0, 600, 800), (600, 0, 1200, 400), (600, 400, 1200, 800)],
+            arrange(Layout::MasterStack, 3, AREA, &no_gaps()));
+    }
+
+    #[test]
+    fn spiral_and_monocle() {
+        assert_eq!(
+            vec![(0, 0, 600, 800), (600, 0, 1200, 400), (900, 400, 1200, 800), (600, 400, 900, 800)],
+            arrange(Layout::Spiral, 4, AREA, &no_gaps()));
+        assert_eq!(vec![AREA, AREA], arrange(Layout::Monocle, 2, AREA, &no_gaps()));
+    }
+
+    #[test]
+    fn gap_and_padding() {
+        let options = LayoutOptions {
+            gap: 10,
+            padding: 20,
+            master_ratio: 0.5,
+        };
+
+        assert_eq!(
+            vec![(20, 20, 595, 780), (605, 20, 1180, 780)],
+            arrange(Layout::Columns, 2, AREA, &options));
+        assert_eq!(vec![(20, 20, 1180, 780)], arrange(Layout::Monocle, 1, AREA, &options));
+    }
+
+    #[test]
+    fn relayout_when_windows_join_and_leave() {
+        let mut system = FakeWindowSystem::new();
+        system.monitors.clear();
+        system.add_monitor(AREA, AREA);
+        let config = Config::new();
+        let filter = WindowFilter::new(0);
+        let mut registry = WindowRegistry::new();
+        let mut tiler = Tiler::new(LayoutOptions::default());
+
+        let editor = system.open("editor").hwnd();
+        tiler.tile(TileTarget::Monitor(0), Layout::Columns);
+        registry.refresh(&system);
+        tiler.update(&mut system, &config, &filter, registry.windows());
+        assert_eq!(AREA, system.window(editor).bounds);
+
+        let browser = system.open("browser").hwnd();
+        registry.refresh(&system);
+        tiler.update(&mut system, &config, &filter, registry.windows());
+        assert_eq!((0, 0, 600, 800), system.window(editor).bounds);
+        assert_eq!((600, 0, 1200, 800), system.window(browser).bounds);
+
+        system.windows.retain(|w| w.info.hwnd != editor);
+        registry.refresh(&system);
+        tiler.update(&mut system, &config, &filter, registry.windows());
+        assert_eq!(AREA, system.window(browser).bounds);
+    }
+}
@@ -2,10 +2,17 @@ use kernel32;
 use user32;
 use winapi::minwindef::*;
 use winapi::windef::*;
+use winapi::winerror::HRESULT;
 
 use utils;
 use utils::Win32Result;
 
+// Not in dwmapi-sys 0.1
+#[link(name = "dwmapi")]
+extern "system" {
+    fn DwmGetWindowAttribute(hwnd: HWND, attribute: DWORD, value: LPVOID, size: DWORD) -> HRESULT;
+}
+
 // https://github.com/retep998/wio-rs/blob/master/src/apc.rs
 pub fn enum_windows<T>(func: T) -> Win32Result<()>
     where T: FnMut(HWND) -> BOOL {
@@ -53,4 +60,66 @@ pub fn get_window_text(hwnd: HWND) -> Win32Result<String> {
     };
 
     Ok(utils::from_wide_slice(&buffer))
-}
\ No newline at end of file
+}
+
+pub fn get_class_name(hwnd: HWND) -> Win32Result<String> {
+    const BUFFER_LEN: usize = 256;
+    let mut buffer = [0u16; BUFFER_LEN];
+
+    unsafe {
+        if user32::GetClassNameW(hwnd, buffer.as_mut_ptr(), BUFFER_LEN as i32) == 0 {
+            return Err(kernel32::GetLastError());
+        }
+    }
+
+    Ok(utils::from_wide_slice(&buffer))
+}
+
+pub fn get_window_process_id(hwnd: HWND) -> DWORD {
+    let mut pid: DWORD = 0;
+    unsafe { user32::GetWindowThreadProcessId(hwnd, &mut pid); }
+
+    pid
+}
+
+pub fn get_process_image_name(pid: DWORD) -> Win32Result<String> {
+    // PROCESS_QUERY_LIMITED_INFORMATION is enough to read the image name of
+    // elevated processes too
+    const PROCESS_QUERY_LIMITED_INFORMATION: DWORD = 0x1000;
+    const BUFFER_LEN: usize = 1024;
+
+    unsafe {
+        let handle = kernel32::OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid);
+        if handle.is_null() {
+            return Err(kernel32::GetLastError());
+        }
+
+        let mut buffer = [0u16; BUFFER_LEN];
+        let mut len = BUFFER_LEN as DWORD;
+        let result = kernel32::QueryFullProcessImageNameW(handle, 0, buffer.as_mut_ptr(), &mut len);
+        let err = kernel32::GetLastError();
+        kernel32::CloseHandle(handle);
+
+        match result {
+            FALSE => Err(err),
+            _ => Ok(utils::from_wide_slice(&buffer[..len as usize]))
+        }
+    }
+}
+
+/// Whether the compositor hides the window even though it reports as visible.
+/// Always false before Windows 8.
+pub fn is_window_cloaked(hwnd: HWND) -> bool {
+    const DWMWA_CLOAKED: DWORD = 14;
+    let mut cloaked: DWORD = 0;
+
+    let result = unsafe {
+        DwmGetWindowAttribute(
+            hwnd,
+            DWMWA_CLOAKED,
+            &mut cloaked as *mut _ as LPVOID,
+            ::std::mem::size_of::<DWORD>() as DWORD)
+    };
+
+    result == 0 && cloaked != 0
+}
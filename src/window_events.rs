@@ -0,0 +1,132 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use kernel32;
+use user32;
+use winapi::*;
+
+use utils::Win32Result;
+
+/// Changes to top-level windows, as reported by the accessibility event hooks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HookEvent {
+    Shown(HWND),
+    Hidden(HWND),
+    Destroyed(HWND),
+    TitleChanged(HWND),
+    Focused(HWND),
+}
+
+// Not in winapi 0.2
+const EVENT_SYSTEM_FOREGROUND: DWORD = 0x0003;
+const EVENT_OBJECT_DESTROY: DWORD = 0x8001;
+const EVENT_OBJECT_SHOW: DWORD = 0x8002;
+const EVENT_OBJECT_HIDE: DWORD = 0x8003;
+const EVENT_OBJECT_NAMECHANGE: DWORD = 0x800C;
+const WINEVENT_OUTOFCONTEXT: DWORD = 0;
+const WINEVENT_SKIPOWNPROCESS: DWORD = 2;
+const OBJID_WINDOW: LONG = 0;
+const CHILDID_SELF: LONG = 0;
+const GA_ROOT: UINT = 2;
+
+// Out-of-context hooks are called on the installing thread while it pumps
+// messages, so the queue never crosses threads
+thread_local! {
+    static EVENTS: RefCell<VecDeque<HookEvent>> = RefCell::new(VecDeque::new());
+}
+
+const HOOKED_EVENTS: [DWORD; 5] = [
+    EVENT_OBJECT_SHOW,
+    EVENT_OBJECT_HIDE,
+    EVENT_OBJECT_DESTROY,
+    EVENT_OBJECT_NAMECHANGE,
+    EVENT_SYSTEM_FOREGROUND,
+];
+
+/// Keeps the window event hooks installed for as long as it lives.
+pub struct EventHook {
+    hooks: Vec<HWINEVENTHOOK>,
+}
+
+impl EventHook {
+    pub fn install() -> Win32Result<Self> {
+        let mut hook = EventHook {
+            hooks: Vec::new()
+        };
+
+        for &event in HOOKED_EVENTS.iter() {
+            let handle = unsafe {
+                user32::SetWinEventHook(
+                    event,
+                    event,
+                    0 as HMODULE,
+                    Some(hook_proc),
+                    0,
+                    0,
+                    WINEVENT_OUTOFCONTEXT | WINEVENT_SKIPOWNPROCESS)
+            };
+
+            if handle.is_null() {
+                return Err(unsafe { kernel32::GetLastError() });
+            }
+
+            hook.hooks.push(handle);
+        }
+
+        Ok(hook)
+    }
+
+    pub fn poll(&self) -> Option<HookEvent> {
+        EVENTS.with(|events| events.borrow_mut().pop_front())
+    }
+}
+
+impl Drop for EventHook {
+    fn drop(&mut self) {
+        for &hook in &self.hooks {
+            unsafe { user32::UnhookWinEvent(hook); }
+        }
+    }
+}
+
+unsafe extern "system" fn hook_proc(
+    _hook: HWINEVENTHOOK,
+    event: DWORD,
+    hwnd: HWND,
+    id_object: LONG,
+    id_child: LONG,
+    _thread: DWORD,
+    _time: DWORD) {
+
+    if hwnd.is_null() || id_object != OBJID_WINDOW || id_child != CHILDID_SELF {
+        return;
+    }
+
+    // Child controls report the same events, only keep top-level windows.
+    // A destroyed window can no longer be inspected and is passed through.
+    if event != EVENT_OBJECT_DESTROY && user32::GetAncestor(hwnd, GA_ROOT) != hwnd {
+        return;
+    }
+
+    let event = match event {
+        EVENT_OBJECT_SHOW => HookEvent::Shown(hwnd),
+        EVENT_OBJECT_HIDE => HookEvent::Hidden(hwnd),
+        EVENT_OBJECT_DESTROY => HookEvent::Destroyed(hwnd),
+        EVENT_OBJECT_NAMECHANGE => HookEvent::TitleChanged(hwnd),
+        EVENT_SYSTEM_FOREGROUND => HookEvent::Focused(hwnd),
+        _ => return
+    };
+
+    let was_empty = EVENTS.with(|events| {
+        let mut events = events.borrow_mut();
+        let was_empty = events.is_empty();
+        events.push_back(event);
+        was_empty
+    });
+
+    // Hook callbacks do not make GetMessage return, post an empty message
+    // so that the main loop wakes up and drains the queue
+    if was_empty {
+        user32::PostThreadMessageW(kernel32::GetCurrentThreadId(), WM_NULL, 0, 0);
+    }
+}
@@ -0,0 +1,214 @@
+use winapi::minwindef::*;
+use winapi::winuser::{WS_EX_APPWINDOW, WS_EX_TOOLWINDOW};
+
+use window_tracking::{WindowInfo, WindowMatch};
+
+/// Built-in tests a window must pass to be listed in the switcher.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Predicate {
+    HasTitle,
+    Visible,
+    /// Tool windows are skipped unless they ask for a taskbar button
+    NotToolWindow,
+    /// Owned windows (dialogs, palettes) are skipped unless they ask for a taskbar button
+    UnownedOrAppWindow,
+    /// Skips WinMan's own windows, like the popup
+    NotOwnProcess,
+    NotCloaked,
+}
+
+pub const DEFAULT_PREDICATES: [Predicate; 6] = [
+    Predicate::HasTitle,
+    Predicate::Visible,
+    Predicate::NotToolWindow,
+    Predicate::UnownedOrAppWindow,
+    Predicate::NotOwnProcess,
+    Predicate::NotCloaked,
+];
+
+impl Predicate {
+    pub fn test(&self, info: &WindowInfo, own_pid: DWORD) -> bool {
+        let app_window = info.ex_style & WS_EX_APPWINDOW != 0;
+
+        match *self {
+            Predicate::HasTitle => !info.title.is_empty(),
+            Predicate::Visible => info.visible,
+            Predicate::NotToolWindow => info.ex_style & WS_EX_TOOLWINDOW == 0 || app_window,
+            Predicate::UnownedOrAppWindow => info.owner.is_none() || app_window,
+            Predicate::NotOwnProcess => info.pid != own_pid,
+            Predicate::NotCloaked => !info.cloaked,
+        }
+    }
+}
+
+/// Decides which windows the switcher lists.
+///
+/// Deny rules win over allow rules, and allow rules win over the built-in
+/// predicates, so a rule can bring back e.g. a tool window the defaults hide.
+pub struct WindowFilter {
+    predicates: Vec<Predicate>,
+    allow: Vec<WindowMatch>,
+    deny: Vec<WindowMatch>,
+    own_pid: DWORD,
+}
+
+impl WindowFilter {
+    pub fn new(own_pid: DWORD) -> Self {
+        WindowFilter {
+            predicates: DEFAULT_PREDICATES.to_vec(),
+            allow: Vec::new(),
+            deny: Vec::new(),
+            own_pid: own_pid,
+        }
+    }
+
+    pub fn allow(&mut self, rule: WindowMatch) {
+        self.allow.push(rule);
+    }
+
+    pub fn deny(&mut self, rule: WindowMatch) {
+        self.deny.push(rule);
+    }
+
+    pub fn accepts(&self, info: &WindowInfo) -> bool {
+        if self.deny.iter().any(|rule| rule.matches(info)) {
+            return false;
+        }
+
+        if self.allow.iter().any(|rule| rule.matches(info)) {
+            return true;
+        }
+
+        self.predicates
+            .iter()
+            .all(|predicate| predicate.test(info, self.own_pid))
+    }
+
+    /// Accepted windows, in the order given.
+    pub fn apply<'a>(&self, windows: &'a [WindowInfo]) -> Vec<&'a WindowInfo> {
+        windows.iter()
+               .filter(|info| self.accepts(info))
+               .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winapi::winuser::{WS_EX_APPWINDOW, WS_EX_TOOLWINDOW};
+
+    use window_registry::WindowRegistry;
+    use window_system::fake::{FakeWindowSystem, FAKE_PID};
+    use window_tracking::WindowMatch;
+    use super::WindowFilter;
+
+    fn titles(filter: &WindowFilter, system: &FakeWindowSystem) -> Vec<String> {
+        let mut registry = WindowRegistry::new();
+        registry.refresh(system);
+
+        filter.apply(registry.windows())
+              .into_iter()
+              .map(|info| info.title.clone())
+              .collect()
+    }
+
+    #[test]
+    fn lists_app_windows_in_z_order() {
+        let mut system = FakeWindowSystem::new();
+        system.open("editor");
+        system.open("browser");
+
+        let filter = WindowFilter::new(0);
+
+        assert_eq!(vec!["browser", "editor"], titles(&filter, &system));
+    }
+
+    #[test]
+    fn builtin_predicates() {
+        let mut system = FakeWindowSystem::new();
+        let owner = system.open("editor").hwnd();
+
+        system.open("");
+
+        let hwnd_hidden = system.open("hidden").hwnd();
+        system.window_mut(hwnd_hidden).info.visible = false;
+
+        let hwnd_tool = system.open("palette").hwnd();
+        system.window_mut(hwnd_tool).info.ex_style = WS_EX_TOOLWINDOW;
+
+        let hwnd_dialog = system.open("find").hwnd();
+        system.window_mut(hwnd_dialog).info.owner = Some(owner);
+
+        let hwnd_cloaked = system.open("suspended").hwnd();
+        system.window_mut(hwnd_cloaked).info.cloaked = true;
+
+        let filter = WindowFilter::new(0);
+
+        assert_eq!(vec!["editor"], titles(&filter, &system));
+    }
+
+    #[test]
+    fn app_window_style_overrides_tool_and_owner() {
+        let mut system = FakeWindowSystem::new();
+        let owner = system.open("editor").hwnd();
+
+        let hwnd = system.open("detached").hwnd();
+        system.window_mut(hwnd).info.owner = Some(owner);
+        system.window_mut(hwnd).info.ex_style = WS_EX_TOOLWINDOW | WS_EX_APPWINDOW;
+
+        let filter = WindowFilter::new(0);
+
+        assert_eq!(vec!["detached", "editor"], titles(&filter, &system));
+    }
+
+    #[test]
+    fn skips_own_process() {
+        let mut system = FakeWindowSystem::new();
+        system.open("popup");
+
+        let filter = WindowFilter::new(FAKE_PID);
+
+        assert!(titles(&filter, &system).is_empty());
+    }
+
+    #[test]
+    fn user_rules() {
+        let mut system = FakeWindowSystem::new();
+        system.open("editor");
+
+        let hwnd_tray = system.open("tray").hwnd();
+        system.window_mut(hwnd_tray).info.class = "Shell_TrayWnd".to_string();
+
+        let hwnd_tool = system.open("palette").hwnd();
+        system.window_mut(hwnd_tool).info.ex_style = WS_EX_TOOLWINDOW;
+        system.window_mut(hwnd_tool).info.exe = "paint.exe".to_string();
+
+        let mut filter = WindowFilter::new(0);
+        filter.deny(WindowMatch {
+            class: Some("Shell_TrayWnd".to_string()),
+            ..WindowMatch::default()
+        });
+        filter.allow(WindowMatch {
+            exe: Some("PAINT.EXE".to_string()),
+            ..WindowMatch::default()
+        });
+
+        assert_eq!(vec!["palette", "editor"], titles(&filter, &system));
+    }
+
+    #[test]
+    fn deny_wins_over_allow() {
+        let mut system = FakeWindowSystem::new();
+        system.open("editor");
+
+        let rule = WindowMatch {
+            title: Some("edit".to_string()),
+            ..WindowMatch::default()
+        };
+
+        let mut filter = WindowFilter::new(0);
+        filter.allow(rule.clone());
+        filter.deny(rule);
+
+        assert!(titles(&filter, &system).is_empty());
+    }
+}
@@ -0,0 +1,260 @@
+use std::sync::mpsc;
+
+use winapi::windef::*;
+
+use window_events::HookEvent;
+use window_system::WindowSystem;
+use window_tracking::WindowInfo;
+
+/// Changes to the set of top-level windows.
+#[derive(Clone, Debug)]
+pub enum WindowEvent {
+    Created(WindowInfo),
+    Destroyed(HWND),
+    /// A known window became visible again
+    Shown(WindowInfo),
+    Hidden(HWND),
+    TitleChanged(WindowInfo),
+    Focused(HWND),
+}
+
+/// The current top-level windows, most recently focused first.
+///
+/// Kept up to date either incrementally from hook events, or by diffing
+/// snapshots of the desktop when hooks are not available. Both produce the
+/// same `WindowEvent`s for subscribers.
+pub struct WindowRegistry {
+    windows: Vec<WindowInfo>,
+    focused: Option<HWND>,
+    subscribers: Vec<mpsc::Sender<WindowEvent>>,
+}
+
+impl WindowRegistry {
+    pub fn new() -> Self {
+        WindowRegistry {
+            windows: Vec::new(),
+            focused: None,
+            subscribers: Vec::new(),
+        }
+    }
+
+    pub fn windows(&self) -> &[WindowInfo] {
+        &self.windows
+    }
+
+    pub fn focused(&self) -> Option<HWND> {
+        self.focused
+    }
+
+    /// Events from now on are delivered to the returned receiver.
+    pub fn subscribe(&mut self) -> mpsc::Receiver<WindowEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.push(tx);
+        rx
+    }
+
+    /// Applies a single hook event, looking up only the window it concerns.
+    pub fn handle<S: WindowSystem>(&mut self, system: &S, event: HookEvent) {
+        match event {
+            HookEvent::Destroyed(hwnd) => self.remove(hwnd),
+
+            HookEvent::Focused(hwnd) => {
+                if let Some(info) = system.window_info(hwnd) {
+                    self.update(info);
+                }
+
+                if let Some(index) = self.index_of(hwnd) {
+                    let info = self.windows.remove(index);
+                    self.windows.insert(0, info);
+                }
+
+                if self.focused != Some(hwnd) {
+                    self.focused = Some(hwnd);
+                    self.publish(WindowEvent::Focused(hwnd));
+                }
+            },
+
+            HookEvent::Shown(hwnd) | HookEvent::Hidden(hwnd) | HookEvent::TitleChanged(hwnd) => {
+                match system.window_info(hwnd) {
+                    Some(info) => self.update(info),
+                    None => self.remove(hwnd)
+                }
+            },
+        }
+    }
+
+    /// Replaces the registry with a fresh snapshot of the desktop and
+    /// publishes the differences.
+    pub fn refresh<S: WindowSystem>(&mut self, system: &S) {
+        let snapshot: Vec<WindowInfo> = system.windows()
+            .into_iter()
+            .filter_map(|hwnd| system.window_info(hwnd))
+            .collect();
+
+        let gone: Vec<HWND> = self.windows
+            .iter()
+            .map(|w| w.hwnd)
+            .filter(|&hwnd| !snapshot.iter().any(|w| w.hwnd == hwnd))
+            .collect();
+
+        for hwnd in gone {
+            self.remove(hwnd);
+        }
+
+        for info in &snapshot {
+            self.update(info.clone());
+        }
+
+        // Snapshots are in z-order, which is the best guess at focus order
+        self.windows = snapshot;
+
+        let focused = system.foreground_window().map(|w| w.hwnd());
+        if focused != self.focused {
+            self.focused = focused;
+
+            if let Some(hwnd) = focused {
+                self.publish(WindowEvent::Focused(hwnd));
+            }
+        }
+    }
+
+    fn index_of(&self, hwnd: HWND) -> Option<usize> {
+        self.windows.iter().position(|w| w.hwnd == hwnd)
+    }
+
+    fn update(&mut self, info: WindowInfo) {
+        let index = match self.index_of(info.hwnd) {
+            Some(index) => index,
+            None => {
+                self.windows.insert(0, info.clone());
+                self.publish(WindowEvent::Created(info));
+                return;
+            }
+        };
+
+        let old = ::std::mem::replace(&mut self.windows[index], info.clone());
+
+        if old.visible != info.visible {
+            if info.visible {
+                self.publish(WindowEvent::Shown(info.clone()));
+            } else {
+                self.publish(WindowEvent::Hidden(info.hwnd));
+            }
+        }
+
+        if old.title != info.title {
+            self.publish(WindowEvent::TitleChanged(info));
+        }
+    }
+
+    fn remove(&mut self, hwnd: HWND) {
+        if let Some(index) = self.index_of(hwnd) {
+            self.windows.remove(index);
+
+            if self.focused == Some(hwnd) {
+                self.focused = None;
+            }
+
+            self.publish(WindowEvent::Destroyed(hwnd));
+        }
+    }
+
+    fn publish(&mut self, event: WindowEvent) {
+        // Subscribers that went away are dropped
+        self.subscribers.retain(|tx| tx.send(event.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::Receiver;
+
+    use window_events::HookEvent;
+    use window_system::fake::FakeWindowSystem;
+    use super::{WindowEvent, WindowRegistry};
+
+    fn drain(rx: &Receiver<WindowEvent>) -> Vec<String> {
+        rx.try_iter()
+          .map(|event| match event {
+              WindowEvent::Created(info) => format!("created {}", info.title),
+              WindowEvent::Destroyed(hwnd) => format!("destroyed {}", hwnd as usize),
+              WindowEvent::Shown(info) => format!("shown {}", info.title),
+              WindowEvent::Hidden(hwnd) => format!("hidden {}", hwnd as usize),
+              WindowEvent::TitleChanged(info) => format!("title {}", info.title),
+              WindowEvent::Focused(hwnd) => format!("focused {}", hwnd as usize),
+          })
+          .collect()
+    }
+
+    #[test]
+    fn incremental_updates() {
+        let mut system = FakeWindowSystem::new();
+        let mut registry = WindowRegistry::new();
+        let rx = registry.subscribe();
+
+        let editor = system.open("editor").hwnd();
+        registry.handle(&system, HookEvent::Shown(editor));
+        let browser = system.open("browser").hwnd();
+        registry.handle(&system, HookEvent::Shown(browser));
+        registry.handle(&system, HookEvent::Focused(browser));
+
+        system.window_mut(editor).info.title = "editor - main.rs".to_string();
+        registry.handle(&system, HookEvent::TitleChanged(editor));
+        registry.handle(&system, HookEvent::Focused(editor));
+
+        system.windows.retain(|w| w.info.hwnd != browser);
+        registry.handle(&system, HookEvent::Destroyed(browser));
+
+        assert_eq!(drain(&rx), vec![
+            "created editor", "created browser", "focused 2",
+            "title editor - main.rs", "focused 1", "destroyed 2",
+        ]);
+        assert_eq!(registry.windows().len(), 1);
+        assert_eq!(registry.focused(), Some(editor));
+    }
+
+    #[test]
+    fn snapshot_diffing() {
+        let mut system = FakeWindowSystem::new();
+        let mut registry = WindowRegistry::new();
+
+        let editor = system.open("editor").hwnd();
+        let browser = system.open("browser").hwnd();
+        registry.refresh(&system);
+
+        let rx = registry.subscribe();
+
+        system.windows.retain(|w| w.info.hwnd != browser);
+        system.window_mut(editor).info.title = "editor - main.rs".to_string();
+        system.open("terminal");
+        registry.refresh(&system);
+
+        assert_eq!(drain(&rx), vec![
+            "destroyed 2", "created terminal", "title editor - main.rs", "focused 3",
+        ]);
+
+        let titles: Vec<&str> = registry.windows().iter().map(|w| &w.title[..]).collect();
+        assert_eq!(titles, vec!["terminal", "editor - main.rs"]);
+
+        // Nothing changed, nothing published
+        registry.refresh(&system);
+        assert!(drain(&rx).is_empty());
+    }
+
+    #[test]
+    fn visibility_changes() {
+        let mut system = FakeWindowSystem::new();
+        let mut registry = WindowRegistry::new();
+
+        let hwnd = system.open("editor").hwnd();
+        registry.refresh(&system);
+        let rx = registry.subscribe();
+
+        system.window_mut(hwnd).info.visible = false;
+        registry.handle(&system, HookEvent::Hidden(hwnd));
+        system.window_mut(hwnd).info.visible = true;
+        registry.handle(&system, HookEvent::Shown(hwnd));
+
+        assert_eq!(drain(&rx), vec!["hidden 1", "shown editor"]);
+    }
+}
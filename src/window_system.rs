@@ -0,0 +1,324 @@
+use user32;
+use winapi::minwindef::*;
+use winapi::windef::*;
+
+use monitors;
+use monitors::Monitor;
+use utils::Win32Result;
+use utils::api_wrappers;
+use window_tracking;
+use window_tracking::{Placement, Window, WindowInfo};
+use windows;
+use windows::Bounds;
+
+/// The desktop operations slot handling needs, so that it can run against
+/// a fake desktop in tests.
+pub trait WindowSystem {
+    /// Top-level windows in z-order, topmost first
+    fn windows(&self) -> Vec<HWND>;
+    fn window_info(&self, hwnd: HWND) -> Option<WindowInfo>;
+    fn foreground_window(&self) -> Option<Window>;
+    fn set_foreground_window(&mut self, hwnd: HWND) -> Win32Result<()>;
+    /// Whether `hwnd` still names a window
+    fn is_window(&self, hwnd: HWND) -> bool;
+    fn window_bounds(&self, hwnd: HWND) -> Bounds;
+    fn set_window_bounds(&mut self, hwnd: HWND, bounds: Bounds) -> Win32Result<()>;
+    fn set_topmost(&mut self, hwnd: HWND, topmost: bool) -> Win32Result<()>;
+    fn minimize(&mut self, hwnd: HWND);
+    fn set_visible(&mut self, hwnd: HWND, visible: bool);
+    fn placement(&self, hwnd: HWND) -> Option<Placement>;
+    fn set_placement(&mut self, hwnd: HWND, placement: &Placement) -> Win32Result<()>;
+    fn monitors(&self) -> Vec<Monitor>;
+    /// Mouse position in screen coordinates
+    fn cursor_position(&self) -> Option<(i32, i32)>;
+}
+
+pub struct Win32WindowSystem;
+
+impl WindowSystem for Win32WindowSystem {
+    fn windows(&self) -> Vec<HWND> {
+        let mut windows = Vec::new();
+
+        api_wrappers::enum_windows(|hwnd| {
+            windows.push(hwnd);
+            TRUE
+        }).expect("Callback does not SetLastError");
+
+        windows
+    }
+
+    fn window_info(&self, hwnd: HWND) -> Option<WindowInfo> {
+        window_tracking::get_window_info(hwnd)
+    }
+
+    fn foreground_window(&self) -> Option<Window> {
+        window_tracking::get_foreground_window().ok()
+    }
+
+    fn set_foreground_window(&mut self, hwnd: HWND) -> Win32Result<()> {
+        window_tracking::set_foreground_window(hwnd)
+    }
+
+    fn is_window(&self, hwnd: HWND) -> bool {
+        unsafe { user32::IsWindow(hwnd) != FALSE }
+    }
+
+    fn window_bounds(&self, hwnd: HWND) -> Bounds {
+        windows::get_window_bounds(hwnd)
+    }
+
+    fn set_window_bounds(&mut self, hwnd: HWND, bounds: Bounds) -> Win32Result<()> {
+        window_tracking::set_window_bounds(hwnd, bounds)
+    }
+
+    fn set_topmost(&mut self, hwnd: HWND, topmost: bool) -> Win32Result<()> {
+        window_tracking::set_topmost(hwnd, topmost)
+    }
+
+    fn minimize(&mut self, hwnd: HWND) {
+        window_tracking::minimize_window(hwnd)
+    }
+
+    fn set_visible(&mut self, hwnd: HWND, visible: bool) {
+        window_tracking::show_window(hwnd, visible)
+    }
+
+    fn placement(&self, hwnd: HWND) -> Option<Placement> {
+        window_tracking::get_window_placement(hwnd).ok()
+    }
+
+    fn set_placement(&mut self, hwnd: HWND, placement: &Placement) -> Win32Result<()> {
+        window_tracking::set_window_placement(hwnd, placement)
+    }
+
+    fn monitors(&self) -> Vec<Monitor> {
+        monitors::enumerate()
+    }
+
+    fn cursor_position(&self) -> Option<(i32, i32)> {
+        monitors::cursor_position()
+    }
+}
+
+#[cfg(test)]
+pub mod fake {
+    use winapi::windef::*;
+
+    use monitors;
+    use monitors::Monitor;
+    use utils::Win32Result;
+    use window_tracking::{Placement, ShowState, Window, WindowInfo};
+    use windows::Bounds;
+    use super::WindowSystem;
+
+    const ERROR_ACCESS_DENIED: u32 = 5;
+    const ERROR_INVALID_WINDOW_HANDLE: u32 = 1400;
+    pub const FAKE_PID: u32 = 1000;
+
+    pub struct FakeWindow {
+        pub info: WindowInfo,
+        pub bounds: Bounds,
+        pub topmost: bool,
+        pub minimized: bool,
+        pub maximized: bool,
+    }
+
+    /// An in-memory desktop. Windows are numbered from 1 in opening order.
+    /// It starts with a single 1920x1080 monitor with a 40 pixel taskbar.
+    pub struct FakeWindowSystem {
+        /// Windows in z-order, topmost first
+        pub windows: Vec<FakeWindow>,
+        pub foreground: Option<HWND>,
+        pub monitors: Vec<Monitor>,
+        pub cursor: Option<(i32, i32)>,
+        /// Refuses to change the foreground window, like Windows does when
+        /// another program has the user's attention
+        pub foreground_locked: bool,
+        next_hwnd: usize,
+    }
+
+    impl FakeWindowSystem {
+        pub fn new() -> Self {
+            let mut system = FakeWindowSystem {
+                windows: Vec::new(),
+                foreground: None,
+                monitors: Vec::new(),
+                cursor: None,
+                foreground_locked: false,
+                next_hwnd: 1,
+            };
+
+            system.add_monitor((0, 0, 1920, 1080), (0, 0, 1920, 1040));
+            system
+        }
+
+        /// Plugs in another monitor, named `\\.\DISPLAYn` in order.
+        pub fn add_monitor(&mut self, bounds: Bounds, work_area: Bounds) {
+            let device = format!("\\\\.\\DISPLAY{}", self.monitors.len() + 1);
+
+            self.monitors.push(Monitor {
+                device: device,
+                bounds: bounds,
+                work_area: work_area,
+                dpi: 96,
+                primary: self.monitors.is_empty(),
+            });
+        }
+
+        /// Sets the scaling of a monitor, 144 for 150%.
+        pub fn set_dpi(&mut self, index: usize, dpi: u32) {
+            self.monitors[index].dpi = dpi;
+        }
+
+        /// Unplugs a monitor. Like Windows, windows on it move to the primary.
+        pub fn remove_monitor(&mut self, index: usize) {
+            let orphans: Vec<HWND> = self.windows.iter()
+                .filter(|w| monitors::containing(&self.monitors, w.bounds) == Some(index))
+                .map(|w| w.info.hwnd)
+                .collect();
+
+            let removed = self.monitors.remove(index);
+            let primary = match self.monitors.iter().find(|m| m.primary) {
+                Some(primary) => primary.work_area,
+                None => return
+            };
+
+            for hwnd in orphans {
+                let window = self.window_mut(hwnd);
+                window.bounds = monitors::translate(window.bounds, removed.work_area, primary);
+            }
+        }
+
+        /// Opens a visible application window and brings it to the foreground.
+        pub fn open(&mut self, title: &str) -> Window {
+            let hwnd = self.next_hwnd as HWND;
+            self.next_hwnd += 1;
+
+            self.windows.insert(0, FakeWindow {
+                info: WindowInfo {
+                    hwnd: hwnd,
+                    title: title.to_string(),
+                    class: "FakeWindow".to_string(),
+                    exe: "fake.exe".to_string(),
+                    pid: FAKE_PID,
+                    visible: true,
+                    ex_style: 0,
+                    owner: None,
+                    cloaked: false,
+                },
+                bounds: (100, 100, 900, 700),
+                topmost: false,
+                minimized: false,
+                maximized: false,
+            });
+            self.foreground = Some(hwnd);
+
+            Window::new(hwnd, title.to_string())
+        }
+
+        pub fn window_mut(&mut self, hwnd: HWND) -> &mut FakeWindow {
+            self.windows
+                .iter_mut()
+                .find(|w| w.info.hwnd == hwnd)
+                .expect("No such fake window")
+        }
+
+        pub fn window(&self, hwnd: HWND) -> &FakeWindow {
+            self.windows
+                .iter()
+                .find(|w| w.info.hwnd == hwnd)
+                .expect("No such fake window")
+        }
+    }
+
+    impl WindowSystem for FakeWindowSystem {
+        fn windows(&self) -> Vec<HWND> {
+            self.windows.iter().map(|w| w.info.hwnd).collect()
+        }
+
+        fn window_info(&self, hwnd: HWND) -> Option<WindowInfo> {
+            self.windows
+                .iter()
+                .find(|w| w.info.hwnd == hwnd)
+                .map(|w| w.info.clone())
+        }
+
+        fn foreground_window(&self) -> Option<Window> {
+            self.foreground
+                .and_then(|hwnd| self.window_info(hwnd))
+                .map(|w| Window::new(w.hwnd, w.title))
+        }
+
+        fn set_foreground_window(&mut self, hwnd: HWND) -> Win32Result<()> {
+            match self.windows.iter().position(|w| w.info.hwnd == hwnd) {
+                Some(_) if self.foreground_locked => Err(ERROR_ACCESS_DENIED),
+                Some(index) => {
+                    let mut window = self.windows.remove(index);
+                    window.minimized = false;
+                    self.windows.insert(0, window);
+                    self.foreground = Some(hwnd);
+                    Ok(())
+                },
+                None => Err(ERROR_INVALID_WINDOW_HANDLE)
+            }
+        }
+
+        fn is_window(&self, hwnd: HWND) -> bool {
+            self.window_info(hwnd).is_some()
+        }
+
+        fn window_bounds(&self, hwnd: HWND) -> Bounds {
+            self.window(hwnd).bounds
+        }
+
+        fn set_window_bounds(&mut self, hwnd: HWND, bounds: Bounds) -> Win32Result<()> {
+            self.window_mut(hwnd).bounds = bounds;
+            Ok(())
+        }
+
+        fn set_topmost(&mut self, hwnd: HWND, topmost: bool) -> Win32Result<()> {
+            self.window_mut(hwnd).topmost = topmost;
+            Ok(())
+        }
+
+        fn minimize(&mut self, hwnd: HWND) {
+            self.window_mut(hwnd).minimized = true;
+        }
+
+        fn set_visible(&mut self, hwnd: HWND, visible: bool) {
+            self.window_mut(hwnd).info.visible = visible;
+        }
+
+        fn placement(&self, hwnd: HWND) -> Option<Placement> {
+            self.windows
+                .iter()
+                .find(|w| w.info.hwnd == hwnd)
+                .map(|w| Placement {
+                    state: if w.minimized {
+                        ShowState::Minimized
+                    } else if w.maximized {
+                        ShowState::Maximized
+                    } else {
+                        ShowState::Normal
+                    },
+                    bounds: w.bounds,
+                })
+        }
+
+        fn set_placement(&mut self, hwnd: HWND, placement: &Placement) -> Win32Result<()> {
+            let window = self.window_mut(hwnd);
+            window.bounds = placement.bounds;
+            window.minimized = placement.state == ShowState::Minimized;
+            window.maximized = placement.state == ShowState::Maximized;
+            Ok(())
+        }
+
+        fn monitors(&self) -> Vec<Monitor> {
+            self.monitors.clone()
+        }
+
+        fn cursor_position(&self) -> Option<(i32, i32)> {
+            self.cursor
+        }
+    }
+}
@@ -1,12 +1,23 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::VecDeque;
 
 use kernel32;
 use user32;
 use winapi::minwindef::*;
 use winapi::windef::*;
 
+use constants::*;
+use fuzzy;
 use utils;
 use utils::Win32Result;
+use monitors;
+use utils::api_wrappers;
+use windows::Bounds;
+
+// Not in user32-sys 0.1
+#[link(name = "user32")]
+extern "system" {
+	fn SetWindowPlacement(hwnd: HWND, placement: *const winapi::winuser::WINDOWPLACEMENT) -> BOOL;
+}
 
 #[derive(Clone)]
 pub struct Window {
@@ -32,8 +43,105 @@ impl Window {
 			None => None
 		}
 	}
+
+	/// Returns whether the title changed.
+	pub fn set_title(&mut self, title: &str) -> bool {
+		if self.title() == Some(title) {
+			return false;
+		}
+
+		self.title = Some(title.to_string());
+		true
+	}
+
+	/// Re-reads the title from the live window. Returns whether it changed.
+	/// The stored title is kept when the window is gone or has no title.
+	pub fn refresh(&mut self) -> bool {
+		match utils::api_wrappers::get_window_text(self.hwnd) {
+			Ok(title) => self.set_title(&title),
+			Err(_) => false
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShowState {
+	Normal,
+	Minimized,
+	Maximized,
+}
+
+/// Where a window goes when restored, and how it is shown.
+/// Minimized and maximized windows keep their restored bounds here.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Placement {
+	pub state: ShowState,
+	pub bounds: Bounds,
+}
+
+/// Snapshot of the properties used to recognise and filter top-level windows.
+#[derive(Clone, Debug)]
+pub struct WindowInfo {
+	pub hwnd: HWND,
+	pub title: String,
+	pub class: String,
+	/// File name of the owning process, e.g. `code.exe`
+	pub exe: String,
+	pub pid: DWORD,
+	pub visible: bool,
+	pub ex_style: DWORD,
+	pub owner: Option<HWND>,
+	/// Hidden by the compositor, e.g. suspended UWP apps and windows on other virtual desktops
+	pub cloaked: bool,
 }
 
+/// Identity rule used to recognise a slot's window among all top-level windows.
+/// Every field that is set must match.
+#[derive(Clone, Debug, Default)]
+pub struct WindowMatch {
+	/// File name of the owning process, e.g. `code.exe`
+	pub exe: Option<String>,
+	/// Window class name
+	pub class: Option<String>,
+	/// Case insensitive regex on the window title
+	pub title: Option<String>,
+}
+
+impl WindowMatch {
+	pub fn is_match(&self, hwnd: HWND) -> bool {
+		match get_window_info(hwnd) {
+			Some(info) => self.matches(&info),
+			None => false
+		}
+	}
+
+	pub fn matches(&self, info: &WindowInfo) -> bool {
+		if let Some(ref exe) = self.exe {
+			if !info.exe.eq_ignore_ascii_case(exe) {
+				return false;
+			}
+		}
+
+		if let Some(ref class) = self.class {
+			if info.class != *class {
+				return false;
+			}
+		}
+
+		if let Some(ref title) = self.title {
+			match fuzzy::Finder::new(title) {
+				Ok(finder) => if !finder.is_match(&info.title) {
+					return false;
+				},
+				Err(_) => return false
+			}
+		}
+
+		true
+	}
+}
+
+#[derive(Clone)]
 pub struct WindowSet {
 	windows: VecDeque<Window>
 }
@@ -73,36 +181,451 @@ impl WindowSet {
 			None => None
 		}
 	}
+
+	pub fn front(&self) -> Option<&Window> {
+		self.windows.front()
+	}
+
+	pub fn contains(&self, window: &Window) -> bool {
+		self.windows.iter().any(|w| w.hwnd == window.hwnd)
+	}
+
+	/// Adds windows at the back of the set, keeping the current window in front.
+	pub fn append(&mut self, other: WindowSet) {
+		for window in other.windows {
+			if !self.contains(&window) {
+				self.windows.push_back(window);
+			}
+		}
+	}
+
+	/// Moves `window` by `offset` places in cycling order.
+	/// Negative offsets make it come around sooner.
+	pub fn shift(&mut self, window: &Window, offset: isize) -> bool {
+		let index = match self.windows.iter().position(|w| w.hwnd == window.hwnd) {
+			Some(index) => index,
+			None => return false
+		};
+
+		// Cycling walks the set from the back, so the offset runs against the index
+		let last = self.windows.len() as isize - 1;
+		let target = (index as isize - offset).max(0).min(last) as usize;
+
+		if let Some(window) = self.windows.remove(index) {
+			self.windows.insert(target, window);
+		}
+
+		true
+	}
+
+	pub fn iter<'a>(&'a self) -> ::std::collections::vec_deque::Iter<'a, Window> {
+		self.windows.iter()
+	}
+
+	fn iter_mut<'a>(&'a mut self) -> ::std::collections::vec_deque::IterMut<'a, Window> {
+		self.windows.iter_mut()
+	}
+
+	/// Windows in the set ordered bottom-most first, so that raising them
+	/// in sequence leaves the most recently focused window on top.
+	pub fn z_order(&self) -> Vec<Window> {
+		self.windows
+		    .iter()
+		    .rev()
+		    .cloned()
+		    .collect()
+	}
+}
+
+/// Sets are equal when they hold the same windows in the same order.
+impl PartialEq for WindowSet {
+	fn eq(&self, other: &WindowSet) -> bool {
+		self.windows.len() == other.windows.len()
+			&& self.windows.iter().zip(other.windows.iter()).all(|(a, b)| a.hwnd == b.hwnd)
+	}
+}
+
+/// What a slot's key does when one of its windows already has focus.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SlotMode {
+	/// Switch to the next window in the slot
+	Cycle,
+	/// Minimize the window and go back to the one used before it
+	Minimize,
+	/// Go back to the window used before, leaving this one where it is
+	Previous,
+}
+
+/// A named group of windows, optionally bound to a key.
+/// Like vim marks, Alt+Shift+key marks a window and Alt+key jumps to it.
+pub struct Slot {
+	pub name: String,
+	pub key: Option<UINT>,
+	pub mode: SlotMode,
+	pub description: Option<String>,
+	/// Free-form hint for UIs that list slots, e.g. an icon path
+	pub icon: Option<String>,
+	windows: WindowSet,
+}
+
+impl Slot {
+	pub fn new(name: &str) -> Self {
+		Slot {
+			name: name.to_string(),
+			key: None,
+			mode: SlotMode::Cycle,
+			description: None,
+			icon: None,
+			windows: WindowSet::new(),
+		}
+	}
+
+	pub fn windows(&self) -> &WindowSet {
+		&self.windows
+	}
+}
+
+/// Number of slot edits that can be undone.
+const HISTORY_LIMIT: usize = 100;
+
+/// A reversible slot edit: the window sets of the affected slots before and after.
+/// A slot that did not exist yet is recorded as `None`.
+struct Edit {
+	before: Vec<(String, Option<WindowSet>)>,
+	after: Vec<(String, Option<WindowSet>)>,
 }
 
 pub struct Config {
-    windows: HashMap<UINT, WindowSet>
+    slots: Vec<Slot>,
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
 }
 
 impl Config {
+	/// Starts out with slots "0" to "9" bound to the digit keys.
 	pub fn new() -> Self {
+		let slots = (0..10)
+			.map(|n| {
+				let mut slot = Slot::new(&n.to_string());
+				slot.key = Some(VK_0 + n);
+				slot
+			})
+			.collect();
+
 		Config {
-			windows: HashMap::new()
+			slots: slots,
+			undo: VecDeque::new(),
+			redo: Vec::new(),
+		}
+	}
+
+	/// Adds a slot, or replaces the key, mode and description of the existing slot with the same name.
+	/// Window sets are kept when a slot is redefined.
+	pub fn define_slot(&mut self, slot: Slot) {
+		match self.slots.iter().position(|s| s.name == slot.name) {
+			Some(index) => {
+				let existing = &mut self.slots[index];
+				existing.key = slot.key;
+				existing.mode = slot.mode;
+				existing.description = slot.description;
+				existing.icon = slot.icon;
+			},
+			None => self.slots.push(slot)
+		}
+	}
+
+	pub fn slots(&self) -> &[Slot] {
+		&self.slots
+	}
+
+	/// Tracking a window on an unknown name creates a slot without a key.
+	pub fn track_window(&mut self, name: &str, window: Window) {
+		self.edit(vec![name.to_string()], |config| {
+			config.add_window(name, window);
+		});
+	}
+
+	pub fn get_windows(&mut self, name: &str) -> Option<&mut WindowSet> {
+		self.slots
+		    .iter_mut()
+		    .find(|s| s.name == name)
+		    .map(|s| &mut s.windows)
+	}
+
+	pub fn clear_windows(&mut self, name: &str) {
+		self.edit(vec![name.to_string()], |config| {
+			if let Some(window_set) = config.get_windows(name) {
+				*window_set = WindowSet::new();
+			}
+		});
+	}
+
+	/// Moves the current window of `from` to the front of `to`.
+	pub fn move_window(&mut self, from: &str, to: &str) -> Option<Window> {
+		self.edit(vec![from.to_string(), to.to_string()], |config| {
+			let window = match config.get_windows(from) {
+				Some(window_set) => match window_set.front().cloned() {
+					Some(window) => {
+						window_set.remove(&window);
+						window
+					},
+					None => return None
+				},
+				None => return None
+			};
+
+			config.add_window(to, window.clone());
+
+			Some(window)
+		})
+	}
+
+	pub fn swap_slots(&mut self, a: &str, b: &str) -> bool {
+		if a == b {
+			return self.get_windows(a).is_some();
+		}
+
+		self.edit(vec![a.to_string(), b.to_string()], |config| {
+			let windows_a = match config.get_windows(a) {
+				Some(window_set) => ::std::mem::replace(window_set, WindowSet::new()),
+				None => return false
+			};
+
+			match config.get_windows(b) {
+				Some(window_set) => {
+					let windows_b = ::std::mem::replace(window_set, windows_a);
+					*config.get_windows(a).unwrap() = windows_b;
+					true
+				},
+				None => {
+					*config.get_windows(a).unwrap() = windows_a;
+					false
+				}
+			}
+		})
+	}
+
+	/// Appends the windows of `from` to `into` and empties `from`.
+	pub fn merge_slots(&mut self, from: &str, into: &str) -> bool {
+		if from == into || self.get_windows(into).is_none() {
+			return false;
+		}
+
+		self.edit(vec![from.to_string(), into.to_string()], |config| {
+			let windows = match config.get_windows(from) {
+				Some(window_set) => ::std::mem::replace(window_set, WindowSet::new()),
+				None => return false
+			};
+
+			config.get_windows(into).unwrap().append(windows);
+
+			true
+		})
+	}
+
+	/// Removes `window` from every slot, returning the names of the slots that held it.
+	pub fn remove_window(&mut self, window: &Window) -> Vec<String> {
+		let names = self.slots
+		                .iter()
+		                .filter(|s| s.windows.contains(window))
+		                .map(|s| s.name.clone())
+		                .collect::<Vec<_>>();
+
+		self.edit(names.clone(), |config| {
+			for slot in config.slots.iter_mut() {
+				slot.windows.remove(window);
+			}
+		});
+
+		names
+	}
+
+	/// Name of the first slot holding `window`.
+	pub fn find_slot(&self, window: &Window) -> Option<String> {
+		self.slots
+		    .iter()
+		    .find(|s| s.windows.contains(window))
+		    .map(|s| s.name.clone())
+	}
+
+	pub fn reorder_window(&mut self, name: &str, window: &Window, offset: isize) -> bool {
+		self.edit(vec![name.to_string()], |config| {
+			match config.get_windows(name) {
+				Some(window_set) => window_set.shift(window, offset),
+				None => false
+			}
+		})
+	}
+
+	/// Updates the stored title of `hwnd` wherever it is tracked, including
+	/// the undo history so that undoing does not bring back stale titles.
+	/// This is not an edit and is not recorded.
+	pub fn update_title(&mut self, hwnd: HWND, title: &str) -> bool {
+		let mut changed = false;
+
+		{
+			let history = self.undo
+				.iter_mut()
+				.chain(self.redo.iter_mut())
+				.flat_map(|edit| edit.before.iter_mut().chain(edit.after.iter_mut()))
+				.filter_map(|&mut (_, ref mut windows)| windows.as_mut());
+
+			let current = self.slots.iter_mut().map(|s| &mut s.windows);
+
+			for window_set in current.chain(history) {
+				for window in window_set.iter_mut().filter(|w| w.hwnd == hwnd) {
+					changed |= window.set_title(title);
+				}
+			}
+		}
+
+		changed
+	}
+
+	/// Re-reads the titles of all tracked windows.
+	pub fn refresh_titles(&mut self) {
+		let mut changed = Vec::new();
+
+		for slot in self.slots.iter_mut() {
+			for window in slot.windows.iter_mut() {
+				if window.refresh() {
+					changed.push((window.hwnd, window.title().unwrap_or("").to_string()));
+				}
+			}
+		}
+
+		for (hwnd, title) in changed {
+			self.update_title(hwnd, &title);
+		}
+	}
+
+	/// Reverts the last slot edit. Returns false when there is nothing to undo.
+	pub fn undo(&mut self) -> bool {
+		match self.undo.pop_back() {
+			Some(edit) => {
+				self.restore(&edit.before);
+				self.redo.push(edit);
+				true
+			},
+			None => false
+		}
+	}
+
+	/// Reapplies the last undone slot edit. Returns false when there is nothing to redo.
+	pub fn redo(&mut self) -> bool {
+		match self.redo.pop() {
+			Some(edit) => {
+				self.restore(&edit.after);
+				self.undo.push_back(edit);
+				true
+			},
+			None => false
 		}
 	}
 
-	pub fn track_window(&mut self, vk: UINT, window: Window) {
-		let mut window_set = self.windows
-		                         .entry(vk)
-		                         .or_insert(WindowSet::new());
+	fn add_window(&mut self, name: &str, window: Window) {
+		if !self.slots.iter().any(|s| s.name == name) {
+			self.slots.push(Slot::new(name));
+		}
+
+		if let Some(window_set) = self.get_windows(name) {
+			window_set.add(window);
+		}
+	}
+
+	/// Runs a mutation of the named slots and records it for undo.
+	/// Mutations that change nothing are not recorded.
+	fn edit<F, R>(&mut self, names: Vec<String>, f: F) -> R
+		where F: FnOnce(&mut Self) -> R {
+
+		let before = self.snapshot(&names);
+		let result = f(self);
+		let after = self.snapshot(&names);
+
+		if before != after {
+			self.redo.clear();
+			self.undo.push_back(Edit {
+				before: before,
+				after: after,
+			});
+
+			if self.undo.len() > HISTORY_LIMIT {
+				self.undo.pop_front();
+			}
+		}
 
-		window_set.add(window);		
+		result
 	}
 
-	pub fn get_windows(&mut self, vk: UINT) -> Option<&mut WindowSet> {
-		self.windows.get_mut(&vk)
+	fn snapshot(&self, names: &[String]) -> Vec<(String, Option<WindowSet>)> {
+		names.iter()
+		     .map(|name| {
+		         let windows = self.slots
+		                           .iter()
+		                           .find(|s| s.name == *name)
+		                           .map(|s| s.windows.clone());
+
+		         (name.clone(), windows)
+		     })
+		     .collect()
 	}
 
-	pub fn clear_windows(&mut self, vk: UINT) {
-		self.windows.remove(&vk);
+	fn restore(&mut self, state: &[(String, Option<WindowSet>)]) {
+		for &(ref name, ref windows) in state {
+			let windows = windows.clone().unwrap_or(WindowSet::new());
+
+			match self.get_windows(name) {
+				Some(window_set) => *window_set = windows,
+				None => {
+					let mut slot = Slot::new(name);
+					slot.windows = windows;
+					self.slots.push(slot);
+				}
+			}
+		}
 	}
 }
 
+pub fn get_window(hwnd: HWND) -> Window {
+	let title = utils::api_wrappers::get_window_text(hwnd).unwrap_or("".to_string());
+
+	Window::new(hwnd, title)
+}
+
+pub fn get_window_info(hwnd: HWND) -> Option<WindowInfo> {
+	use winapi::winuser::{GWL_EXSTYLE, GW_OWNER};
+
+	if unsafe { user32::IsWindow(hwnd) } == FALSE {
+		return None;
+	}
+
+	let pid = api_wrappers::get_window_process_id(hwnd);
+	let exe = api_wrappers::get_process_image_name(pid)
+		.ok()
+		.and_then(|image| image.rsplit('\\').next().map(|s| s.to_string()))
+		.unwrap_or("".to_string());
+
+	let (visible, ex_style, owner) = unsafe {
+		(
+			user32::IsWindowVisible(hwnd) != FALSE,
+			user32::GetWindowLongPtrW(hwnd, GWL_EXSTYLE) as DWORD,
+			user32::GetWindow(hwnd, GW_OWNER),
+		)
+	};
+
+	Some(WindowInfo {
+		hwnd: hwnd,
+		title: api_wrappers::get_window_text(hwnd).unwrap_or("".to_string()),
+		class: api_wrappers::get_class_name(hwnd).unwrap_or("".to_string()),
+		exe: exe,
+		pid: pid,
+		visible: visible,
+		ex_style: ex_style,
+		owner: if owner.is_null() { None } else { Some(owner) },
+		cloaked: api_wrappers::is_window_cloaked(hwnd),
+	})
+}
+
 pub fn get_foreground_window() -> Win32Result<Window> {
 	let hwnd = unsafe {
 		let hwnd = user32::GetForegroundWindow();
@@ -114,10 +637,7 @@ pub fn get_foreground_window() -> Win32Result<Window> {
 		hwnd
 	};
 
-	let title = utils::api_wrappers::get_window_text(hwnd).unwrap_or("".to_string());
-	let window = Window::new(hwnd, title);
-
-	Ok(window)
+	Ok(get_window(hwnd))
 }
 
 pub fn set_foreground_window(hwnd: HWND) -> Win32Result<()> {
@@ -143,3 +663,261 @@ pub fn set_foreground_window(hwnd: HWND) -> Win32Result<()> {
 
 	Ok(())
 }
+
+/// Moves and resizes a window. Maximized windows are restored first.
+pub fn set_window_bounds(hwnd: HWND, bounds: Bounds) -> Win32Result<()> {
+	use winapi::winuser::{SW_RESTORE, SWP_NOACTIVATE, SWP_NOZORDER};
+
+	let (l, t, r, b) = bounds;
+
+	unsafe {
+		if user32::IsZoomed(hwnd) != FALSE {
+			user32::ShowWindow(hwnd, SW_RESTORE);
+		}
+
+		if user32::SetWindowPos(hwnd, 0 as HWND, l, t, r - l, b - t, SWP_NOZORDER | SWP_NOACTIVATE) == FALSE {
+			return Err(kernel32::GetLastError());
+		}
+	}
+
+	Ok(())
+}
+
+pub fn set_topmost(hwnd: HWND, topmost: bool) -> Win32Result<()> {
+	use winapi::winuser::{HWND_NOTOPMOST, HWND_TOPMOST, SWP_NOACTIVATE, SWP_NOMOVE, SWP_NOSIZE};
+
+	let insert_after = if topmost { HWND_TOPMOST } else { HWND_NOTOPMOST };
+
+	unsafe {
+		if user32::SetWindowPos(hwnd, insert_after, 0, 0, 0, 0, SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE) == FALSE {
+			return Err(kernel32::GetLastError());
+		}
+	}
+
+	Ok(())
+}
+
+pub fn minimize_window(hwnd: HWND) {
+	use winapi::winuser::SW_SHOWMINNOACTIVE;
+
+	unsafe { user32::ShowWindow(hwnd, SW_SHOWMINNOACTIVE); }
+}
+
+/// Hidden windows are gone from the screen and the taskbar.
+pub fn show_window(hwnd: HWND, visible: bool) {
+	use winapi::winuser::{SW_HIDE, SW_SHOW};
+
+	unsafe { user32::ShowWindow(hwnd, if visible { SW_SHOW } else { SW_HIDE }); }
+}
+
+/// Restored bounds and show state, in screen coordinates.
+pub fn get_window_placement(hwnd: HWND) -> Win32Result<Placement> {
+	use winapi::winuser::{SW_SHOWMAXIMIZED, SW_SHOWMINIMIZED, WINDOWPLACEMENT};
+
+	let mut placement: WINDOWPLACEMENT = unsafe { ::std::mem::zeroed() };
+	placement.length = ::std::mem::size_of::<WINDOWPLACEMENT>() as UINT;
+
+	if unsafe { user32::GetWindowPlacement(hwnd, &mut placement) } == FALSE {
+		return Err(unsafe { kernel32::GetLastError() });
+	}
+
+	let r = placement.rcNormalPosition;
+	let (dx, dy) = monitors::workspace_offset((r.left, r.top, r.right, r.bottom));
+
+	let state = match placement.showCmd as i32 {
+		SW_SHOWMINIMIZED => ShowState::Minimized,
+		SW_SHOWMAXIMIZED => ShowState::Maximized,
+		_ => ShowState::Normal,
+	};
+
+	Ok(Placement {
+		state: state,
+		bounds: (r.left + dx, r.top + dy, r.right + dx, r.bottom + dy),
+	})
+}
+
+/// Moves a window to its restored bounds, then shows it as `placement.state`
+/// without activating it.
+pub fn set_window_placement(hwnd: HWND, placement: &Placement) -> Win32Result<()> {
+	use winapi::winuser::{SW_SHOWMAXIMIZED, SW_SHOWMINNOACTIVE, SW_SHOWNOACTIVATE, WINDOWPLACEMENT};
+
+	let (l, t, r, b) = placement.bounds;
+	let (dx, dy) = monitors::workspace_offset(placement.bounds);
+
+	let mut raw: WINDOWPLACEMENT = unsafe { ::std::mem::zeroed() };
+	raw.length = ::std::mem::size_of::<WINDOWPLACEMENT>() as UINT;
+	raw.showCmd = match placement.state {
+		ShowState::Normal => SW_SHOWNOACTIVATE,
+		ShowState::Minimized => SW_SHOWMINNOACTIVE,
+		ShowState::Maximized => SW_SHOWMAXIMIZED,
+	} as UINT;
+	raw.rcNormalPosition = RECT {
+		left: l - dx,
+		top: t - dy,
+		right: r - dx,
+		bottom: b - dy,
+	};
+
+	if unsafe { SetWindowPlacement(hwnd, &raw) } == FALSE {
+		return Err(unsafe { kernel32::GetLastError() });
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use winapi::windef::*;
+
+	use window_system::WindowSystem;
+	use window_system::fake::FakeWindowSystem;
+	use super::{Config, HISTORY_LIMIT};
+
+	fn hwnds(config: &mut Config, slot: &str) -> Vec<HWND> {
+		config.get_windows(slot)
+		      .map(|set| set.iter().map(|w| w.hwnd()).collect())
+		      .unwrap_or(Vec::new())
+	}
+
+	fn grab(config: &mut Config, system: &FakeWindowSystem, slot: &str) {
+		config.track_window(slot, system.foreground_window().unwrap());
+	}
+
+	#[test]
+	fn undo_redo_track() {
+		let mut system = FakeWindowSystem::new();
+		let mut config = Config::new();
+
+		let editor = system.open("editor");
+		grab(&mut config, &system, "1");
+		let terminal = system.open("terminal");
+		grab(&mut config, &system, "1");
+
+		assert_eq!(vec![terminal.hwnd(), editor.hwnd()], hwnds(&mut config, "1"));
+
+		assert!(config.undo());
+		assert_eq!(vec![editor.hwnd()], hwnds(&mut config, "1"));
+
+		assert!(config.redo());
+		assert_eq!(vec![terminal.hwnd(), editor.hwnd()], hwnds(&mut config, "1"));
+	}
+
+	#[test]
+	fn undo_clear() {
+		let mut system = FakeWindowSystem::new();
+		let mut config = Config::new();
+
+		let editor = system.open("editor");
+		grab(&mut config, &system, "1");
+		config.clear_windows("1");
+		assert!(hwnds(&mut config, "1").is_empty());
+
+		assert!(config.undo());
+		assert_eq!(vec![editor.hwnd()], hwnds(&mut config, "1"));
+	}
+
+	#[test]
+	fn undo_move_and_swap() {
+		let mut system = FakeWindowSystem::new();
+		let mut config = Config::new();
+
+		let editor = system.open("editor");
+		grab(&mut config, &system, "1");
+		let browser = system.open("browser");
+		grab(&mut config, &system, "2");
+
+		config.move_window("1", "2");
+		assert_eq!(vec![editor.hwnd(), browser.hwnd()], hwnds(&mut config, "2"));
+
+		config.swap_slots("1", "2");
+		assert_eq!(vec![editor.hwnd(), browser.hwnd()], hwnds(&mut config, "1"));
+		assert!(hwnds(&mut config, "2").is_empty());
+
+		assert!(config.undo());
+		assert!(config.undo());
+		assert_eq!(vec![editor.hwnd()], hwnds(&mut config, "1"));
+		assert_eq!(vec![browser.hwnd()], hwnds(&mut config, "2"));
+	}
+
+	#[test]
+	fn undo_new_slot() {
+		let mut system = FakeWindowSystem::new();
+		let mut config = Config::new();
+
+		system.open("notes");
+		grab(&mut config, &system, "notes");
+		assert!(config.undo());
+
+		assert!(hwnds(&mut config, "notes").is_empty());
+		assert!(!config.undo());
+	}
+
+	#[test]
+	fn edits_clear_redo() {
+		let mut system = FakeWindowSystem::new();
+		let mut config = Config::new();
+
+		system.open("editor");
+		grab(&mut config, &system, "1");
+		assert!(config.undo());
+
+		system.open("browser");
+		grab(&mut config, &system, "2");
+		assert!(!config.redo());
+	}
+
+	#[test]
+	fn no_op_edits_are_not_recorded() {
+		let mut config = Config::new();
+
+		config.clear_windows("1");
+		config.swap_slots("1", "2");
+
+		assert!(!config.undo());
+	}
+
+	#[test]
+	fn history_is_bounded() {
+		let mut system = FakeWindowSystem::new();
+		let mut config = Config::new();
+
+		for i in 0..HISTORY_LIMIT + 10 {
+			system.open(&i.to_string());
+			grab(&mut config, &system, "1");
+		}
+
+		let mut undone = 0;
+		while config.undo() {
+			undone += 1;
+		}
+
+		assert_eq!(HISTORY_LIMIT, undone);
+		assert_eq!(10, hwnds(&mut config, "1").len());
+	}
+
+	#[test]
+	fn titles_follow_changes() {
+		let mut system = FakeWindowSystem::new();
+		let mut config = Config::new();
+
+		let notepad = system.open("Untitled - Notepad");
+		grab(&mut config, &system, "1");
+		config.clear_windows("1");
+		assert!(config.undo());
+
+		assert!(config.update_title(notepad.hwnd(), "notes.txt - Notepad"));
+		assert!(!config.update_title(notepad.hwnd(), "notes.txt - Notepad"));
+
+		let title = |config: &mut Config| {
+			config.get_windows("1")
+			      .and_then(|set| set.front().and_then(|w| w.title().map(|t| t.to_string())))
+		};
+		assert_eq!(Some("notes.txt - Notepad".to_string()), title(&mut config));
+
+		// History snapshots were updated too
+		assert!(config.undo());
+		assert_eq!(None, title(&mut config));
+		assert!(config.redo());
+		assert_eq!(Some("notes.txt - Notepad".to_string()), title(&mut config));
+	}
+}
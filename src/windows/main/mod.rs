@@ -1,38 +1,52 @@
 use winapi::*;
 use kernel32;
 use user32;
-use spmc;
 
+use commands::Command;
 use constants::*;
+// winapi has these too, as c_int
+use constants::{VK_DOWN, VK_ESCAPE, VK_LEFT, VK_RETURN, VK_RIGHT, VK_UP};
+use hotkeys::{built_in_hotkeys, scratchpad_hotkey, script_hotkey, slot_hotkeys, BindingTable, Hotkey, Outcome, Registrar, Report};
+use keys::Chord;
+use reactor::{Event, Sender};
+use settings::Settings;
 use utils;
 use utils::Win32Result;
+use window_tracking::Config;
 use windows::ManagedWindow2;
 
 const CLASS_NAME: &'static str = "WinmanMainWindow";
 
-const HK_QUIT: i32 = 1;
-const HK_POPUP: i32 = 2;
-const HK_GRAB: i32 = 3;
-const HK_SWITCH: i32 = 4;
-const HK_CLEAR: i32 = 5;
+/// Adjust mode keys take the ids from here on, one each. Those below are
+/// given out by the binding table.
+const HK_ADJUST_KEY: i32 = 0xB000;
 
-const MOD_QUIT: u32 = MOD_CONTROL | MOD_ALT;
-const MOD_POPUP: u32 = MOD_NOREPEAT | MOD_ALT;
-const MOD_GRAB: u32 = MOD_NOREPEAT| MOD_ALT | MOD_SHIFT;
-const MOD_SWITCH: u32 = MOD_NOREPEAT | MOD_ALT;
-const MOD_CLEAR: u32 = MOD_NOREPEAT | MOD_CONTROL | MOD_ALT | MOD_SHIFT;
+/// Keys taken over while adjusting a window
+const ADJUST_KEYS: [(u32, UINT); 20] = [
+    (0, VK_LEFT), (0, VK_RIGHT), (0, VK_UP), (0, VK_DOWN),
+    (MOD_SHIFT, VK_LEFT), (MOD_SHIFT, VK_RIGHT), (MOD_SHIFT, VK_UP), (MOD_SHIFT, VK_DOWN),
+    (0, VK_0), (0, VK_1), (0, VK_2), (0, VK_3), (0, VK_4),
+    (0, VK_5), (0, VK_6), (0, VK_7), (0, VK_8), (0, VK_9),
+    (0, VK_RETURN), (0, VK_ESCAPE),
+];
 
 pub enum AppMsg {
-    ShowPopup,
-    GrabWindow(u32),
-    FocusWindow(u32),
-    ClearWindow(u32),
+    /// A program started for a slot opened its first window.
+    /// The window handle is carried as an integer so the message stays Send.
+    WindowLaunched(String, usize),
+    Command(Command),
+    /// Monitors were added, removed or changed resolution
+    DisplayChanged,
+    /// A key pressed while adjusting, with its modifiers
+    AdjustKey(u32, u32),
 }
 
 pub struct AppWindow {
     pub hwnd: HWND,
-    tx: spmc::Sender<AppMsg>,
-    rx: spmc::Receiver<AppMsg>,
+    tx: Sender<Event>,
+    bindings: BindingTable,
+    /// How registering the hotkeys went at startup
+    pub hotkeys: Report,
 }
 
 impl AppWindow {
@@ -52,7 +66,9 @@ impl AppWindow {
         }
     }
 
-    pub fn new() -> Win32Result<ManagedWindow2<Self>> {
+    /// Hotkeys and window messages are sent to `events` as `AppMsg`s.
+    /// Hotkeys that could not be registered are listed in `hotkeys`.
+    pub fn new(hotkeys: Vec<Hotkey>, events: Sender<Event>) -> Win32Result<ManagedWindow2<Self>> {
         let class_name = utils::to_wide_chars(CLASS_NAME);
 
         let hwnd = unsafe {
@@ -77,45 +93,75 @@ impl AppWindow {
             hwnd
         };
 
-        register_hotkeys(hwnd);
+        let mut bindings = BindingTable::new(1);
+        let report = bindings.add_all(&mut WindowRegistrar(hwnd), hotkeys);
 
-        let (tx, rx) = spmc::channel();
         let app = AppWindow {
             hwnd: hwnd,
-            tx: tx,
-            rx: rx,
+            tx: events,
+            bindings: bindings,
+            hotkeys: report,
         };
 
         Ok(ManagedWindow2::new(hwnd, Box::new(app)).unwrap())
     }
 
-    pub fn listen(&self) -> spmc::Receiver<AppMsg> {
-        self.rx.clone()
-    }
-
-    fn on_hotkey(&self, id: i32, _modifiers: u32, vk: u32) {
-        match (id, vk) {
-            (HK_QUIT, _) => {
-                unsafe { user32::PostQuitMessage(0); }
-            },
+    /// Binds a chord to a command, taking it from whatever had it.
+    pub fn bind(&mut self, chord: Chord, command: Command) -> Result<(), Outcome> {
+        let hotkey = Hotkey {
+            chord: chord,
+            command: command,
+            repeat: false,
+            binding: true,
+        };
 
-            (HK_POPUP, _) => {
-                let _ = self.tx.send(AppMsg::ShowPopup);
-            },
+        self.bindings.replace(&mut WindowRegistrar(self.hwnd), hotkey).map(|_| ())
+    }
 
-            (HK_GRAB, vk) => {
-                let _ = self.tx.send(AppMsg::GrabWindow(vk));
-            },
+    /// Returns whether the chord was bound.
+    pub fn unbind(&mut self, chord: Chord) -> bool {
+        self.bindings.remove(&mut WindowRegistrar(self.hwnd), chord).is_some()
+    }
 
-            (HK_SWITCH, vk) => {
-                let _ = self.tx.send(AppMsg::FocusWindow(vk));
-            },
+    /// Registers the adjust mode keys, which stops them reaching other
+    /// programs until `release_adjust_keys`. They are reported under
+    /// `adjust-window`, the command that grabs them.
+    pub fn grab_adjust_keys(&self) -> Report {
+        let mut registrar = WindowRegistrar(self.hwnd);
+
+        let entries = ADJUST_KEYS.iter()
+            .enumerate()
+            .map(|(i, &(modifiers, vk))| {
+                let hotkey = Hotkey {
+                    chord: Chord { modifiers: modifiers, vk: vk },
+                    command: Command::AdjustWindow,
+                    repeat: true,
+                    binding: false,
+                };
+
+                let outcome = match registrar.register(HK_ADJUST_KEY + i as i32, hotkey.chord, hotkey.repeat) {
+                    Ok(()) => Outcome::Registered,
+                    Err(error) => Outcome::Failed(error)
+                };
+
+                (hotkey, outcome)
+            })
+            .collect();
+
+        Report { entries: entries }
+    }
 
-            (HK_CLEAR, vk) => {
-                let _ = self.tx.send(AppMsg::ClearWindow(vk));
-            },
+    pub fn release_adjust_keys(&self) {
+        for i in 0..ADJUST_KEYS.len() {
+            unsafe { user32::UnregisterHotKey(self.hwnd, HK_ADJUST_KEY + i as i32); }
+        }
+    }
 
-            _ => {}
+    fn on_hotkey(&self, id: i32, modifiers: u32, vk: u32) {
+        if id >= HK_ADJUST_KEY {
+            let _ = self.tx.send(AppMsg::AdjustKey(vk, modifiers));
+        } else if let Some(command) = self.bindings.command(id) {
+            let _ = self.tx.send(AppMsg::Command(command.clone()));
         }
     }
 
@@ -133,6 +179,11 @@ impl AppWindow {
                     return 0;
                 },
 
+                WM_DISPLAYCHANGE => {
+                    let _ = instance.tx.send(AppMsg::DisplayChanged);
+                    return 0;
+                },
+
                 WM_DESTROY => {
                     user32::PostQuitMessage(0);
                     return 0;
@@ -146,23 +197,67 @@ impl AppWindow {
     }
 }
 
-fn register_hotkeys(hwnd: HWND) {
-    // Virtual key codes: https://msdn.microsoft.com/en-us/library/windows/desktop/dd375731(v=vs.85).aspx
-    // CTRL-ALT-Q to quit
-    unsafe {
-        user32::RegisterHotKey(hwnd, HK_QUIT, MOD_QUIT, VK_Q);
-        user32::RegisterHotKey(hwnd, HK_POPUP, MOD_POPUP, 0x20); // VK_SPACE
+/// Every hotkey WinMan registers at startup, bindings first so that they
+/// win over built-in hotkeys on the same chord. Adjust mode keys are only
+/// taken while adjusting and are not included.
+pub fn hotkeys(settings: &Settings, config: &Config) -> Vec<Hotkey> {
+    let mut hotkeys: Vec<Hotkey> = settings.bindings().iter()
+        .map(|(chord, command)| Hotkey {
+            chord: *chord,
+            command: command.clone(),
+            repeat: false,
+            binding: true,
+        })
+        .collect();
+
+    hotkeys.extend(built_in_hotkeys());
+
+    for (name, vk) in settings.scratchpads() {
+        hotkeys.push(scratchpad_hotkey(name, *vk));
+    }
+
+    for script in settings.scripts() {
+        if let Some(vk) = script.key {
+            hotkeys.push(script_hotkey(&script.name, vk));
+        }
+    }
+
+    for slot in config.slots() {
+        if let Some(vk) = slot.key {
+            hotkeys.extend(slot_hotkeys(&slot.name, vk));
+        }
     }
 
-    // ALT-SHIFT-1 to ALT-SHIFT-9 to grab windows,
-    // ALT-1 to ALT-9 to switch windows
-    for i in 0..10 {
-        let vk_n = VK_0 + i;
+    hotkeys
+}
+
+/// Registers `hotkeys` for this thread and releases them again, to see
+/// which would work. A running WinMan has all of its own taken.
+pub fn check_hotkeys(hotkeys: Vec<Hotkey>) -> Report {
+    let mut registrar = WindowRegistrar(0 as HWND);
+    let mut bindings = BindingTable::new(1);
+    let report = bindings.add_all(&mut registrar, hotkeys);
+    bindings.clear(&mut registrar);
+
+    report
+}
+
+/// Hotkeys sent to a window, or to the thread for a null one.
+struct WindowRegistrar(HWND);
+
+impl Registrar for WindowRegistrar {
+    fn register(&mut self, id: i32, chord: Chord, repeat: bool) -> Result<(), DWORD> {
+        let modifiers = if repeat { chord.modifiers } else { chord.modifiers | MOD_NOREPEAT };
 
         unsafe {
-            user32::RegisterHotKey(hwnd, HK_GRAB, MOD_GRAB, vk_n);
-            user32::RegisterHotKey(hwnd, HK_SWITCH, MOD_SWITCH, vk_n);
-            user32::RegisterHotKey(hwnd, HK_CLEAR, MOD_CLEAR, vk_n);
+            match user32::RegisterHotKey(self.0, id, modifiers, chord.vk) {
+                FALSE => Err(kernel32::GetLastError()),
+                _ => Ok(())
+            }
         }
     }
-}
\ No newline at end of file
+
+    fn unregister(&mut self, id: i32) {
+        unsafe { user32::UnregisterHotKey(self.0, id); }
+    }
+}
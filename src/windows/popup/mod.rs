@@ -1,9 +1,12 @@
+use std::cell::RefCell;
+
 use winapi::*;
 use kernel32;
 use user32;
 use gdi32;
-use spmc;
 
+use reactor::{Event, Sender};
+use scripting;
 use utils;
 use utils::Win32Result;
 use windows::*;
@@ -18,6 +21,15 @@ const WIN_DIMENSIONS: (i32, i32) = (340, 50);
 const THEME_BG_COLOR: u32 = 0x00222222;
 const THEME_EDIT_COLOR: u32 = 0x00A3FFA3;
 const THEME_EDIT_BG_COLOR: u32 = 0x00323232;
+const THEME_LIST_COLOR: u32 = 0x00909090;
+
+// Script lists are drawn under the edit box, the first match in the edit color
+const LIST_ITEM_HEIGHT: i32 = 20;
+const LIST_MAX_ITEMS: usize = 10;
+const LIST_PADDING: i32 = 10;
+
+// Not in winapi 0.2
+const TRANSPARENT: c_int = 1;
 
 const MSG_NOTIFY_RETURN: u32 = 1;
 const MSG_NOTIFY_ESCAPE: u32 = 2;
@@ -26,6 +38,8 @@ const MSG_NOTIFY_CHAR: u32 = 3;
 pub enum PopupMsg {
     Search(Option<String>),
     Accept(String),
+    /// Closed with Escape
+    Dismiss,
 }
 
 pub struct PopupWindow {
@@ -33,8 +47,8 @@ pub struct PopupWindow {
     edit_box: EditBox,
     hbrush_primary: HBRUSH,
     hbrush_secondary: HBRUSH,
-    tx: spmc::Sender<PopupMsg>,
-    rx: spmc::Receiver<PopupMsg>,
+    tx: Sender<Event>,
+    list: RefCell<Vec<String>>,
 }
 
 impl PopupWindow {
@@ -56,7 +70,8 @@ impl PopupWindow {
         }
     }
 
-    pub fn new(hwnd_parent: HWND) -> Win32Result<ManagedWindow2<PopupWindow>> {
+    /// What is typed is sent to `events` as `PopupMsg`s.
+    pub fn new(hwnd_parent: HWND, events: Sender<Event>) -> Win32Result<ManagedWindow2<PopupWindow>> {
         let (w, h) = WIN_DIMENSIONS;
         let class_name = utils::to_wide_chars(CLASS_NAME);
 
@@ -85,7 +100,7 @@ impl PopupWindow {
         // If we error on step 2, we must destroy the window instance before returning
         // The parent HWND is not managed and will be forgotten on an early return
         // The easiest way to ensure this is to perform the rest of the layout in a separate funcction
-        let create_result = PopupWindow::new_impl(hwnd);
+        let create_result = PopupWindow::new_impl(hwnd, events);
 
         match create_result {
             Ok(window) => {
@@ -99,7 +114,7 @@ impl PopupWindow {
         }
     }
 
-    fn new_impl(hwnd: HWND) -> Win32Result<PopupWindow> {
+    fn new_impl(hwnd: HWND, events: Sender<Event>) -> Win32Result<PopupWindow> {
         // Create controls
         let bounds_window = get_window_bounds(hwnd);
 
@@ -123,34 +138,40 @@ impl PopupWindow {
         let hbrush_primary = unsafe { gdi32::CreateSolidBrush(THEME_BG_COLOR) };
         let hbrush_secondary = unsafe { gdi32::CreateSolidBrush(THEME_EDIT_BG_COLOR) };
 
-        // Open a channel to broadcast UI events
-        let (tx, rx) = spmc::channel();
-
         Ok(PopupWindow {
             hwnd: hwnd,
             edit_box: edit_box,
             hbrush_primary: hbrush_primary,
             hbrush_secondary: hbrush_secondary,
-            tx: tx,
-            rx: rx,
+            tx: events,
+            list: RefCell::new(Vec::new()),
         })
     }
 
-    pub fn listen(&self) -> spmc::Receiver<PopupMsg> {
-        self.rx.clone()
+    /// Shows the popup centered in `area`, in screen coordinates.
+    pub fn show(&self, area: Bounds) {
+        self.show_list(area, Vec::new());
     }
 
-    pub fn show(&self) {
-        let screen_bounds = get_screen_bounds();
-        let (w, h) = WIN_DIMENSIONS;
+    /// Shows the popup with `items` listed under the edit box, narrowed down
+    /// as the user types. Enter accepts the first one left.
+    pub fn show_list(&self, area: Bounds, items: Vec<String>) {
+        let rows = ::std::cmp::min(items.len(), LIST_MAX_ITEMS) as i32;
+        let (w, h) = match rows {
+            0 => WIN_DIMENSIONS,
+            _ => (WIN_DIMENSIONS.0, WIN_DIMENSIONS.1 + rows * LIST_ITEM_HEIGHT + LIST_PADDING)
+        };
+        *self.list.borrow_mut() = items;
+
         let (x, y, w, h) = calc_window_pos(
-            screen_bounds,
+            area,
             Some(w),
             Some(h),
             None,
             None,
             HorizontalAlignment::Center,
             VerticalAlignment::Center);
+        let (x, y) = (area.0 + x, area.1 + y);
 
         unsafe {
             user32::SetWindowPos(self.hwnd, winuser::HWND_TOPMOST, x, y, w, h, 0);
@@ -168,6 +189,36 @@ impl PopupWindow {
         }
     }
 
+    fn dismiss(&self) {
+        self._hide();
+        let _ = self.tx.send(PopupMsg::Dismiss);
+    }
+
+    /// The list items left by what is typed so far.
+    fn matching_items(&self) -> Vec<String> {
+        let query = self.edit_box.get_text().unwrap_or_default();
+        scripting::matching_items(&self.list.borrow(), &query).into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    fn draw_list(&self, hdc: HDC) {
+        let (_, top) = WIN_DIMENSIONS;
+
+        unsafe { gdi32::SetBkMode(hdc, TRANSPARENT); }
+
+        for (i, item) in self.matching_items().iter().take(LIST_MAX_ITEMS).enumerate() {
+            let color = if i == 0 { THEME_EDIT_COLOR } else { THEME_LIST_COLOR };
+            let text = utils::to_wide_chars(item);
+
+            unsafe {
+                gdi32::SetTextColor(hdc, color);
+                // Without the terminating nul
+                gdi32::TextOutW(hdc, 15, top + i as i32 * LIST_ITEM_HEIGHT, text.as_ptr(), text.len() as c_int - 1);
+            }
+        }
+    }
+
     fn wm_erasebkgnd(&self, hdc: HDC) -> Option<HBRUSH> {
         let brush = self.hbrush_primary;
 
@@ -178,6 +229,10 @@ impl PopupWindow {
             user32::FillRect(hdc, &rc, brush);
         }
 
+        if !self.list.borrow().is_empty() {
+            self.draw_list(hdc);
+        }
+
         // Border
         Some(self.hbrush_secondary)
     }
@@ -194,7 +249,15 @@ impl PopupWindow {
     fn wm_notify(&self, nmhdr: &winuser::NMHDR) {
         match nmhdr.code {
             MSG_NOTIFY_ESCAPE => {
-                self._hide();
+                self.dismiss();
+            },
+
+            // Over a list, the highlighted item is taken as typed
+            MSG_NOTIFY_RETURN if !self.list.borrow().is_empty() => {
+                let query = self.edit_box.get_text().unwrap_or_default();
+                let item = self.matching_items().into_iter().next().unwrap_or(query);
+                let _ = self.tx.send(PopupMsg::Accept(item));
+                self.edit_box.clear();
             },
 
             MSG_NOTIFY_RETURN => {
@@ -207,6 +270,10 @@ impl PopupWindow {
             MSG_NOTIFY_CHAR => {
                 let query = self.edit_box.get_text();
                 let _ = self.tx.send(PopupMsg::Search(query));
+
+                if !self.list.borrow().is_empty() {
+                    unsafe { user32::InvalidateRect(self.hwnd, ::std::ptr::null(), TRUE); }
+                }
             },
 
             _ => ()
@@ -216,7 +283,7 @@ impl PopupWindow {
     fn wm_keydown(&self, vk: i32, _flags: i32) {
         match vk {
             VK_ESCAPE => {
-                self._hide();
+                self.dismiss();
             },
 
             _ => ()
@@ -3,139 +3,540 @@ extern crate comctl32;
 extern crate kernel32;
 extern crate user32;
 extern crate gdi32;
-extern crate spmc;
 extern crate fuzzy;
+extern crate rhai;
+extern crate toml;
 
-use winapi::minwindef::*;
 use winapi::windef::*;
+use winapi::winuser::WM_TIMER;
 
-use window_tracking::Config;
+use std::sync::mpsc;
+
+use adjust::{AdjustMode, Effect, Key};
+use commands::Command;
+use control::{Request, Response, Subscribers, Topic};
+use dispatcher::{Dispatcher, Outcome};
+use display_profiles::DisplayProfiles;
+use reactor::{Event, Reactor, Sender, ThreadWaker, WM_APP_WAKE};
+use rules::RuleEngine;
+use settings::{Settings, SlotSettings};
+use window_events::EventHook;
+use window_filter::WindowFilter;
+use window_registry::{WindowEvent, WindowRegistry};
+use window_system::{WindowSystem, Win32WindowSystem};
+use window_tracking::{Config, ShowState, Slot, Window};
+use windows::Bounds;
 use windows::main::{AppWindow, AppMsg};
 use windows::popup::{PopupWindow, PopupMsg};
 
+mod adjust;
+mod commands;
 mod constants;
+mod control;
+mod dispatcher;
+mod display_profiles;
+mod hotkeys;
+mod keys;
+mod launcher;
+mod monitors;
+mod reactor;
+mod rules;
+mod scratchpads;
+mod scripting;
+mod settings;
+mod snap;
+mod snapshots;
+mod tiling;
 mod utils;
+mod window_events;
+mod window_filter;
+mod window_registry;
+mod window_system;
 mod window_tracking;
 mod windows;
 
+// How often the window list is re-read when event hooks are unavailable,
+// and the layout recorded for automatic restore
+const SNAPSHOT_INTERVAL_MS: u32 = 1000;
+
 pub fn main() {
 	println!("Hello Windows!");
 
+    // Before any window exists, and for the one-shot commands too
+    monitors::declare_dpi_awareness();
+
+    // Persistent state
+    let settings = match settings::load(&settings::default_path()) {
+        Ok(settings) => settings,
+        Err(e) => {
+            println!("Could not load settings: {}", e);
+            Settings::default()
+        }
+    };
+    for warning in settings.warnings() {
+        println!("Settings: {}", warning);
+    }
+
+    // `winman layout save|restore <name>`, `winman ctl <command>` and
+    // `winman check-keys` run once instead of starting up
+    let args: Vec<String> = ::std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        ::std::process::exit(run_cli(&settings, &args));
+    }
+
+    let config = build_config(&settings);
+    let mut system = Win32WindowSystem;
+    let window_filter = build_filter(&settings);
+    let mut registry = WindowRegistry::new();
+    registry.refresh(&system);
+    let mut rule_engine = RuleEngine::new(settings.rules().to_vec(), settings.dry_run());
+    let mut display_profiles = DisplayProfiles::new(&system);
+    let mut adjust_mode = AdjustMode::new();
+    let mut dispatcher = Dispatcher::new(&settings, &window_filter, config);
+    let reactor = Reactor::new(ThreadWaker::current());
+    if let Err(e) = reactor::quit_on_ctrl_c(reactor.sender()) {
+        println!("Could not handle Ctrl+C: error {}", e);
+    }
+    if let Err(e) = control::serve(&control::default_endpoint(), reactor.sender()) {
+        println!("Could not serve control requests: {}", e);
+    }
+
+    // Windows left hidden by a run that crashed
+    scratchpads::recover_from_file(&mut system);
+    let rule_rx = registry.subscribe();
+    let title_rx = registry.subscribe();
+    let status_rx = registry.subscribe();
+    let mut subscribers = Subscribers::new();
+    let mut window_list: Vec<(HWND, String)> = Vec::new();
+    let mut popup_target: Option<Window> = None;
+
     // Register window classes
     AppWindow::register_classes().expect("Could not register AppWindow class");
     PopupWindow::register_classes().expect("Could not register PopupWindow class");
 
     // Main window
-    let app_window = AppWindow::new().expect("Could not create AppWindow");
-    let app_rx = app_window.listen();
+    let mut app_window = AppWindow::new(windows::main::hotkeys(&settings, dispatcher.config()), reactor.sender()).expect("Could not create AppWindow");
+    for (hotkey, outcome) in app_window.hotkeys.failures() {
+        println!("Hotkey {} ({}) is not working: {}", hotkey.chord, hotkey.command, outcome);
+    }
 
     // Popup window
-    let popup = PopupWindow::new(app_window.hwnd).expect("Could not create PopupWindow");
-    let popup_rx = popup.listen();
+    let popup = PopupWindow::new(app_window.hwnd, reactor.sender()).expect("Could not create PopupWindow");
 
-    // Persistent state
-    let mut config = load_config().unwrap_or(Config::new());
-    let mut window_list: Vec<(HWND, String)> = Vec::new();
+    // Window events keep the registry up to date, falling back to snapshots
+    let event_hook = match EventHook::install() {
+        Ok(hook) => Some(hook),
+        Err(e) => {
+            println!("Could not install window event hooks, polling instead: error {}", e);
+            None
+        }
+    };
+    if event_hook.is_none() || settings.auto_restore() {
+        unsafe { user32::SetTimer(0 as HWND, 0, SNAPSHOT_INTERVAL_MS, None); }
+    }
 
+    let mut running = true;
     let mut msg = unsafe { ::std::mem::zeroed() };
-    while unsafe { user32::GetMessageW(&mut msg, 0 as HWND, 0, 0) } > 0 {
-        unsafe {
-            user32::TranslateMessage(&mut msg);
-            user32::DispatchMessageW(&mut msg);
+    while running {
+        // Sleeps until a window message arrives or a producer wakes the thread
+        if unsafe { user32::GetMessageW(&mut msg, 0 as HWND, 0, 0) } <= 0 {
+            break;
         }
 
-        // App messages
-        while let Ok(event) = app_rx.try_recv() {
-            match event {
-                AppMsg::ShowPopup => {
-                    window_list.clear();
-                    get_window_list(&mut window_list);
-                    println!("Grabbed {} window titles", window_list.len());
+        if msg.message != WM_APP_WAKE {
+            unsafe {
+                user32::TranslateMessage(&mut msg);
+                user32::DispatchMessageW(&mut msg);
+            }
+        }
 
-                    popup.show();
-                },
+        // Window events
+        match event_hook {
+            Some(ref event_hook) => {
+                while let Some(event) = event_hook.poll() {
+                    registry.handle(&system, event);
+                }
+            },
+            None => {
+                if msg.message == WM_TIMER && msg.hwnd.is_null() {
+                    registry.refresh(&system);
+                }
+            }
+        }
 
-                AppMsg::GrabWindow(vk) => {
-                    let window = window_tracking::get_foreground_window();
+        // Layouts are recorded ahead of monitor changes, see DisplayProfiles
+        if settings.auto_restore() && msg.message == WM_TIMER && msg.hwnd.is_null() {
+            update_display_profiles(&mut system, &window_filter, &mut display_profiles);
+        }
 
-                    if let Ok(window) = window {
-                        println!("Tracking foreground window {:?}: {}",
-                            window.hwnd(),
-                            window.title().unwrap_or("No title"));
-                        
-                        config.track_window(vk, window);
-                    }
-                },
+        while let Ok(event) = rule_rx.try_recv() {
+            for firing in rule_engine.handle(&window_filter, &event) {
+                rule_engine.execute(&mut system, dispatcher.config_mut(), &firing);
+            }
+        }
 
-                AppMsg::FocusWindow(vk) => {
-                    let window_set = config.get_windows(vk);
+        while let Ok(event) = title_rx.try_recv() {
+            if let WindowEvent::TitleChanged(info) = event {
+                dispatcher.config_mut().update_title(info.hwnd, &info.title);
+            }
+        }
+
+        while let Ok(event) = status_rx.try_recv() {
+            if let Some((topic, event)) = control::window_event(&event, &registry, dispatcher.config()) {
+                subscribers.publish(topic, event);
+            }
+        }
 
-                    if let Some(window_set) = window_set {
-                        while let Some(window) = window_set.cycle() {
-                            println!("Switching to window {:?}: {}",
+        // Commands from hotkeys, the popup and other threads, with the window each applies to
+        // and where to answer control requests
+        let mut commands: Vec<(Command, Option<Window>, Option<mpsc::Sender<Response>>)> = Vec::new();
+
+        // Events from the windows and other threads
+        while let Some(event) = reactor.poll() {
+            match event {
+                Event::App(msg) => {
+                    let command = match msg {
+                        AppMsg::Command(command) => Some(command),
+
+                        AppMsg::WindowLaunched(slot, hwnd) => {
+                            let window = window_tracking::get_window(hwnd as HWND);
+                            println!("Launched window {:?}: {}",
                                 window.hwnd(),
                                 window.title().unwrap_or("No title"));
 
-                            match window_tracking::set_foreground_window(window.hwnd()) {
-                                Ok(_) => break,
-                                Err(_) => {
-                                    window_set.remove(&window);
-                                }
+                            let _ = system.set_foreground_window(window.hwnd());
+                            dispatcher.config_mut().track_window(&slot, window);
+                            None
+                        },
+
+                        AppMsg::AdjustKey(vk, modifiers) => {
+                            let effect = match Key::from_vk(vk, modifiers) {
+                                Some(key) => adjust_mode.key(key),
+                                None => Effect::Nothing
+                            };
+
+                            let result = match effect {
+                                Effect::Nothing | Effect::Commit(_) => Ok(()),
+                                Effect::Preview(hwnd, bounds) | Effect::Revert(hwnd, bounds) => system.set_window_bounds(hwnd, bounds),
+                            };
+                            if let Err(e) = result {
+                                println!("Could not adjust window: error {}", e);
+                            }
+
+                            if !adjust_mode.is_active() {
+                                app_window.release_adjust_keys();
+                                subscribers.publish(Topic::Mode, control::mode_event(mode_name(&adjust_mode)));
                             }
+                            None
+                        },
+
+                        AppMsg::DisplayChanged => {
+                            if settings.auto_restore() {
+                                update_display_profiles(&mut system, &window_filter, &mut display_profiles);
+                            }
+                            None
+                        },
+                    };
+
+                    if let Some(command) = command {
+                        commands.push((command, system.foreground_window(), None));
+                    }
+                },
+
+                Event::Popup(msg) => match msg {
+                    PopupMsg::Search(Some(s)) => {
+                        println!("Search: {}", s);
+                    },
+
+                    PopupMsg::Search(None) => {
+                        println!("Search: <null>");
+                    },
+
+                    // Closing the popup over a script's list picks nothing
+                    PopupMsg::Dismiss => {
+                        dispatcher.dismiss_list();
+                    },
+
+                    // 'name jumps to a slot, like a vim mark
+                    PopupMsg::Accept(ref s) if s.starts_with('\'') => {
+                        popup._hide();
+                        commands.push((Command::FocusSlot(s[1..].trim().to_string()), None, None));
+                    },
+
+                    // :command runs a command on the window the popup was opened over
+                    PopupMsg::Accept(ref s) if s.starts_with(':') => {
+                        match s[1..].parse::<Command>() {
+                            Ok(command) => {
+                                popup._hide();
+                                commands.push((command, popup_target.clone(), None));
+                            },
+                            Err(e) => println!("Invalid command '{}': {}", s, e)
+                        }
+                    },
+
+                    // Over a script's list, the script gets the item
+                    PopupMsg::Accept(s) if dispatcher.showing_list() => {
+                        popup._hide();
+                        commands.push((Command::Choose(s), None, None));
+                    },
+
+                    PopupMsg::Accept(s) => {
+                        println!("Accept: {}", s);
+
+                        let finder = fuzzy::Finder::new(&s).unwrap();
+
+                        let xx = window_list.iter().find(|w| finder.is_match(&w.1));
+                        match xx {
+                            Some(&(hwnd, ref title)) => {
+                                println!("match! {:?} {}", hwnd, title);
+                                let _ = system.set_foreground_window(hwnd);
+                                popup._hide();
+                            },
+                            None => println!("no match!")
                         }
                     }
                 },
 
-                AppMsg::ClearWindow(vk) => {
-                    println!("Clearing windows on hotkey {}", vk);
-                    config.clear_windows(vk);
+                Event::Command(command) => {
+                    commands.push((command, system.foreground_window(), None));
+                },
+
+                Event::Control(command, reply) => {
+                    commands.push((command, system.foreground_window(), Some(reply)));
+                },
+
+                Event::Subscribe(topics, tx) => {
+                    let state = control::state(dispatcher.config(), &registry, mode_name(&adjust_mode));
+                    subscribers.add(topics, tx, state);
                 },
             }
         }
 
-        // Popup messages
-        while let Ok(event) = popup_rx.try_recv() {
-            match event {
-                PopupMsg::Search(Some(s)) => {
-                    println!("Search: {}", s);
+        for (command, current, reply) in commands {
+            let outcome = dispatcher.dispatch(&mut system, &registry, &command, current.as_ref());
+
+            let mut response = control::response(&command, dispatcher.config());
+
+            match outcome {
+                Outcome::Done => {},
+
+                Outcome::Launch(slot) => {
+                    if let Some(slot_settings) = settings.slot(&slot) {
+                        launch_slot(&reactor.sender(), &slot, slot_settings);
+                    }
+                },
+
+                Outcome::ShowPopup => {
+                    if event_hook.is_none() {
+                        registry.refresh(&system);
+                    }
+
+                    window_list.clear();
+                    get_window_list(&registry, &window_filter, &mut window_list);
+                    println!("Grabbed {} window titles", window_list.len());
+
+                    // Remember what the popup is opened over, so that it can be marked
+                    popup_target = system.foreground_window();
+                    popup.show(popup_area(&system, &settings));
+                    subscribers.publish(Topic::Popup, control::popup_event());
                 },
 
-                PopupMsg::Search(None) => {
-                    println!("Search: <null>");
+                Outcome::ShowList(items) => {
+                    popup_target = system.foreground_window();
+                    popup.show_list(popup_area(&system, &settings), items);
+                    subscribers.publish(Topic::Popup, control::popup_event());
                 },
 
-                PopupMsg::Accept(s) => {
-                    println!("Accept: {}", s);
+                Outcome::AdjustWindow => {
+                    if let Some(window) = system.foreground_window() {
+                        let targets = adjust_targets(&system, &window_filter, &registry, window.hwnd());
+                        adjust_mode.enter(window.hwnd(), system.window_bounds(window.hwnd()), &targets);
+                        for (hotkey, outcome) in app_window.grab_adjust_keys().failures() {
+                            println!("Adjust key {} is not working: {}", hotkey.chord, outcome);
+                        }
+                        subscribers.publish(Topic::Mode, control::mode_event(mode_name(&adjust_mode)));
+                    }
+                },
 
-                    let finder = fuzzy::Finder::new(&s).unwrap();
+                Outcome::ListHotkeys => {
+                    print!("{}", app_window.hotkeys);
+                    response = Response::Ok(Some(control::hotkeys_json(&app_window.hotkeys)));
+                },
 
-                    let xx = window_list.iter().find(|w| finder.is_match(&w.1));
-                    match xx {
-                        Some(&(hwnd, ref title)) => {
-                            println!("match! {:?} {}", hwnd, title);
-                            let _ = window_tracking::set_foreground_window(hwnd);
-                            popup._hide();
-                        },
-                        None => println!("no match!")
+                Outcome::Bind(chord, command) => {
+                    if let Err(e) = app_window.bind(chord, command) {
+                        println!("Could not bind {}: {}", chord, e);
+                        response = Response::Error(format!("{} is not working: {}", chord, e));
                     }
-                }
+                },
+
+                Outcome::Unbind(chord) => {
+                    if !app_window.unbind(chord) {
+                        response = Response::Error(format!("{} is not bound", chord));
+                    }
+                },
+
+                Outcome::Quit => {
+                    running = false;
+                },
+            }
+
+            // The client may have given up waiting
+            if let Some(reply) = reply {
+                let _ = reply.send(response);
             }
         }
+
+        dispatcher.update_tiles(&mut system, &registry);
+        subscribers.update_slots(dispatcher.config());
     }
+
+    // The one way out, whether from the quit hotkey, Ctrl+C, another thread
+    // or the window being closed. Nothing stays hidden once WinMan is gone, and the
+    // hooks and hotkeys go with their owners.
+    println!("Shutting down");
+    dispatcher.restore_scratchpads(&mut system);
+    drop(event_hook);
+    drop(popup);
+    drop(app_window);
 }
 
-fn get_window_list(vec: &mut Vec<(HWND, String)>) {
-    utils::api_wrappers::enum_windows(|hwnd| {
-        let text = utils::api_wrappers::get_window_text(hwnd);
+fn launch_slot(events: &Sender<Event>, slot: &str, slot_settings: &SlotSettings) {
+    if let Some(ref launch) = slot_settings.launch {
+        println!("Launching {} for slot {}", launch.program, slot);
 
-        if let Ok(text) = text {
-            vec.push((hwnd, text));
+        let result = launcher::run(events, slot, launch, slot_settings.identity.as_ref());
+        if let Err(e) = result {
+            println!("Could not launch {}: {}", launch.program, e);
         }
+    }
+}
+
+fn popup_area<S: WindowSystem>(system: &S, settings: &Settings) -> Bounds {
+    monitors::active(system, settings.popup_monitor())
+        .map(|monitor| monitor.work_area)
+        .unwrap_or_else(windows::get_screen_bounds)
+}
 
-        TRUE
-    }).expect("Callback does not SetLastError");
+/// The mode subscribers are told about.
+fn mode_name(adjust_mode: &AdjustMode) -> &'static str {
+    if adjust_mode.is_active() { "adjust" } else { "normal" }
+}
+
+/// Work areas and the other switchable windows, for edges to snap to.
+fn adjust_targets<S: WindowSystem>(system: &S, filter: &WindowFilter, registry: &WindowRegistry, hwnd: HWND) -> Vec<Bounds> {
+    let mut targets: Vec<Bounds> = system.monitors().iter().map(|m| m.work_area).collect();
+
+    targets.extend(filter.apply(registry.windows())
+        .into_iter()
+        .filter(|info| info.hwnd != hwnd)
+        .filter(|info| system.placement(info.hwnd).map(|p| p.state) == Some(ShowState::Normal))
+        .map(|info| system.window_bounds(info.hwnd)));
+
+    targets
+}
+
+fn build_filter(settings: &Settings) -> WindowFilter {
+    let mut window_filter = WindowFilter::new(unsafe { kernel32::GetCurrentProcessId() });
+    for rule in &settings.filter().allow {
+        window_filter.allow(rule.clone());
+    }
+    for rule in &settings.filter().deny {
+        window_filter.deny(rule.clone());
+    }
+
+    window_filter
+}
+
+/// Runs a command given on the command line, returning the exit code.
+fn run_cli(settings: &Settings, args: &[String]) -> i32 {
+    if args[0] == "ctl" {
+        return control::ctl(&args[1..]);
+    }
+
+    if args[0] == "check-keys" {
+        return check_keys(settings);
+    }
+
+    let command = match args.join(" ").parse::<Command>() {
+        Ok(command) => command,
+        Err(e) => {
+            println!("{}", e);
+            return 2;
+        }
+    };
+
+    let mut system = Win32WindowSystem;
+    let filter = build_filter(settings);
+
+    let ok = match command {
+        Command::SaveLayout(ref name) => dispatcher::save_layout(&system, &filter, name),
+        Command::RestoreLayout(ref name) => dispatcher::restore_layout(&mut system, &filter, name),
+        _ => {
+            println!("'{}' can only be run from a running WinMan", command);
+            return 2;
+        }
+    };
+
+    if ok { 0 } else { 1 }
+}
+
+/// Prints which hotkeys can be registered, failing if any cannot. A
+/// running WinMan holds its own hotkeys, so it is asked how registering
+/// them went instead.
+fn check_keys(settings: &Settings) -> i32 {
+    match control::request(&control::default_endpoint(), &Request::Command(Command::ListHotkeys)) {
+        Ok(Response::Ok(Some(result))) => {
+            println!("WinMan is running with these hotkeys, restart it to check changed settings:");
+            return if control::print_hotkeys(&result) { 0 } else { 1 };
+        },
+        Ok(Response::Ok(None)) => {
+            println!("WinMan is running and holds its hotkeys, but did not list them");
+            return 1;
+        },
+        Ok(Response::Error(message)) => {
+            println!("WinMan is running and holds its hotkeys, but did not list them: {}", message);
+            return 1;
+        },
+        // Not running, so nothing of ours is holding the keys
+        Err(_) => {}
+    }
+
+    let config = build_config(settings);
+    let report = windows::main::check_hotkeys(windows::main::hotkeys(settings, &config));
+    print!("{}", report);
+
+    if report.failures().is_empty() { 0 } else { 1 }
+}
+
+fn update_display_profiles<S: WindowSystem>(system: &mut S, filter: &WindowFilter, profiles: &mut DisplayProfiles) {
+    if let Some(report) = profiles.update(system, filter) {
+        dispatcher::print_report(&report);
+    }
+}
+
+fn get_window_list(registry: &WindowRegistry, filter: &WindowFilter, vec: &mut Vec<(HWND, String)>) {
+    for info in filter.apply(registry.windows()) {
+        vec.push((info.hwnd, info.title.clone()));
+    }
 }
 
 fn load_config() -> Option<Config> {
     Some(Config::new())
+}
+
+/// The slots from the settings, on top of the default ones.
+fn build_config(settings: &Settings) -> Config {
+    let mut config = load_config().unwrap_or(Config::new());
+    for slot_settings in settings.slots() {
+        let mut slot = Slot::new(&slot_settings.name);
+        slot.key = slot_settings.key;
+        slot.mode = slot_settings.mode;
+        slot.description = slot_settings.description.clone();
+        slot.icon = slot_settings.icon.clone();
+
+        config.define_slot(slot);
+    }
+
+    config
 }
\ No newline at end of file